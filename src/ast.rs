@@ -1,8 +1,19 @@
 use crate::{
-    error::EngineError,
-    lexer::{Lexer, Token},
+    error::{EngineError, Span},
+    lexer::{Lexer, SpannedToken, Token},
+    number::number_to_string,
 };
 
+/**
+ * Where a node came from in the source, for runtime error messages.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub span: Span,
+    /// 1-based line of `span.start`.
+    pub line: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct BinaryExpression {
     pub left: Box<Expression>,
@@ -10,6 +21,12 @@ pub struct BinaryExpression {
     pub right: Box<Expression>,
 }
 
+#[derive(Debug, Clone)]
+pub struct UnaryExpression {
+    pub operator: Token,
+    pub operand: Box<Expression>,
+}
+
 #[derive(Debug, Clone)]
 pub struct IdentifierExpression {
     pub name: String,
@@ -17,25 +34,51 @@ pub struct IdentifierExpression {
 
 #[derive(Debug, Clone)]
 pub struct NumericLiteralExpression {
-    pub value: f32,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct StringLiteralExpression {
+    pub value: String,
 }
 
 #[derive(Debug, Clone)]
 pub struct FunctionCallExpression {
     pub function: Box<Expression>,
     pub arguments: Vec<Expression>,
+    /// `f?.()`: evaluates to `undefined` instead of calling when `f` is undefined.
+    pub optional: bool,
+    /// From the callee through `)`, `None` when parsed from bare tokens.
+    pub location: Option<SourceLocation>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConditionalExpression {
+    pub test: Box<Expression>,
+    pub consequent: Box<Expression>,
+    pub alternate: Box<Expression>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewExpression {
+    pub constructor: Box<Expression>,
+    pub arguments: Vec<Expression>,
 }
 
 #[derive(Debug, Clone)]
 pub struct ElementAccessExpression {
     pub expression: Box<Expression>,
     pub element: Box<Expression>,
+    /// `a?.[k]`: evaluates to `undefined` instead of erroring when `a` is undefined.
+    pub optional: bool,
 }
 
 #[derive(Debug, Clone)]
 pub enum ObjectPropertyName {
     Name(String),
     Computed(Box<Expression>),
+    /// `...value`: copies the own enumerable properties of the property's value.
+    Spread,
 }
 
 #[derive(Debug, Clone)]
@@ -54,10 +97,20 @@ pub struct ArrayLiteralExpression {
     pub elements: Vec<Expression>,
 }
 
+/**
+ * `...expression` inside an array literal, expanded through `VM::iterate`.
+ */
+#[derive(Debug, Clone)]
+pub struct SpreadExpression {
+    pub expression: Box<Expression>,
+}
+
 #[derive(Debug, Clone)]
 pub struct PropertyAccessExpression {
     pub expression: Box<Expression>,
     pub property: String,
+    /// `a?.b`: evaluates to `undefined` instead of erroring when `a` is undefined.
+    pub optional: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -101,14 +154,21 @@ pub struct AssignmentExpression {
 #[derive(Debug, Clone)]
 pub enum Expression {
     Binary(BinaryExpression),
+    Unary(UnaryExpression),
     Identifier(IdentifierExpression),
     NumericLiteral(NumericLiteralExpression),
+    StringLiteral(StringLiteralExpression),
     ObjectLiteral(ObjectLiteralExpression),
     ArrayLiteral(ArrayLiteralExpression),
     ElementAccess(ElementAccessExpression),
     PropertyAccess(PropertyAccessExpression),
     FunctionCall(FunctionCallExpression),
     FunctionDefinition(FunctionDefinitionExpression),
+    New(NewExpression),
+    Conditional(ConditionalExpression),
+    Spread(SpreadExpression),
+    /// The `new.target` meta-property.
+    NewTarget,
 }
 
 #[derive(Debug, Clone)]
@@ -122,9 +182,16 @@ pub struct ExpressionStatement {
 }
 
 #[derive(Debug, Clone)]
-pub struct LetStatement {
+pub struct LetDeclaration {
     pub name: String,
-    pub value: Box<Expression>,
+    /// `None` for `let x;`, which binds `undefined`.
+    pub value: Option<Box<Expression>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LetStatement {
+    /// `let a = 1, b = a;` holds one entry per binding, initialized left to right.
+    pub declarations: Vec<LetDeclaration>,
 }
 
 #[derive(Debug, Clone)]
@@ -134,9 +201,56 @@ pub struct IfStatement {
     pub else_: Option<Box<Statement>>,
 }
 
+#[derive(Debug, Clone)]
+pub struct ForStatement {
+    pub init: Option<Box<Statement>>,
+    pub condition: Option<Box<Expression>>,
+    pub update: Option<Box<Expression>>,
+    pub body: Box<Statement>,
+}
+
+/**
+ * `for (let binding of iterable) body`.
+ */
+#[derive(Debug, Clone)]
+pub struct ForOfStatement {
+    pub binding: String,
+    pub iterable: Box<Expression>,
+    pub body: Box<Statement>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SwitchCase {
+    /// `None` for the `default` clause.
+    pub test: Option<Expression>,
+    pub body: Vec<Statement>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SwitchStatement {
+    pub discriminant: Box<Expression>,
+    /// Clauses in source order, `default` may sit anywhere among them.
+    pub cases: Vec<SwitchCase>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CatchClause {
+    /// `None` for `catch { ... }` without a binding.
+    pub param: Option<String>,
+    pub body: BlockStatement,
+}
+
+#[derive(Debug, Clone)]
+pub struct TryStatement {
+    pub block: BlockStatement,
+    pub handler: Option<CatchClause>,
+    pub finalizer: Option<BlockStatement>,
+}
+
 #[derive(Debug, Clone)]
 pub struct ReturnStatement {
-    pub expression: Box<Expression>,
+    /// `None` for a bare `return;`, which yields `undefined`.
+    pub expression: Option<Box<Expression>>,
 }
 
 #[derive(Debug, Clone)]
@@ -145,7 +259,13 @@ pub enum Statement {
     Let(LetStatement),
     Block(BlockStatement),
     If(IfStatement),
+    For(ForStatement),
+    ForOf(ForOfStatement),
+    Switch(SwitchStatement),
+    Try(TryStatement),
     Return(ReturnStatement),
+    Break,
+    Continue,
 }
 
 impl Expression {
@@ -157,18 +277,59 @@ impl Expression {
         })
     }
 
+    pub fn unary(operator: Token, operand: Expression) -> Expression {
+        Expression::Unary(UnaryExpression {
+            operator,
+            operand: Box::new(operand),
+        })
+    }
+
     pub fn identifier(name: String) -> Expression {
         Expression::Identifier(IdentifierExpression { name })
     }
 
-    pub fn numeric_literal(value: f32) -> Expression {
+    pub fn numeric_literal(value: f64) -> Expression {
         Expression::NumericLiteral(NumericLiteralExpression { value })
     }
 
+    pub fn string_literal(value: String) -> Expression {
+        Expression::StringLiteral(StringLiteralExpression { value })
+    }
+
     pub fn function_call(function: Expression, arguments: Vec<Expression>) -> Expression {
         Expression::FunctionCall(FunctionCallExpression {
             function: Box::new(function),
             arguments,
+            optional: false,
+            location: None,
+        })
+    }
+
+    pub fn optional_function_call(function: Expression, arguments: Vec<Expression>) -> Expression {
+        Expression::FunctionCall(FunctionCallExpression {
+            function: Box::new(function),
+            arguments,
+            optional: true,
+            location: None,
+        })
+    }
+
+    pub fn new_(constructor: Expression, arguments: Vec<Expression>) -> Expression {
+        Expression::New(NewExpression {
+            constructor: Box::new(constructor),
+            arguments,
+        })
+    }
+
+    pub fn conditional(
+        test: Expression,
+        consequent: Expression,
+        alternate: Expression,
+    ) -> Expression {
+        Expression::Conditional(ConditionalExpression {
+            test: Box::new(test),
+            consequent: Box::new(consequent),
+            alternate: Box::new(alternate),
         })
     }
 
@@ -176,6 +337,15 @@ impl Expression {
         Expression::ElementAccess(ElementAccessExpression {
             expression: Box::new(expression),
             element: Box::new(element),
+            optional: false,
+        })
+    }
+
+    pub fn optional_element_access(expression: Expression, element: Expression) -> Expression {
+        Expression::ElementAccess(ElementAccessExpression {
+            expression: Box::new(expression),
+            element: Box::new(element),
+            optional: true,
         })
     }
 
@@ -191,6 +361,15 @@ impl Expression {
         Expression::PropertyAccess(PropertyAccessExpression {
             expression: Box::new(expression),
             property,
+            optional: false,
+        })
+    }
+
+    pub fn optional_property_access(expression: Expression, property: String) -> Expression {
+        Expression::PropertyAccess(PropertyAccessExpression {
+            expression: Box::new(expression),
+            property,
+            optional: true,
         })
     }
 
@@ -206,6 +385,29 @@ impl Expression {
         })
     }
 
+    /**
+     * Human-readable name of the node's kind, for error messages.
+     */
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            Expression::Binary(_) => "binary expression",
+            Expression::Unary(_) => "unary expression",
+            Expression::Identifier(_) => "identifier",
+            Expression::NumericLiteral(_) => "numeric literal",
+            Expression::StringLiteral(_) => "string literal",
+            Expression::ObjectLiteral(_) => "object literal",
+            Expression::ArrayLiteral(_) => "array literal",
+            Expression::ElementAccess(_) => "element access",
+            Expression::PropertyAccess(_) => "property access",
+            Expression::FunctionCall(_) => "function call",
+            Expression::FunctionDefinition(_) => "function definition",
+            Expression::New(_) => "new expression",
+            Expression::Conditional(_) => "conditional expression",
+            Expression::Spread(_) => "spread element",
+            Expression::NewTarget => "new.target",
+        }
+    }
+
     pub fn try_as_binary(&self) -> Option<&BinaryExpression> {
         match self {
             Expression::Binary(expr) => Some(expr),
@@ -213,6 +415,13 @@ impl Expression {
         }
     }
 
+    pub fn try_as_unary(&self) -> Option<&UnaryExpression> {
+        match self {
+            Expression::Unary(expr) => Some(expr),
+            _ => None,
+        }
+    }
+
     pub fn try_as_identifier(&self) -> Option<&IdentifierExpression> {
         match self {
             Expression::Identifier(expr) => Some(expr),
@@ -227,6 +436,27 @@ impl Expression {
         }
     }
 
+    pub fn try_as_new(&self) -> Option<&NewExpression> {
+        match self {
+            Expression::New(expr) => Some(expr),
+            _ => None,
+        }
+    }
+
+    pub fn try_as_conditional(&self) -> Option<&ConditionalExpression> {
+        match self {
+            Expression::Conditional(expr) => Some(expr),
+            _ => None,
+        }
+    }
+
+    pub fn try_as_string_literal(&self) -> Option<&StringLiteralExpression> {
+        match self {
+            Expression::StringLiteral(expr) => Some(expr),
+            _ => None,
+        }
+    }
+
     pub fn try_as_element_access(&self) -> Option<&ElementAccessExpression> {
         match self {
             Expression::ElementAccess(expr) => Some(expr),
@@ -277,10 +507,15 @@ impl Statement {
         })
     }
 
-    pub fn let_(name: String, value: Expression) -> Statement {
+    pub fn let_(declarations: Vec<(String, Option<Expression>)>) -> Statement {
         Statement::Let(LetStatement {
-            name,
-            value: Box::new(value),
+            declarations: declarations
+                .into_iter()
+                .map(|(name, value)| LetDeclaration {
+                    name,
+                    value: value.map(Box::new),
+                })
+                .collect(),
         })
     }
 
@@ -296,9 +531,50 @@ impl Statement {
         })
     }
 
-    pub fn return_(expression: Expression) -> Statement {
+    pub fn for_(
+        init: Option<Statement>,
+        condition: Option<Expression>,
+        update: Option<Expression>,
+        body: Statement,
+    ) -> Statement {
+        Statement::For(ForStatement {
+            init: init.map(Box::new),
+            condition: condition.map(Box::new),
+            update: update.map(Box::new),
+            body: Box::new(body),
+        })
+    }
+
+    pub fn for_of(binding: impl Into<String>, iterable: Expression, body: Statement) -> Statement {
+        Statement::ForOf(ForOfStatement {
+            binding: binding.into(),
+            iterable: Box::new(iterable),
+            body: Box::new(body),
+        })
+    }
+
+    pub fn switch(discriminant: Expression, cases: Vec<SwitchCase>) -> Statement {
+        Statement::Switch(SwitchStatement {
+            discriminant: Box::new(discriminant),
+            cases,
+        })
+    }
+
+    pub fn try_(
+        block: BlockStatement,
+        handler: Option<CatchClause>,
+        finalizer: Option<BlockStatement>,
+    ) -> Statement {
+        Statement::Try(TryStatement {
+            block,
+            handler,
+            finalizer,
+        })
+    }
+
+    pub fn return_(expression: Option<Expression>) -> Statement {
         Statement::Return(ReturnStatement {
-            expression: Box::new(expression),
+            expression: expression.map(Box::new),
         })
     }
 
@@ -330,6 +606,27 @@ impl Statement {
         }
     }
 
+    pub fn try_as_for(&self) -> Option<&ForStatement> {
+        match self {
+            Statement::For(stmt) => Some(stmt),
+            _ => None,
+        }
+    }
+
+    pub fn try_as_for_of(&self) -> Option<&ForOfStatement> {
+        match self {
+            Statement::ForOf(stmt) => Some(stmt),
+            _ => None,
+        }
+    }
+
+    pub fn try_as_switch(&self) -> Option<&SwitchStatement> {
+        match self {
+            Statement::Switch(stmt) => Some(stmt),
+            _ => None,
+        }
+    }
+
     pub fn try_as_return(&self) -> Option<&ReturnStatement> {
         match self {
             Statement::Return(stmt) => Some(stmt),
@@ -341,8 +638,12 @@ impl Statement {
 #[derive(Clone)]
 pub struct ASTParser {
     tokens: Vec<Token>,
+    /// Location of each token, empty when parsing bare tokens.
+    locations: Vec<SourceLocation>,
     pos: usize,
     inside_function: bool,
+    inside_loop: bool,
+    inside_switch: bool,
 }
 
 impl ASTParser {
@@ -350,6 +651,45 @@ impl ASTParser {
         self.tokens.get(self.pos).cloned()
     }
 
+    fn expect_token(&mut self, expected: Token, message: &str) -> Result<(), EngineError> {
+        match self.advance_token() {
+            Some(token) if std::mem::discriminant(&token) == std::mem::discriminant(&expected) => {
+                Ok(())
+            }
+            token => Err(EngineError::ast(format!("{}, got: {:?}", message, token))),
+        }
+    }
+
+    /**
+     * Location covering the tokens from `start` up to the last consumed one.
+     */
+    fn location_from(&self, start: usize) -> Option<SourceLocation> {
+        let first = self.locations.get(start)?;
+        let last = self.locations.get(self.pos.checked_sub(1)?)?;
+
+        Some(SourceLocation {
+            span: Span {
+                start: first.span.start,
+                end: last.span.end,
+            },
+            line: first.line,
+        })
+    }
+
+    /**
+     * Error for input ending inside the construct whose opening token sits at `open`.
+     */
+    fn unterminated(&self, construct: &str, open: usize) -> EngineError {
+        match self.locations.get(open) {
+            Some(location) => EngineError::ast(format!(
+                "Unterminated {} opened at line {}",
+                construct, location.line
+            ))
+            .with_span(location.span),
+            None => EngineError::ast(format!("Unterminated {}", construct)),
+        }
+    }
+
     fn advance_token(&mut self) -> Option<Token> {
         let token = self.peek_token();
         self.pos += 1;
@@ -364,23 +704,42 @@ impl ASTParser {
         self.peek_token()
     }
 
+    /**
+     * Parses a function body, where `return` is allowed and an enclosing loop
+     * or switch no longer accepts `break`/`continue`.
+     */
+    fn parse_function_body(&mut self) -> Result<Statement, EngineError> {
+        let prev_inside_function = self.inside_function;
+        let prev_inside_loop = self.inside_loop;
+        let prev_inside_switch = self.inside_switch;
+        self.inside_function = true;
+        self.inside_loop = false;
+        self.inside_switch = false;
+        let body = self.parse_statement();
+        self.inside_function = prev_inside_function;
+        self.inside_loop = prev_inside_loop;
+        self.inside_switch = prev_inside_switch;
+
+        body
+    }
+
     fn try_parse_arrow_function(&mut self) -> Result<Option<Expression>, EngineError> {
         let mut arrow_func_args: Vec<String> = vec![];
-        let mut clone = self.clone();
+        let start = self.pos;
 
-        let mut next = clone
+        let mut next = self
             .advance_token()
             .ok_or_else(|| EngineError::ast("Expected a token after LParen"))?;
 
         while let Token::Identifier(identifier) = &next {
             arrow_func_args.push(identifier.name.clone());
 
-            next = clone
+            next = self
                 .advance_token()
                 .ok_or_else(|| EngineError::ast("Expected a token after LParen"))?;
 
             if matches!(next, Token::Comma) {
-                next = clone
+                next = self
                     .advance_token()
                     .ok_or_else(|| EngineError::ast("Expected a token after LParen"))?;
             } else {
@@ -389,37 +748,85 @@ impl ASTParser {
         }
 
         if matches!(next, Token::RParen) {
-            let next = clone
+            let next = self
                 .advance_token()
                 .ok_or_else(|| EngineError::ast("Expected a token after RParen"))?;
 
             if matches!(next, Token::Arrow) {
-                let body = clone.parse_statement()?;
+                // `{` always starts a block body, so `() => ({ ... })` is needed to return an object
+                let body = if matches!(self.peek_token(), Some(Token::LBrace)) {
+                    let body = self.parse_function_body()?;
 
-                let expression = Expression::function_definition(
-                    FunctionKind::Arrow,
-                    arrow_func_args,
                     body.try_as_block()
                         .ok_or_else(|| EngineError::ast("Expected a block statement after ARROW"))
-                        .cloned()?,
-                );
+                        .cloned()?
+                } else {
+                    BlockStatement {
+                        body: vec![Statement::return_(Some(self.parse_expression()?))],
+                    }
+                };
+
+                let expression =
+                    Expression::function_definition(FunctionKind::Arrow, arrow_func_args, body);
 
-                self.pos = clone.pos;
                 return Ok(Some(expression));
             }
         }
 
+        // not an arrow function, rewind so the caller can parse a parenthesized expression
+        self.pos = start;
+
         Ok(None)
     }
 
+    /**
+     * Parses the rest of `for (let x of iterable) body` when the loop head has that
+     * shape, otherwise consumes nothing so the caller parses a C-style `for`.
+     */
+    fn parse_for_of(&mut self) -> Result<Option<Statement>, EngineError> {
+        let binding = match (
+            self.tokens.get(self.pos),
+            self.tokens.get(self.pos + 1),
+            self.tokens.get(self.pos + 2),
+        ) {
+            (
+                Some(Token::LetKeyword),
+                Some(Token::Identifier(binding)),
+                Some(Token::Identifier(of)),
+            ) if of.name == "of" => binding.name.clone(),
+            _ => return Ok(None),
+        };
+
+        self.pos += 3;
+        let iterable = self.parse_expression()?;
+        self.expect_token(Token::RParen, "Expected ) after for...of iterable")?;
+
+        let prev_inside_loop = self.inside_loop;
+        self.inside_loop = true;
+        let body = self.parse_statement();
+        self.inside_loop = prev_inside_loop;
+
+        Ok(Some(Statement::for_of(binding, iterable, body?)))
+    }
+
     fn parse_primary(&mut self) -> Result<Expression, EngineError> {
+        let start = self.pos;
+        let expr = self.parse_atom()?;
+        self.parse_postfix(expr, start, true)
+    }
+
+    fn parse_atom(&mut self) -> Result<Expression, EngineError> {
         let token = self.peek_token().unwrap();
 
-        let mut expr = match token {
+        let expr = match token {
             Token::NumericLiteral(token) => {
                 self.advance_token();
                 Expression::numeric_literal(token.value)
             }
+            Token::StringLiteral(token) => {
+                self.advance_token();
+                Expression::string_literal(token.value)
+            }
             Token::Identifier(token) => {
                 self.advance_token();
 
@@ -485,10 +892,7 @@ impl ASTParser {
                     }
                 }
 
-                let prev_inside_function = self.inside_function;
-                self.inside_function = true;
-                let body = self.parse_statement()?;
-                self.inside_function = prev_inside_function;
+                let body = self.parse_function_body()?;
 
                 let Statement::Block(block) = body else {
                     return Err(EngineError::ast(format!(
@@ -500,6 +904,7 @@ impl ASTParser {
                 Expression::function_definition(kind, arguments, block)
             }
             Token::LBracket => {
+                let open = self.pos;
                 self.advance_token();
                 let mut elements: Vec<Expression> = vec![];
 
@@ -508,12 +913,23 @@ impl ASTParser {
                         .peek_token()
                         .ok_or_else(|| EngineError::ast("Expected a token in array defintion"))?;
 
+                    if matches!(next, Token::End) {
+                        return Err(self.unterminated("array literal", open));
+                    }
+
                     if matches!(next, Token::RBracket) {
                         self.advance_token();
                         break;
                     }
 
-                    elements.push(self.parse_expression()?);
+                    if matches!(next, Token::DotDotDot) {
+                        self.advance_token();
+                        elements.push(Expression::Spread(SpreadExpression {
+                            expression: Box::new(self.parse_expression()?),
+                        }));
+                    } else {
+                        elements.push(self.parse_expression()?);
+                    }
 
                     let next = self
                         .peek_token()
@@ -524,6 +940,10 @@ impl ASTParser {
                         continue;
                     }
 
+                    if matches!(next, Token::End) {
+                        return Err(self.unterminated("array literal", open));
+                    }
+
                     if !matches!(next, Token::RBracket) {
                         return Err(EngineError::ast(format!(
                             "
@@ -537,6 +957,7 @@ impl ASTParser {
                 Expression::array_literal(elements)
             }
             Token::LBrace => {
+                let open = self.pos;
                 self.advance_token();
                 let mut properties: Vec<ObjectProperty> = vec![];
 
@@ -549,10 +970,60 @@ impl ASTParser {
                         break;
                     }
 
+                    if matches!(next, Token::End) {
+                        return Err(self.unterminated("object literal", open));
+                    }
+
                     let name: ObjectPropertyName;
 
+                    if matches!(next, Token::DotDotDot) {
+                        properties.push(ObjectProperty {
+                            name: ObjectPropertyName::Spread,
+                            value: Box::new(self.parse_expression()?),
+                        });
+
+                        match self.peek_token() {
+                            Some(Token::Comma) => {
+                                self.advance_token();
+                            }
+                            Some(Token::RBrace) => {}
+                            Some(Token::End) | None => {
+                                return Err(self.unterminated("object literal", open));
+                            }
+                            Some(token) => {
+                                return Err(EngineError::ast(format!(
+                                    "Expected either COMMA or RBrace after spread property, got: {:#?}",
+                                    token
+                                )));
+                            }
+                        }
+
+                        continue;
+                    }
+
+                    // shorthand `{ a }` is sugar for `{ a: a }`
+                    if let Token::Identifier(identifier) = &next
+                        && matches!(self.peek_token(), Some(Token::Comma | Token::RBrace))
+                    {
+                        properties.push(ObjectProperty {
+                            name: ObjectPropertyName::Name(identifier.name.clone()),
+                            value: Box::new(Expression::identifier(identifier.name.clone())),
+                        });
+
+                        if matches!(self.peek_token(), Some(Token::Comma)) {
+                            self.advance_token();
+                        }
+
+                        continue;
+                    }
+
                     if let Token::Identifier(identifier) = next {
                         name = ObjectPropertyName::Name(identifier.name);
+                    } else if let Token::NumericLiteral(literal) = next {
+                        // `{ 1: x }` is keyed by the canonical string form, same as `obj[1]`
+                        name = ObjectPropertyName::Name(number_to_string(literal.value));
+                    } else if let Token::StringLiteral(literal) = next {
+                        name = ObjectPropertyName::Name(literal.value);
                     } else if matches!(next, Token::LBracket) {
                         name = ObjectPropertyName::Computed(Box::new(self.parse_expression()?));
 
@@ -572,7 +1043,7 @@ impl ASTParser {
                             })?;
                     } else {
                         return Err(EngineError::ast(format!(
-                            "Expected either an identifier or a computed property starting with RBracket in object definition, got: {:#?}",
+                            "Expected an identifier, a numeric or string literal, or a computed property starting with LBracket in object definition, got: {:#?}",
                             next
                         )));
                     }
@@ -604,6 +1075,10 @@ impl ASTParser {
                         continue;
                     }
 
+                    if matches!(next, Token::End) {
+                        return Err(self.unterminated("object literal", open));
+                    }
+
                     if !matches!(next, Token::RBrace) {
                         return Err(EngineError::ast(format!(
                             "
@@ -617,6 +1092,7 @@ impl ASTParser {
                 Expression::object_literal(properties)
             }
             Token::LParen => {
+                let open = self.pos;
                 self.advance_token();
 
                 // prioritize arrow function parsing
@@ -624,6 +1100,10 @@ impl ASTParser {
                     return Ok(arrow_func);
                 }
 
+                if matches!(self.peek_token(), Some(Token::End)) {
+                    return Err(self.unterminated("parenthesized expression", open));
+                }
+
                 let expression = self.parse_expression()?;
 
                 self.peek_token()
@@ -632,6 +1112,8 @@ impl ASTParser {
                         if matches!(next, Token::RParen) {
                             self.advance_token();
                             Ok(expression)
+                        } else if matches!(next, Token::End) {
+                            Err(self.unterminated("parenthesized expression", open))
                         } else {
                             Err(EngineError::ast(format!(
                                 "Expected RParen after expression end, got: {next:#?}"
@@ -639,6 +1121,26 @@ impl ASTParser {
                         }
                     })?
             }
+            Token::NewKeyword => {
+                self.advance_token();
+
+                if matches!(self.peek_token(), Some(Token::Dot)) {
+                    return self.parse_new_target();
+                }
+
+                let start = self.pos;
+                let constructor = self.parse_atom()?;
+                let constructor = self.parse_postfix(constructor, start, false)?;
+
+                let arguments = if matches!(self.peek_token(), Some(Token::LParen)) {
+                    self.advance_token();
+                    self.parse_call_arguments()?
+                } else {
+                    vec![]
+                };
+
+                Expression::new_(constructor, arguments)
+            }
             _ => {
                 return Err(EngineError::ast(format!(
                     "Expression starting with {:#?} is not impl",
@@ -647,28 +1149,113 @@ impl ASTParser {
             }
         };
 
-        let mut clone = self.clone();
+        Ok(expr)
+    }
+
+    /**
+     * The rest of `new.target` after `new`, only valid inside a function body.
+     */
+    fn parse_new_target(&mut self) -> Result<Expression, EngineError> {
+        self.advance_token();
+
+        match self.advance_token() {
+            Some(Token::Identifier(identifier)) if identifier.name == "target" => {}
+            token => {
+                return Err(EngineError::ast(format!(
+                    "Expected target after new., got: {:#?}",
+                    token
+                )));
+            }
+        }
+
+        if !self.inside_function {
+            return Err(EngineError::ast(
+                "new.target expression is not allowed here",
+            ));
+        }
+
+        Ok(Expression::NewTarget)
+    }
+
+    /**
+     * A `{ ... }` block required after a keyword such as `try`.
+     */
+    fn parse_block(&mut self, keyword: &str) -> Result<BlockStatement, EngineError> {
+        if !matches!(self.peek_token(), Some(Token::LBrace)) {
+            return Err(EngineError::ast(format!("Expected {{ after {}", keyword)));
+        }
+
+        match self.parse_statement()? {
+            Statement::Block(block) => Ok(block),
+            statement => Err(EngineError::ast(format!(
+                "Expected a block after {}, got: {:#?}",
+                keyword, statement
+            ))),
+        }
+    }
+
+    fn parse_call_arguments(&mut self) -> Result<Vec<Expression>, EngineError> {
+        // called right after the `(`
+        let open = self.pos - 1;
+        let mut arguments: Vec<Expression> = vec![];
+
+        if matches!(self.peek_token(), Some(Token::RParen)) {
+            self.advance_token();
+            return Ok(arguments);
+        }
+
+        loop {
+            if matches!(self.peek_token(), Some(Token::End)) {
+                return Err(self.unterminated("argument list", open));
+            }
+
+            arguments.push(self.parse_expression()?);
+
+            let next_token = self
+                .advance_token()
+                .ok_or_else(|| EngineError::ast("Expected a token in function call arguments"))?;
+
+            if matches!(next_token, Token::Comma) {
+                continue;
+            }
+
+            if matches!(next_token, Token::End) {
+                return Err(self.unterminated("argument list", open));
+            }
+
+            if matches!(next_token, Token::RParen) {
+                break;
+            }
+
+            return Err(EngineError::ast(format!(
+                "Expected Comma or RParen in function call arguments, got: {:#?}",
+                next_token
+            )));
+        }
 
-        while let Some(token) = clone.peek_token() {
+        Ok(arguments)
+    }
+
+    /**
+     * Parse member accesses and calls following an expression that began at token `start`.
+     * Calls are not consumed when `allow_call` is false, so `new a.b(c)` keeps `(c)` for `new`.
+     */
+    fn parse_postfix(
+        &mut self,
+        mut expr: Expression,
+        start: usize,
+        allow_call: bool,
+    ) -> Result<Expression, EngineError> {
+        while let Some(token) = self.peek_token() {
             match token {
                 Token::LBracket => {
-                    clone.advance_token();
-                    let element = clone.parse_expression()?;
-                    let next = clone.advance_token();
-                    if let Some(token) = &next
-                        && matches!(token, Token::RBracket)
-                    {
-                        expr = Expression::element_access(expr, element);
-                    } else {
-                        return Err(EngineError::ast(format!(
-                            "Expected RBracket for ElementAccessExpression, got: {:#?}",
-                            next
-                        )));
-                    }
+                    self.advance_token();
+                    let element = self.parse_element()?;
+                    expr = Expression::element_access(expr, element);
                 }
                 Token::Dot => {
-                    clone.advance_token();
-                    let next = clone.advance_token();
+                    self.advance_token();
+                    let next = self.advance_token();
 
                     if let Some(token) = &next
                         && let Token::Identifier(identifier) = token
@@ -681,40 +1268,37 @@ impl ASTParser {
                         )));
                     }
                 }
-                Token::LParen => {
-                    clone.advance_token();
-                    let mut arguments: Vec<Expression> = vec![];
-
-                    if clone
-                        .peek_token()
-                        .map(|token| !matches!(token, Token::RParen))
-                        .unwrap_or(true)
-                    {
-                        loop {
-                            arguments.push(clone.parse_expression()?);
-
-                            let next_token = clone.advance_token().ok_or_else(|| {
-                                EngineError::ast("Expected a token in function call arguments")
-                            })?;
+                Token::LParen if allow_call => {
+                    self.advance_token();
+                    let arguments = self.parse_call_arguments()?;
 
-                            if matches!(next_token, Token::Comma) {
-                                continue;
-                            }
+                    expr = Expression::function_call(expr, arguments);
+                    self.locate_call(&mut expr, start);
+                }
+                Token::QuestionDot => {
+                    self.advance_token();
 
-                            if matches!(next_token, Token::RParen) {
-                                break;
-                            }
+                    match self.advance_token() {
+                        Some(Token::Identifier(identifier)) => {
+                            expr = Expression::optional_property_access(expr, identifier.name);
+                        }
+                        Some(Token::LBracket) => {
+                            let element = self.parse_element()?;
+                            expr = Expression::optional_element_access(expr, element);
+                        }
+                        Some(Token::LParen) if allow_call => {
+                            let arguments = self.parse_call_arguments()?;
 
+                            expr = Expression::optional_function_call(expr, arguments);
+                            self.locate_call(&mut expr, start);
+                        }
+                        next => {
                             return Err(EngineError::ast(format!(
-                                "Expected Comma or RParen in function call arguments, got: {:#?}",
-                                next_token
+                                "Expected Identifier, element or call arguments after ?., got: {:#?}",
+                                next
                             )));
                         }
-                    } else {
-                        clone.advance_token();
                     }
-
-                    expr = Expression::function_call(expr, arguments)
                 }
                 _ => {
                     break;
@@ -722,135 +1306,205 @@ impl ASTParser {
             }
         }
 
-        self.pos = clone.pos;
-
         Ok(expr)
     }
 
-    fn parse_factor(&mut self) -> Result<Expression, EngineError> {
-        let mut expr = self.parse_primary()?;
+    /**
+     * The key of an element access after its `[`, through the closing `]`.
+     */
+    fn parse_element(&mut self) -> Result<Expression, EngineError> {
+        let element = self.parse_expression()?;
+        let next = self.advance_token();
 
-        while let Some(token) = self.peek_token()
-            && (matches!(token, Token::Slash) || matches!(token, Token::Star))
-        {
-            self.advance_token();
-            expr = Expression::binary(expr, token, self.parse_primary()?);
+        if !matches!(next, Some(Token::RBracket)) {
+            return Err(EngineError::ast(format!(
+                "Expected RBracket for ElementAccessExpression, got: {:#?}",
+                next
+            )));
         }
 
-        Ok(expr)
+        Ok(element)
     }
 
-    fn parse_term(&mut self) -> Result<Expression, EngineError> {
-        let mut expr = self.parse_factor()?;
+    fn locate_call(&self, expr: &mut Expression, start: usize) {
+        if let Expression::FunctionCall(call) = expr {
+            call.location = self.location_from(start);
+        }
+    }
 
-        while let Some(token) = self.peek_token()
-            && (matches!(token, Token::Plus) || matches!(token, Token::Minus))
+    fn parse_unary(&mut self) -> Result<Expression, EngineError> {
+        if let Some(token) = self.peek_token()
+            && matches!(token, Token::Minus | Token::Plus | Token::TypeofKeyword)
         {
             self.advance_token();
-
-            expr = Expression::binary(expr, token, self.parse_factor()?);
+            return Ok(Expression::unary(token, self.parse_unary()?));
         }
 
-        Ok(expr)
+        self.parse_primary()
     }
 
-    fn parse_comparison(&mut self) -> Result<Expression, EngineError> {
-        let mut expr = self.parse_term()?;
-
-        while let Some(token) = self.peek_token()
-            && (matches!(token, Token::LessThanEqual)
-                || matches!(token, Token::LessThan)
-                || matches!(token, Token::GreaterThan)
-                || matches!(token, Token::GreaterThanEqual))
-        {
-            self.advance_token();
-
-            expr = Expression::binary(expr, token, self.parse_term()?);
+    /**
+     * Pratt binding powers for binary operators as `(left, right)`.
+     * A higher number binds tighter; `left > right` makes the operator right-associative.
+     * Adding an operator is one entry here plus an arm in the VM.
+     */
+    pub fn binding_power(token: &Token) -> Option<(u8, u8)> {
+        match token {
+            Token::Equal | Token::AndAndEqual | Token::OrOrEqual | Token::QuestionQuestionEqual => {
+                Some((2, 1))
+            }
+            // `?` only has a left power, its branches are parsed in `parse_binary`
+            Token::Question => Some((2, 0)),
+            Token::OrOr => Some((3, 4)),
+            Token::AndAnd => Some((5, 6)),
+            Token::EqualEqual
+            | Token::EqualEqualEqual
+            | Token::BangEqual
+            | Token::BangEqualEqual => Some((7, 8)),
+            Token::LessThan
+            | Token::LessThanEqual
+            | Token::GreaterThan
+            | Token::GreaterThanEqual => Some((9, 10)),
+            Token::Plus | Token::Minus => Some((11, 12)),
+            Token::Star | Token::Slash | Token::Percent => Some((13, 14)),
+            Token::StarStar => Some((16, 15)),
+            _ => None,
         }
-
-        Ok(expr)
     }
 
-    fn parse_equality(&mut self) -> Result<Expression, EngineError> {
-        let mut expr = self.parse_comparison()?;
+    fn parse_binary(&mut self, min_binding_power: u8) -> Result<Expression, EngineError> {
+        let mut expr = self.parse_unary()?;
 
         while let Some(token) = self.peek_token()
-            && (matches!(token, Token::EqualEqual)
-                || matches!(token, Token::EqualEqualEqual)
-                || matches!(token, Token::BangEqual)
-                || matches!(token, Token::BangEqualEqual))
+            && let Some((left_power, right_power)) = Self::binding_power(&token)
+            && left_power >= min_binding_power
         {
             self.advance_token();
 
-            expr = Expression::binary(expr, token, self.parse_comparison()?);
+            // `a ? b : c = d` is `a ? b : (c = d)`, the alternate is a whole assignment
+            if matches!(token, Token::Question) {
+                let consequent = self.parse_binary(0)?;
+                self.expect_token(Token::Colon, "Expected : in conditional expression")?;
+                let alternate = self.parse_binary(1)?;
+
+                expr = Expression::conditional(expr, consequent, alternate);
+                continue;
+            }
+
+            expr = Expression::binary(expr, token, self.parse_binary(right_power)?);
         }
 
         Ok(expr)
     }
 
-    fn parse_logical_and(&mut self) -> Result<Expression, EngineError> {
-        let mut expr = self.parse_equality()?;
+    fn parse_expression(&mut self) -> Result<Expression, EngineError> {
+        self.parse_binary(0)
+    }
 
-        while let Some(token) = self.peek_token()
-            && matches!(token, Token::AndAnd)
-        {
-            self.advance_token();
+    fn parse_statement(&mut self) -> Result<Statement, EngineError> {
+        match self.peek_token().unwrap() {
+            Token::LetKeyword => {
+                self.advance_token();
+                let mut declarations = vec![];
 
-            expr = Expression::binary(expr, token, self.parse_equality()?);
-        }
+                loop {
+                    let Some(Token::Identifier(identifier_token)) = self.advance_token() else {
+                        return Err(EngineError::ast(
+                            "Expected identifier and a statement after let",
+                        ));
+                    };
 
-        Ok(expr)
-    }
+                    let value = match self.peek_token() {
+                        Some(Token::Equal) => {
+                            self.advance_token();
+                            Some(self.parse_expression()?)
+                        }
+                        Some(Token::Comma | Token::Semicolon | Token::RBrace | Token::End)
+                        | None => None,
+                        _ => {
+                            return Err(EngineError::ast(format!(
+                                "Expected = after let binding {}",
+                                identifier_token.name
+                            )));
+                        }
+                    };
 
-    fn parse_logical_or(&mut self) -> Result<Expression, EngineError> {
-        let mut expr = self.parse_logical_and()?;
+                    declarations.push((identifier_token.name, value));
 
-        while let Some(token) = self.peek_token()
-            && matches!(token, Token::OrOr)
-        {
-            self.advance_token();
+                    if !matches!(self.peek_token(), Some(Token::Comma)) {
+                        break;
+                    }
 
-            expr = Expression::binary(expr, token, self.parse_logical_and()?);
-        }
+                    self.advance_token();
+                }
 
-        Ok(expr)
-    }
+                Ok(Statement::let_(declarations))
+            }
+            Token::ForKeyword => {
+                self.advance_token();
+                self.expect_token(Token::LParen, "Expected ( after for")?;
 
-    fn parse_assignment(&mut self) -> Result<Expression, EngineError> {
-        let mut expr = self.parse_logical_or()?;
+                if let Some(for_of) = self.parse_for_of()? {
+                    return Ok(for_of);
+                }
 
-        if let Some(Token::Equal) = self.peek_token() {
-            self.advance_token();
+                let init = (!matches!(self.peek_token(), Some(Token::Semicolon)))
+                    .then(|| self.parse_statement())
+                    .transpose()?;
+                self.expect_token(Token::Semicolon, "Expected ; after for initializer")?;
 
-            let value = self.parse_assignment()?;
+                let condition = (!matches!(self.peek_token(), Some(Token::Semicolon)))
+                    .then(|| self.parse_expression())
+                    .transpose()?;
+                self.expect_token(Token::Semicolon, "Expected ; after for condition")?;
 
-            expr = Expression::binary(expr, Token::Equal, value)
-        }
+                let update = (!matches!(self.peek_token(), Some(Token::RParen)))
+                    .then(|| self.parse_expression())
+                    .transpose()?;
+                self.expect_token(Token::RParen, "Expected ) after for clauses")?;
 
-        Ok(expr)
-    }
+                let prev_inside_loop = self.inside_loop;
+                self.inside_loop = true;
+                let body = self.parse_statement()?;
+                self.inside_loop = prev_inside_loop;
 
-    fn parse_expression(&mut self) -> Result<Expression, EngineError> {
-        self.parse_assignment()
-    }
+                Ok(Statement::for_(init, condition, update, body))
+            }
+            Token::BreakKeyword => {
+                self.advance_token();
 
-    fn parse_statement(&mut self) -> Result<Statement, EngineError> {
-        match self.peek_token().unwrap() {
-            Token::LetKeyword => {
+                if !self.inside_loop && !self.inside_switch {
+                    return Err(EngineError::ast(
+                        "BreakKeyword is allowed only within a loop or switch",
+                    ));
+                }
+
+                Ok(Statement::Break)
+            }
+            Token::ContinueKeyword => {
                 self.advance_token();
-                if let Some(token) = self.advance_token()
-                    && let Token::Identifier(identifier_token) = token
-                {
-                    self.advance_token();
-                    Ok(Statement::let_(
-                        identifier_token.name,
-                        self.parse_expression()?,
-                    ))
-                } else {
-                    Err(EngineError::ast(
-                        "Expected identifier and a statement after let",
-                    ))
+
+                if !self.inside_loop {
+                    return Err(EngineError::ast(
+                        "ContinueKeyword is allowed only within a loop",
+                    ));
                 }
+
+                Ok(Statement::Continue)
+            }
+            Token::SwitchKeyword => {
+                self.advance_token();
+                self.expect_token(Token::LParen, "Expected ( after switch")?;
+                let discriminant = self.parse_expression()?;
+                self.expect_token(Token::RParen, "Expected ) after switch discriminant")?;
+                self.expect_token(Token::LBrace, "Expected { after switch (...)")?;
+
+                let prev_inside_switch = self.inside_switch;
+                self.inside_switch = true;
+                let cases = self.parse_switch_cases();
+                self.inside_switch = prev_inside_switch;
+
+                Ok(Statement::switch(discriminant, cases?))
             }
             Token::ReturnKeyword => {
                 if !self.inside_function {
@@ -860,13 +1514,69 @@ impl ASTParser {
                 }
 
                 self.advance_token();
-                Ok(Statement::return_(self.parse_expression()?))
+                let expression = (!matches!(
+                    self.peek_token(),
+                    Some(Token::Semicolon | Token::RBrace | Token::End)
+                ))
+                .then(|| self.parse_expression())
+                .transpose()?;
+
+                Ok(Statement::return_(expression))
+            }
+            Token::TryKeyword => {
+                self.advance_token();
+                let block = self.parse_block("try")?;
+
+                let handler = if matches!(self.peek_token(), Some(Token::CatchKeyword)) {
+                    self.advance_token();
+
+                    let param = if matches!(self.peek_token(), Some(Token::LParen)) {
+                        self.advance_token();
+
+                        let Some(Token::Identifier(identifier)) = self.advance_token() else {
+                            return Err(EngineError::ast("Expected identifier in catch (...)"));
+                        };
+
+                        self.expect_token(Token::RParen, "Expected ) after catch binding")?;
+                        Some(identifier.name)
+                    } else {
+                        None
+                    };
+
+                    Some(CatchClause {
+                        param,
+                        body: self.parse_block("catch")?,
+                    })
+                } else {
+                    None
+                };
+
+                let finalizer = matches!(self.peek_token(), Some(Token::FinallyKeyword))
+                    .then(|| {
+                        self.advance_token();
+                        self.parse_block("finally")
+                    })
+                    .transpose()?;
+
+                if handler.is_none() && finalizer.is_none() {
+                    return Err(EngineError::ast("Missing catch or finally after try"));
+                }
+
+                Ok(Statement::try_(block, handler, finalizer))
             }
             Token::IfKeyword => {
                 self.advance_token();
                 let condition = self.parse_expression()?;
                 let then = self.parse_statement()?;
 
+                // `if (a) b; else c;` - the `;` ends `then`, not the whole `if`. Checked by the
+                // innermost `if` first, so a dangling `else` binds to the nearest one.
+                if matches!(self.peek_token(), Some(Token::Semicolon))
+                    && matches!(self.tokens.get(self.pos + 1), Some(Token::ElseKeyword))
+                {
+                    self.advance_token();
+                }
+
                 let else_ = matches!(self.peek_token(), Some(Token::ElseKeyword))
                     .then(|| {
                         self.advance_token();
@@ -876,13 +1586,30 @@ impl ASTParser {
 
                 Ok(Statement::if_(condition, then, else_))
             }
+            // as in JS, a leading `{` is always a block; object literals need parentheses
             Token::LBrace => {
+                let open = self.pos;
                 let mut statements: Vec<Statement> = vec![];
                 self.advance_token();
 
+                if let Some(Token::Colon) = self.tokens.get(self.pos + 1)
+                    && matches!(
+                        self.peek_token(),
+                        Some(
+                            Token::Identifier(_)
+                                | Token::StringLiteral(_)
+                                | Token::NumericLiteral(_)
+                        )
+                    )
+                {
+                    return Err(EngineError::ast(
+                        "Unexpected ':' in block statement. To use an object literal as a statement, wrap it in parentheses: ({ ... })",
+                    ));
+                }
+
                 while let Some(token) = self.peek_token() {
                     if matches!(token, Token::End) {
-                        break;
+                        return Err(self.unterminated("block", open));
                     }
 
                     if matches!(token, Token::RBrace) {
@@ -920,6 +1647,63 @@ impl ASTParser {
         }
     }
 
+    /**
+     * Parses `case x: ...` / `default: ...` clauses up to and including the closing `}`.
+     */
+    fn parse_switch_cases(&mut self) -> Result<Vec<SwitchCase>, EngineError> {
+        let mut cases: Vec<SwitchCase> = vec![];
+
+        loop {
+            let test = match self.advance_token() {
+                Some(Token::RBrace) => break,
+                Some(Token::CaseKeyword) => Some(self.parse_expression()?),
+                Some(Token::DefaultKeyword) => {
+                    if cases.iter().any(|case| case.test.is_none()) {
+                        return Err(EngineError::ast(
+                            "More than one default clause in switch statement",
+                        ));
+                    }
+
+                    None
+                }
+                token => {
+                    return Err(EngineError::ast(format!(
+                        "Expected case, default or }} in switch, got: {:?}",
+                        token
+                    )));
+                }
+            };
+            self.expect_token(Token::Colon, "Expected : after switch clause")?;
+
+            let mut body = vec![];
+
+            while !matches!(
+                self.peek_token(),
+                Some(Token::CaseKeyword | Token::DefaultKeyword | Token::RBrace | Token::End)
+                    | None
+            ) {
+                body.push(self.parse_statement()?);
+
+                match self.peek_token() {
+                    Some(Token::Semicolon) => {
+                        self.advance_token();
+                    }
+                    Some(Token::CaseKeyword | Token::DefaultKeyword | Token::RBrace) => {}
+                    next => {
+                        return Err(EngineError::ast(format!(
+                            "SWITCH: Expected a semicolon, got: {:?}",
+                            next
+                        )));
+                    }
+                }
+            }
+
+            cases.push(SwitchCase { test, body });
+        }
+
+        Ok(cases)
+    }
+
     fn parse_statements(&mut self) -> Result<Vec<Statement>, EngineError> {
         let mut result: Vec<Statement> = vec![];
 
@@ -932,6 +1716,11 @@ impl ASTParser {
 
             result.push(statement);
 
+            // the final statement may omit its semicolon
+            if let Some(Token::End) = self.peek_token() {
+                break;
+            }
+
             if let Some(token) = self.peek_token()
                 && !matches!(token, Token::Semicolon)
             {
@@ -948,25 +1737,56 @@ impl ASTParser {
     }
 
     pub fn parse_from_tokens(tokens: Vec<Token>) -> Result<Vec<Statement>, EngineError> {
+        Self::parse_located(tokens, vec![])
+    }
+
+    fn parse_located(
+        tokens: Vec<Token>,
+        locations: Vec<SourceLocation>,
+    ) -> Result<Vec<Statement>, EngineError> {
         let mut ast = Self {
             pos: 0,
             tokens,
+            locations,
             inside_function: false,
+            inside_loop: false,
+            inside_switch: false,
         };
         ast.parse_statements()
     }
 
     pub fn parse_from_source(source: &str) -> Result<Vec<Statement>, EngineError> {
-        let tokens = Lexer::tokenize(source)?;
-        Self::parse_from_tokens(tokens)
+        let spanned = Lexer::tokenize_spanned(source)?;
+
+        let mut line = 1;
+        let mut chars = source.chars();
+        let mut offset = 0;
+        let mut locations = Vec::with_capacity(spanned.len());
+        let mut tokens = Vec::with_capacity(spanned.len());
+
+        // spans only grow, so lines are counted in a single pass over the source
+        for SpannedToken { token, span } in spanned {
+            for character in chars.by_ref().take(span.start.saturating_sub(offset)) {
+                if character == '\n' {
+                    line += 1;
+                }
+            }
+            offset = offset.max(span.start);
+
+            locations.push(SourceLocation { span, line });
+            tokens.push(token);
+        }
+
+        Self::parse_located(tokens, locations)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        ast::{ASTParser, Expression, ObjectPropertyName},
-        lexer::Token,
+        ast::{ASTParser, Expression, ObjectPropertyName, Statement},
+        error::Span,
+        lexer::{Lexer, Token},
     };
 
     #[test]
@@ -985,6 +1805,46 @@ mod tests {
         assert_eq!(expr.value, 42.0);
     }
 
+    #[test]
+    fn test_parse_string_literal() {
+        let result = ASTParser::parse_from_source("'hello';").unwrap();
+        assert_eq!(result.len(), 1);
+
+        let stmt = result[0].try_as_expression().unwrap();
+        let expr = stmt.expression.try_as_string_literal().unwrap();
+        assert_eq!(expr.value, "hello");
+    }
+
+    #[test]
+    fn test_parse_unary_minus() {
+        let result = ASTParser::parse_from_source("-x * 2;").unwrap();
+        let stmt = result[0].try_as_expression().unwrap();
+
+        // unary minus binds tighter than `*`
+        let binary = stmt.expression.try_as_binary().unwrap();
+        assert!(matches!(binary.operator, Token::Star));
+
+        let unary = binary.left.try_as_unary().unwrap();
+        assert!(matches!(unary.operator, Token::Minus));
+        assert_eq!(unary.operand.try_as_identifier().unwrap().name, "x");
+    }
+
+    #[test]
+    fn test_parse_nested_unary_after_binary_operator() {
+        let result = ASTParser::parse_from_source("1 - -+2;").unwrap();
+        let stmt = result[0].try_as_expression().unwrap();
+
+        let binary = stmt.expression.try_as_binary().unwrap();
+        assert!(matches!(binary.operator, Token::Minus));
+
+        let outer = binary.right.try_as_unary().unwrap();
+        assert!(matches!(outer.operator, Token::Minus));
+
+        let inner = outer.operand.try_as_unary().unwrap();
+        assert!(matches!(inner.operator, Token::Plus));
+        assert_eq!(inner.operand.try_as_numeric_literal().unwrap().value, 2.0);
+    }
+
     #[test]
     fn test_parse_identifier() {
         let result = ASTParser::parse_from_source("x;").unwrap();
@@ -1163,6 +2023,53 @@ mod tests {
         assert!(expr.right.try_as_identifier().is_some());
     }
 
+    #[test]
+    fn test_parse_chained_assignment_is_right_associative() {
+        let result = ASTParser::parse_from_source("a = b = 1 + 2;").unwrap();
+
+        let stmt = result[0].try_as_expression().unwrap();
+        let outer = stmt.expression.try_as_binary().unwrap();
+        assert!(matches!(outer.operator, Token::Equal));
+        assert_eq!(outer.left.try_as_identifier().unwrap().name, "a");
+
+        let inner = outer.right.try_as_binary().unwrap();
+        assert!(matches!(inner.operator, Token::Equal));
+        assert_eq!(inner.left.try_as_identifier().unwrap().name, "b");
+        assert!(matches!(
+            inner.right.try_as_binary().unwrap().operator,
+            Token::Plus
+        ));
+    }
+
+    #[test]
+    fn test_parse_remainder_from_binding_power_table() {
+        // `%` shares the multiplicative level: 1 + 7 % 4 * 2 is 1 + ((7 % 4) * 2)
+        let result = ASTParser::parse_from_source("1 + 7 % 4 * 2;").unwrap();
+
+        let stmt = result[0].try_as_expression().unwrap();
+        let add = stmt.expression.try_as_binary().unwrap();
+        assert!(matches!(add.operator, Token::Plus));
+
+        let mul = add.right.try_as_binary().unwrap();
+        assert!(matches!(mul.operator, Token::Star));
+
+        let rem = mul.left.try_as_binary().unwrap();
+        assert!(matches!(rem.operator, Token::Percent));
+        assert_eq!(rem.left.try_as_numeric_literal().unwrap().value, 7.0);
+    }
+
+    #[test]
+    fn test_binding_power_table() {
+        let (add_left, add_right) = ASTParser::binding_power(&Token::Plus).unwrap();
+        let (mul_left, _) = ASTParser::binding_power(&Token::Star).unwrap();
+        let (assign_left, assign_right) = ASTParser::binding_power(&Token::Equal).unwrap();
+
+        assert!(mul_left > add_left);
+        assert!(add_left < add_right);
+        assert!(assign_left > assign_right);
+        assert!(ASTParser::binding_power(&Token::Comma).is_none());
+    }
+
     #[test]
     fn test_parse_assignment() {
         let result = ASTParser::parse_from_source("x = 5;").unwrap();
@@ -1227,6 +2134,25 @@ mod tests {
         assert!(stmt1.expression.try_as_numeric_literal().is_some());
     }
 
+    #[test]
+    fn test_parse_final_statement_without_semicolon() {
+        let result = ASTParser::parse_from_source("42").unwrap();
+        assert_eq!(result.len(), 1);
+
+        let result = ASTParser::parse_from_source("let x = 1").unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].try_as_let().unwrap().declarations[0].name, "x");
+
+        let result = ASTParser::parse_from_source("let x = 1; x").unwrap();
+        assert_eq!(result.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_statements_without_separator_error() {
+        let result = ASTParser::parse_from_source("let x = 1 let y = 2");
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_parse_chained_operations() {
         let result = ASTParser::parse_from_source("1 + 2 + 3;").unwrap();
@@ -1275,21 +2201,75 @@ mod tests {
         assert_eq!(result.len(), 1);
 
         let stmt = result[0].try_as_let().unwrap();
-        assert_eq!(stmt.name, "x");
+        assert_eq!(stmt.declarations[0].name, "x");
 
-        let expr = stmt.value.try_as_numeric_literal().unwrap();
+        let expr = stmt.declarations[0]
+            .value
+            .as_ref()
+            .unwrap()
+            .try_as_numeric_literal()
+            .unwrap();
         assert_eq!(expr.value, 42.0);
     }
 
+    #[test]
+    fn test_parse_let_without_initializer() {
+        let result = ASTParser::parse_from_source("let x; let a, b = 1;").unwrap();
+
+        let stmt = result[0].try_as_let().unwrap();
+        assert_eq!(stmt.declarations[0].name, "x");
+        assert!(stmt.declarations[0].value.is_none());
+
+        let stmt = result[1].try_as_let().unwrap();
+        assert!(stmt.declarations[0].value.is_none());
+        assert!(stmt.declarations[1].value.is_some());
+    }
+
+    #[test]
+    fn test_parse_let_with_multiple_bindings() {
+        let result = ASTParser::parse_from_source("let a = 1, b = a + 1;").unwrap();
+        assert_eq!(result.len(), 1);
+
+        let stmt = result[0].try_as_let().unwrap();
+        assert_eq!(stmt.declarations.len(), 2);
+        assert_eq!(stmt.declarations[0].name, "a");
+        assert_eq!(stmt.declarations[1].name, "b");
+
+        let expr = stmt.declarations[1]
+            .value
+            .as_ref()
+            .unwrap()
+            .try_as_binary()
+            .unwrap();
+        assert_eq!(expr.left.try_as_identifier().unwrap().name, "a");
+    }
+
+    #[test]
+    fn test_parse_let_errors() {
+        let err = ASTParser::parse_from_source("let a = 1, ;").unwrap_err();
+        assert_eq!(
+            err.message(),
+            "Expected identifier and a statement after let"
+        );
+
+        let err = ASTParser::parse_from_source("let a 1;").unwrap_err();
+        assert_eq!(err.message(), "Expected = after let binding a");
+    }
+
     #[test]
     fn test_parse_let_with_expression() {
         let result = ASTParser::parse_from_source("let y = 10 + 5;").unwrap();
         assert_eq!(result.len(), 1);
 
         let stmt = result[0].try_as_let().unwrap();
-        assert_eq!(stmt.name, "y");
+        assert_eq!(stmt.declarations[0].name, "y");
 
-        let expr = stmt.value.try_as_binary().unwrap();
+        let expr = stmt.declarations[0]
+            .value
+            .as_ref()
+            .unwrap()
+            .try_as_binary()
+            .unwrap();
         assert!(matches!(expr.operator, Token::Plus));
     }
 
@@ -1478,6 +2458,188 @@ mod tests {
         assert_eq!(obj.name, "obj");
     }
 
+    #[test]
+    fn test_parse_parenthesized_object_literal_statement() {
+        let result = ASTParser::parse_from_source("({a:1});").unwrap();
+        assert_eq!(result.len(), 1);
+
+        let stmt = result[0].try_as_expression().unwrap();
+        let object = stmt.expression.try_as_object_literal().unwrap();
+        assert_eq!(object.properties.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_object_literal_in_statement_position_errors() {
+        let err = ASTParser::parse_from_source("{a:1};").unwrap_err();
+        assert!(err.message().contains("wrap it in parentheses"));
+
+        let err = ASTParser::parse_from_source("{'a': 1, b: 2};").unwrap_err();
+        assert!(err.message().contains("wrap it in parentheses"));
+    }
+
+    #[test]
+    fn test_parse_optional_chaining() {
+        let result = ASTParser::parse_from_source("obj.maybeFn?.(1);").unwrap();
+        let stmt = result[0].try_as_expression().unwrap();
+
+        let call = stmt.expression.try_as_function_call().unwrap();
+        assert!(call.optional);
+        assert_eq!(call.arguments.len(), 1);
+
+        let access = call.function.try_as_property_access().unwrap();
+        assert!(!access.optional);
+        assert_eq!(access.property, "maybeFn");
+
+        let result = ASTParser::parse_from_source("a?.b;").unwrap();
+        let stmt = result[0].try_as_expression().unwrap();
+        assert!(stmt.expression.try_as_property_access().unwrap().optional);
+
+        let result = ASTParser::parse_from_source("a?.[\"b\"][0];").unwrap();
+        let stmt = result[0].try_as_expression().unwrap();
+        let outer = stmt.expression.try_as_element_access().unwrap();
+        assert!(!outer.optional);
+        let inner = outer.expression.try_as_element_access().unwrap();
+        assert!(inner.optional);
+        assert_eq!(inner.element.try_as_string_literal().unwrap().value, "b");
+    }
+
+    #[test]
+    fn test_parse_for_statement() {
+        let result = ASTParser::parse_from_source(
+            "for (let i = 0; i < 3; i = i + 1) { if (i) { continue; }; break; };",
+        )
+        .unwrap();
+        assert_eq!(result.len(), 1);
+
+        let stmt = result[0].try_as_for().unwrap();
+        assert!(stmt.init.as_ref().unwrap().try_as_let().is_some());
+        assert!(stmt.condition.as_ref().unwrap().try_as_binary().is_some());
+        assert!(stmt.update.as_ref().unwrap().try_as_binary().is_some());
+
+        let body = stmt.body.try_as_block().unwrap();
+        assert!(matches!(body.body[1], Statement::Break));
+    }
+
+    #[test]
+    fn test_parse_for_with_empty_clauses() {
+        let result = ASTParser::parse_from_source("for (;;) { break; };").unwrap();
+
+        let stmt = result[0].try_as_for().unwrap();
+        assert!(stmt.init.is_none());
+        assert!(stmt.condition.is_none());
+        assert!(stmt.update.is_none());
+    }
+
+    #[test]
+    fn test_parse_break_outside_loop_errors() {
+        let err = ASTParser::parse_from_source("break;").unwrap_err();
+        assert_eq!(
+            err.message(),
+            "BreakKeyword is allowed only within a loop or switch"
+        );
+
+        let err = ASTParser::parse_from_source("for (;;) { let f = function() { continue; }; };")
+            .unwrap_err();
+        assert_eq!(
+            err.message(),
+            "ContinueKeyword is allowed only within a loop"
+        );
+    }
+
+    #[test]
+    fn test_parse_call_location() {
+        let statements = ASTParser::parse_from_source("x;\n  f(1,\n 2);").unwrap();
+
+        let call = statements[1]
+            .try_as_expression()
+            .unwrap()
+            .expression
+            .try_as_function_call()
+            .unwrap();
+        let location = call.location.unwrap();
+        assert_eq!(location.line, 2);
+        assert_eq!((location.span.start, location.span.end), (5, 13));
+
+        let statements = ASTParser::parse_from_tokens(Lexer::tokenize("f();").unwrap()).unwrap();
+        let call = statements[0]
+            .try_as_expression()
+            .unwrap()
+            .expression
+            .try_as_function_call()
+            .unwrap();
+        assert!(call.location.is_none());
+    }
+
+    #[test]
+    fn test_parse_assignment_to_call() {
+        let statements = ASTParser::parse_from_source("foo() = 1;").unwrap();
+
+        let assignment = statements[0]
+            .try_as_expression()
+            .unwrap()
+            .expression
+            .try_as_binary()
+            .unwrap();
+        assert!(matches!(assignment.operator, Token::Equal));
+        assert_eq!(assignment.left.kind_name(), "function call");
+    }
+
+    #[test]
+    fn test_parse_switch() {
+        let statements =
+            ASTParser::parse_from_source("switch (x) { case 1: a; b; default: c; case 2: }")
+                .unwrap();
+
+        let switch = statements[0].try_as_switch().unwrap();
+        assert_eq!(switch.cases.len(), 3);
+        assert_eq!(switch.cases[0].body.len(), 2);
+        assert!(switch.cases[1].test.is_none());
+        assert!(switch.cases[2].body.is_empty());
+    }
+
+    #[test]
+    fn test_parse_switch_errors() {
+        let err =
+            ASTParser::parse_from_source("switch (x) { default: a; default: b }").unwrap_err();
+        assert_eq!(
+            err.message(),
+            "More than one default clause in switch statement"
+        );
+
+        let err = ASTParser::parse_from_source("switch (x) { case 1: continue }").unwrap_err();
+        assert_eq!(
+            err.message(),
+            "ContinueKeyword is allowed only within a loop"
+        );
+    }
+
+    #[test]
+    fn test_parse_bare_return() {
+        let result =
+            ASTParser::parse_from_source("function f() { if (1) { return; }; 2; };").unwrap();
+        let func = result[0]
+            .try_as_expression()
+            .and_then(|e| e.expression.try_as_function_definition())
+            .unwrap();
+
+        let if_stmt = func.block.body[0].try_as_if().unwrap();
+        let then = if_stmt.then.try_as_block().unwrap();
+        assert!(then.body[0].try_as_return().unwrap().expression.is_none());
+
+        let result = ASTParser::parse_from_source("function g() { return }").unwrap();
+        let func = result[0]
+            .try_as_expression()
+            .and_then(|e| e.expression.try_as_function_definition())
+            .unwrap();
+        assert!(
+            func.block.body[0]
+                .try_as_return()
+                .unwrap()
+                .expression
+                .is_none()
+        );
+    }
+
     #[test]
     fn test_parse_empty_block() {
         let result = ASTParser::parse_from_source("{}").unwrap();
@@ -1509,10 +2671,10 @@ mod tests {
         assert_eq!(block.body.len(), 2);
 
         let stmt1 = block.body[0].try_as_let().unwrap();
-        assert_eq!(stmt1.name, "x");
+        assert_eq!(stmt1.declarations[0].name, "x");
 
         let stmt2 = block.body[1].try_as_let().unwrap();
-        assert_eq!(stmt2.name, "y");
+        assert_eq!(stmt2.declarations[0].name, "y");
     }
 
     #[test]
@@ -1554,7 +2716,7 @@ mod tests {
         assert_eq!(block.body.len(), 2);
 
         let let_stmt = block.body[0].try_as_let().unwrap();
-        assert_eq!(let_stmt.name, "x");
+        assert_eq!(let_stmt.declarations[0].name, "x");
 
         let expr_stmt = block.body[1].try_as_expression().unwrap();
         assert!(expr_stmt.expression.try_as_binary().is_some());
@@ -1620,7 +2782,7 @@ mod tests {
         assert_eq!(func.block.body.len(), 1);
 
         let stmt = func.block.body[0].try_as_let().unwrap();
-        assert_eq!(stmt.name, "x");
+        assert_eq!(stmt.declarations[0].name, "x");
     }
 
     #[test]
@@ -1639,7 +2801,7 @@ mod tests {
         assert_eq!(func.block.body.len(), 2);
 
         let let_stmt = func.block.body[0].try_as_let().unwrap();
-        assert_eq!(let_stmt.name, "x");
+        assert_eq!(let_stmt.declarations[0].name, "x");
 
         let expr_stmt = func.block.body[1].try_as_expression().unwrap();
         assert!(expr_stmt.expression.try_as_binary().is_some());
@@ -1786,6 +2948,51 @@ mod tests {
         assert_eq!(func_id.name, "foo");
     }
 
+    #[test]
+    fn test_parse_new_expression() {
+        let result = ASTParser::parse_from_source("new Foo(1, 2);").unwrap();
+        assert_eq!(result.len(), 1);
+
+        let stmt = result[0].try_as_expression().unwrap();
+        let new_expr = stmt.expression.try_as_new().unwrap();
+
+        let constructor = new_expr.constructor.try_as_identifier().unwrap();
+        assert_eq!(constructor.name, "Foo");
+        assert_eq!(new_expr.arguments.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_new_expression_without_arguments() {
+        let result = ASTParser::parse_from_source("new Foo;").unwrap();
+
+        let stmt = result[0].try_as_expression().unwrap();
+        let new_expr = stmt.expression.try_as_new().unwrap();
+        assert_eq!(new_expr.arguments.len(), 0);
+    }
+
+    #[test]
+    fn test_parse_new_member_constructor() {
+        let result = ASTParser::parse_from_source("new a.b(1);").unwrap();
+
+        let stmt = result[0].try_as_expression().unwrap();
+        let new_expr = stmt.expression.try_as_new().unwrap();
+
+        let constructor = new_expr.constructor.try_as_property_access().unwrap();
+        assert_eq!(constructor.property, "b");
+        assert_eq!(new_expr.arguments.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_method_call_on_new_expression() {
+        let result = ASTParser::parse_from_source("new Date().getTime();").unwrap();
+
+        let stmt = result[0].try_as_expression().unwrap();
+        let call = stmt.expression.try_as_function_call().unwrap();
+        let property_access = call.function.try_as_property_access().unwrap();
+        assert_eq!(property_access.property, "getTime");
+        assert!(property_access.expression.try_as_new().is_some());
+    }
+
     #[test]
     fn test_parse_function_call_in_expression() {
         let result = ASTParser::parse_from_source("foo() + bar();").unwrap();
@@ -1878,6 +3085,135 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_object_literal_spread() {
+        let result = ASTParser::parse_from_source("({...a, b: 1, ...c});").unwrap();
+
+        let stmt = result[0].try_as_expression().unwrap();
+        let obj = stmt.expression.try_as_object_literal().unwrap();
+        assert_eq!(obj.properties.len(), 3);
+        assert!(matches!(obj.properties[0].name, ObjectPropertyName::Spread));
+        assert_eq!(
+            obj.properties[0].value.try_as_identifier().unwrap().name,
+            "a"
+        );
+        assert!(matches!(obj.properties[2].name, ObjectPropertyName::Spread));
+
+        assert!(ASTParser::parse_from_source("({...a").is_err());
+    }
+
+    #[test]
+    fn test_parse_object_literal_literal_keys() {
+        let result = ASTParser::parse_from_source("({0: a, 1.5: b, \"c d\": c});").unwrap();
+
+        let stmt = result[0].try_as_expression().unwrap();
+        let obj = stmt.expression.try_as_object_literal().unwrap();
+        let names: Vec<&str> = obj
+            .properties
+            .iter()
+            .map(|prop| match &prop.name {
+                ObjectPropertyName::Name(name) => name.as_str(),
+                _ => panic!("expected a static key"),
+            })
+            .collect();
+        assert_eq!(names, vec!["0", "1.5", "c d"]);
+    }
+
+    #[test]
+    fn test_parse_arrow_function_expression_body() {
+        let result = ASTParser::parse_from_source("(x) => ({ x });").unwrap();
+
+        let stmt = result[0].try_as_expression().unwrap();
+        let function = stmt.expression.try_as_function_definition().unwrap();
+        let body = function.block.body[0].try_as_return().unwrap();
+        assert!(
+            body.expression
+                .as_ref()
+                .unwrap()
+                .try_as_object_literal()
+                .is_some()
+        );
+
+        // arrow bodies are function bodies: `return` is allowed, an outer loop's `break` is not
+        assert!(ASTParser::parse_from_source("() => { return 1; };").is_ok());
+        assert!(ASTParser::parse_from_source("for (;;) { let f = () => { break; }; };").is_err());
+    }
+
+    #[test]
+    fn test_parse_chains_after_parenthesized_expression() {
+        let result = ASTParser::parse_from_source("(a || b).c;").unwrap();
+        let expression = &result[0].try_as_expression().unwrap().expression;
+        let access = expression.try_as_property_access().unwrap();
+        assert_eq!(access.property, "c");
+        assert!(access.expression.try_as_binary().is_some());
+
+        let result = ASTParser::parse_from_source("(getObj())[0];").unwrap();
+        let expression = &result[0].try_as_expression().unwrap().expression;
+        let access = expression.try_as_element_access().unwrap();
+        assert!(access.expression.try_as_function_call().is_some());
+        assert!(access.element.try_as_numeric_literal().is_some());
+
+        let result = ASTParser::parse_from_source("(f)(x).y(z);").unwrap();
+        let expression = &result[0].try_as_expression().unwrap().expression;
+        let outer = expression.try_as_function_call().unwrap();
+        let method = outer.function.try_as_property_access().unwrap();
+        assert_eq!(method.property, "y");
+        let inner = method.expression.try_as_function_call().unwrap();
+        assert_eq!(inner.function.try_as_identifier().unwrap().name, "f");
+        assert_eq!(inner.arguments.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_empty_sources() {
+        assert!(ASTParser::parse_from_source("").unwrap().is_empty());
+        assert!(ASTParser::parse_from_source("   \n ").unwrap().is_empty());
+        assert!(ASTParser::parse_from_tokens(vec![]).unwrap().is_empty());
+        assert!(
+            ASTParser::parse_from_tokens(vec![Token::End])
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_parse_for_of_and_spread() {
+        let result = ASTParser::parse_from_source("for (let x of [...a, 1]) { x; };").unwrap();
+
+        let for_of = result[0].try_as_for_of().unwrap();
+        assert_eq!(for_of.binding, "x");
+
+        let array = for_of.iterable.try_as_array_literal().unwrap();
+        assert!(matches!(array.elements[0], Expression::Spread(_)));
+        assert!(array.elements[1].try_as_numeric_literal().is_some());
+    }
+
+    #[test]
+    fn test_parse_computed_key_with_function_value() {
+        let result = ASTParser::parse_from_source("({[k]: function() { return 1; }, v});").unwrap();
+
+        let stmt = result[0].try_as_expression().unwrap();
+        let obj = stmt.expression.try_as_object_literal().unwrap();
+        assert_eq!(obj.properties.len(), 2);
+
+        assert!(matches!(
+            obj.properties[0].name,
+            ObjectPropertyName::Computed(_)
+        ));
+        assert!(
+            obj.properties[0]
+                .value
+                .try_as_function_definition()
+                .is_some()
+        );
+
+        // shorthand property
+        assert!(matches!(&obj.properties[1].name, ObjectPropertyName::Name(name) if name == "v"));
+        assert_eq!(
+            obj.properties[1].value.try_as_identifier().unwrap().name,
+            "v"
+        );
+    }
+
     #[test]
     fn test_parse_nested_object_literal() {
         let result = ASTParser::parse_from_source("({a: {b: 1}});").unwrap();
@@ -2087,6 +3423,21 @@ mod tests {
         assert_eq!(else_if_cond.name, "y");
     }
 
+    #[test]
+    fn test_parse_if_with_else_if_across_newline() {
+        let result = ASTParser::parse_from_source("if (x) { 1; } else\n  if (y) { }").unwrap();
+        assert_eq!(result.len(), 1);
+
+        let if_stmt = result[0].try_as_if().unwrap();
+
+        let else_if = if_stmt.else_.as_ref().unwrap().try_as_if().unwrap();
+        let else_if_cond = else_if.condition.try_as_identifier().unwrap();
+        assert_eq!(else_if_cond.name, "y");
+
+        let else_if_block = else_if.then.try_as_block().unwrap();
+        assert_eq!(else_if_block.body.len(), 0);
+    }
+
     #[test]
     fn test_parse_if_with_complex_condition() {
         let result = ASTParser::parse_from_source("if (x > 5) { 1; }").unwrap();
@@ -2178,7 +3529,12 @@ mod tests {
         assert_eq!(func.block.body.len(), 1);
 
         let ret_stmt = func.block.body[0].try_as_return().unwrap();
-        let expr = ret_stmt.expression.try_as_identifier().unwrap();
+        let expr = ret_stmt
+            .expression
+            .as_ref()
+            .unwrap()
+            .try_as_identifier()
+            .unwrap();
         assert_eq!(expr.name, "x");
     }
 
@@ -2193,7 +3549,14 @@ mod tests {
             .unwrap();
 
         let ret_stmt = func.block.body[0].try_as_return().unwrap();
-        assert!(ret_stmt.expression.try_as_binary().is_some());
+        assert!(
+            ret_stmt
+                .expression
+                .as_ref()
+                .unwrap()
+                .try_as_binary()
+                .is_some()
+        );
     }
 
     #[test]
@@ -2207,7 +3570,12 @@ mod tests {
             .unwrap();
 
         let ret_stmt = func.block.body[0].try_as_return().unwrap();
-        let num = ret_stmt.expression.try_as_numeric_literal().unwrap();
+        let num = ret_stmt
+            .expression
+            .as_ref()
+            .unwrap()
+            .try_as_numeric_literal()
+            .unwrap();
         assert_eq!(num.value, 42.0);
     }
 
@@ -2232,7 +3600,12 @@ mod tests {
             .unwrap();
 
         let ret_stmt = func.block.body[0].try_as_return().unwrap();
-        let binary = ret_stmt.expression.try_as_binary().unwrap();
+        let binary = ret_stmt
+            .expression
+            .as_ref()
+            .unwrap()
+            .try_as_binary()
+            .unwrap();
         assert!(matches!(binary.operator, Token::Plus));
     }
 
@@ -2247,7 +3620,12 @@ mod tests {
             .unwrap();
 
         let ret_stmt = func.block.body[0].try_as_return().unwrap();
-        let call = ret_stmt.expression.try_as_function_call().unwrap();
+        let call = ret_stmt
+            .expression
+            .as_ref()
+            .unwrap()
+            .try_as_function_call()
+            .unwrap();
         let func_id = call.function.try_as_identifier().unwrap();
         assert_eq!(func_id.name, "foo");
     }
@@ -2360,9 +3738,14 @@ mod tests {
         assert_eq!(result.len(), 1);
 
         let stmt = result[0].try_as_let().unwrap();
-        assert_eq!(stmt.name, "f");
+        assert_eq!(stmt.declarations[0].name, "f");
 
-        let func = stmt.value.try_as_function_definition().unwrap();
+        let func = stmt.declarations[0]
+            .value
+            .as_ref()
+            .unwrap()
+            .try_as_function_definition()
+            .unwrap();
         assert!(func.is_anonymous());
         assert_eq!(func.arguments.len(), 1);
     }
@@ -2373,9 +3756,14 @@ mod tests {
         assert_eq!(result.len(), 1);
 
         let stmt = result[0].try_as_let().unwrap();
-        assert_eq!(stmt.name, "f");
+        assert_eq!(stmt.declarations[0].name, "f");
 
-        let func = stmt.value.try_as_function_definition().unwrap();
+        let func = stmt.declarations[0]
+            .value
+            .as_ref()
+            .unwrap()
+            .try_as_function_definition()
+            .unwrap();
         assert!(func.is_arrow());
         assert_eq!(func.arguments.len(), 1);
     }
@@ -2434,4 +3822,155 @@ mod tests {
         let func = arr.elements[0].try_as_function_definition().unwrap();
         assert!(func.is_arrow());
     }
+
+    #[test]
+    fn test_parse_new_target() {
+        let tokens = Lexer::tokenize("let f = function() { return new.target; };").unwrap();
+        assert!(ASTParser::parse_from_tokens(tokens).is_ok());
+
+        let tokens = Lexer::tokenize("new.target;").unwrap();
+        let err = ASTParser::parse_from_tokens(tokens).unwrap_err();
+        assert_eq!(err.message(), "new.target expression is not allowed here");
+
+        let tokens = Lexer::tokenize("let f = function() { return new.other; };").unwrap();
+        assert!(ASTParser::parse_from_tokens(tokens).is_err());
+    }
+
+    #[test]
+    fn test_parse_conditional_precedence() {
+        let result = ASTParser::parse_from_source("a ? b : c = d;").unwrap();
+        let stmt = result[0].try_as_expression().unwrap();
+
+        let conditional = stmt.expression.try_as_conditional().unwrap();
+        assert_eq!(conditional.test.try_as_identifier().unwrap().name, "a");
+        assert_eq!(
+            conditional.consequent.try_as_identifier().unwrap().name,
+            "b"
+        );
+
+        let assignment = conditional.alternate.try_as_binary().unwrap();
+        assert!(matches!(assignment.operator, Token::Equal));
+        assert_eq!(assignment.left.try_as_identifier().unwrap().name, "c");
+
+        let result = ASTParser::parse_from_source("x = a || b ? c : d ? e : f;").unwrap();
+        let stmt = result[0].try_as_expression().unwrap();
+
+        let assignment = stmt.expression.try_as_binary().unwrap();
+        assert!(matches!(assignment.operator, Token::Equal));
+        assert_eq!(assignment.left.try_as_identifier().unwrap().name, "x");
+
+        let conditional = assignment.right.try_as_conditional().unwrap();
+        let test = conditional.test.try_as_binary().unwrap();
+        assert!(matches!(test.operator, Token::OrOr));
+
+        // conditionals nest to the right
+        let nested = conditional.alternate.try_as_conditional().unwrap();
+        assert_eq!(nested.test.try_as_identifier().unwrap().name, "d");
+    }
+
+    #[test]
+    fn test_parse_conditional_missing_colon() {
+        let err = ASTParser::parse_from_source("a ? b;").unwrap_err();
+        assert!(
+            err.message()
+                .starts_with("Expected : in conditional expression")
+        );
+    }
+
+    #[test]
+    fn test_parse_try_statement() {
+        let result =
+            ASTParser::parse_from_source("try { a; } catch (e) { b; } finally { c; };").unwrap();
+        let Statement::Try(try_statement) = &result[0] else {
+            panic!("expected a try statement, got {:#?}", result[0]);
+        };
+
+        assert_eq!(try_statement.block.body.len(), 1);
+        let handler = try_statement.handler.as_ref().unwrap();
+        assert_eq!(handler.param.as_deref(), Some("e"));
+        assert!(try_statement.finalizer.is_some());
+
+        let err = ASTParser::parse_from_source("try { a; };").unwrap_err();
+        assert_eq!(err.message(), "Missing catch or finally after try");
+    }
+
+    #[test]
+    fn test_unterminated_delimiters() {
+        let cases = [
+            (
+                "let o = {a: 1",
+                "Unterminated object literal opened at line 1",
+            ),
+            (
+                "let a =\n  [1, 2",
+                "Unterminated array literal opened at line 2",
+            ),
+            ("f(1,\n2", "Unterminated argument list opened at line 1"),
+            (
+                "let x = (1 + 2",
+                "Unterminated parenthesized expression opened at line 1",
+            ),
+            ("if (x) {\n  a;", "Unterminated block opened at line 1"),
+        ];
+
+        for (source, message) in cases {
+            let err = ASTParser::parse_from_source(source).unwrap_err();
+            assert_eq!(err.message(), message, "for {:?}", source);
+        }
+
+        let err = ASTParser::parse_from_source("let o = {a: 1").unwrap_err();
+        assert_eq!(err.span(), Some(Span { start: 8, end: 9 }));
+
+        // without source locations the line is left out
+        let tokens = Lexer::tokenize("[1, 2").unwrap();
+        let err = ASTParser::parse_from_tokens(tokens).unwrap_err();
+        assert_eq!(err.message(), "Unterminated array literal");
+    }
+
+    #[test]
+    fn test_parse_exponent_is_right_associative() {
+        let result = ASTParser::parse_from_source("2 * 3 ** 2 ** 2;").unwrap();
+        let stmt = result[0].try_as_expression().unwrap();
+
+        let mul = stmt.expression.try_as_binary().unwrap();
+        assert!(matches!(mul.operator, Token::Star));
+
+        let outer = mul.right.try_as_binary().unwrap();
+        assert!(matches!(outer.operator, Token::StarStar));
+        assert_eq!(outer.left.try_as_numeric_literal().unwrap().value, 3.0);
+
+        let inner = outer.right.try_as_binary().unwrap();
+        assert!(matches!(inner.operator, Token::StarStar));
+    }
+
+    #[test]
+    fn test_parse_braceless_if_else() {
+        let result = ASTParser::parse_from_source("if (a) b; else c; d;").unwrap();
+        assert_eq!(result.len(), 2);
+
+        let Statement::If(if_statement) = &result[0] else {
+            panic!("expected an if statement, got {:#?}", result[0]);
+        };
+        assert!(if_statement.then.try_as_expression().is_some());
+        let else_ = if_statement.else_.as_ref().unwrap();
+        let else_ = else_.try_as_expression().unwrap();
+        assert_eq!(else_.expression.try_as_identifier().unwrap().name, "c");
+    }
+
+    #[test]
+    fn test_parse_dangling_else_binds_to_nearest_if() {
+        let result = ASTParser::parse_from_source("if (a) if (b) c; else d;").unwrap();
+        assert_eq!(result.len(), 1);
+
+        let Statement::If(outer) = &result[0] else {
+            panic!("expected an if statement, got {:#?}", result[0]);
+        };
+        assert!(outer.else_.is_none());
+
+        let Statement::If(inner) = outer.then.as_ref() else {
+            panic!("expected a nested if statement, got {:#?}", outer.then);
+        };
+        let else_ = inner.else_.as_ref().unwrap().try_as_expression().unwrap();
+        assert_eq!(else_.expression.try_as_identifier().unwrap().name, "d");
+    }
 }
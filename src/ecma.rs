@@ -7,18 +7,25 @@ pub trait JSModule {
     fn init(&mut self, vm: &mut VM);
 }
 
-use std::rc::Rc;
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use crate::{
     ast::FunctionDefinitionExpression,
     error::EngineError,
-    vm::{CallContext, JSValue, NativeFunction, Object, ObjectRef, Scope, VM},
+    number::number_to_string,
+    vm::{CallContext, JSValue, NativeFunction, Object, ObjectRef, PropertyAttributes, VM},
 };
 
-pub const PROTOTYPE: &'static str = "prototype";
+pub const PROTOTYPE: &str = "prototype";
 
-pub const OBJECT: &'static str = "Object";
+pub const OBJECT: &str = "Object";
 
+#[derive(Default)]
 pub struct ObjectClass {}
 
 impl JSModule for ObjectClass {
@@ -30,24 +37,26 @@ impl JSModule for ObjectClass {
         let prototype = Object::new().alloc(vm);
 
         let constructor = Object::new()
-            .with_property(PROTOTYPE, JSValue::from_object_ref(prototype.clone()))
+            .with_property(PROTOTYPE, JSValue::from_object_ref(prototype))
             .alloc(vm);
 
-        prototype
-            .load_mut(vm)
-            .set_property("constructor", JSValue::from_object_ref(constructor.clone()));
+        prototype.load_mut(vm).define_property(
+            "constructor",
+            JSValue::from_object_ref(constructor),
+            PropertyAttributes::builtin(),
+        );
 
         vm.global_this
             .load_mut(vm)
-            .set_prototype(prototype.clone()) // set global object's prototype
-            .set_property(OBJECT, JSValue::from_object_ref(constructor.clone()));
+            .set_prototype(prototype) // set global object's prototype
+            .set_property(OBJECT, JSValue::from_object_ref(constructor));
     }
 }
 
-pub const OBJECT_STRING: &'static str = "[object Object]";
+pub const OBJECT_STRING: &str = "[object Object]";
 
 impl ObjectClass {
-    pub fn new() -> impl JSModule {
+    pub fn new() -> Self {
         Self {}
     }
 
@@ -65,8 +74,181 @@ impl ObjectClass {
     }
 
     fn init_methods(vm: &mut VM, function_prototype: ObjectRef, object_prototype: ObjectRef) {
-        let func = JSValue::native_function(function_prototype.clone(), Self::to_string, vm);
-        object_prototype.load_mut(vm).set_property("toString", func);
+        let func = JSValue::native_function(function_prototype, Self::to_string, vm);
+        let value_of = JSValue::native_function(function_prototype, Self::value_of, vm);
+        object_prototype
+            .load_mut(vm)
+            .define_property("toString", func, PropertyAttributes::builtin())
+            .define_property("valueOf", value_of, PropertyAttributes::builtin());
+
+        let constructor = object_prototype
+            .load(vm)
+            .get_property("constructor")
+            .and_then(|constructor| constructor.try_as_object())
+            .expect("Object.prototype.constructor is missing");
+
+        let is = JSValue::native_function(function_prototype, Self::is, vm);
+        let keys = JSValue::native_function(function_prototype, Self::keys, vm);
+        let define_property =
+            JSValue::native_function(function_prototype, Self::define_property, vm);
+
+        constructor
+            .load_mut(vm)
+            .define_property("is", is, PropertyAttributes::builtin())
+            .define_property("keys", keys, PropertyAttributes::builtin())
+            .define_property(
+                "defineProperty",
+                define_property,
+                PropertyAttributes::builtin(),
+            );
+    }
+
+    /**
+     * `Object.prototype.valueOf` returns the object itself, so ToPrimitive on plain
+     * objects and arrays falls through to `toString`.
+     */
+    fn value_of(_vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        Ok(JSValue::Object(call.this))
+    }
+
+    /**
+     * `Object.keys(object)`: `own_enumerable_keys` as an array.
+     */
+    fn keys(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let object = call
+            .arg(0)
+            .and_then(|object| object.try_as_object())
+            .ok_or_else(|| EngineError::js("TypeError: Object.keys called on non-object"))?;
+
+        let keys = Self::own_enumerable_keys(vm, object)?
+            .into_iter()
+            .map(JSValue::String)
+            .collect();
+
+        Ok(JSValue::Object(ArrayClass::from_elements(vm, keys)))
+    }
+
+    /**
+     * Own enumerable keys in the order `Object.keys` reports them: integer keys
+     * ascending, then the rest. Insertion order isn't tracked, so the rest are sorted.
+     */
+    pub fn own_enumerable_keys(vm: &VM, object: ObjectRef) -> Result<Vec<String>, EngineError> {
+        let loaded = object.try_load(vm)?;
+
+        let mut keys: Vec<String> = loaded
+            .properties
+            .keys()
+            .filter(|key| {
+                loaded
+                    .property_attributes(key)
+                    .is_some_and(|attributes| attributes.enumerable)
+            })
+            .cloned()
+            .collect();
+
+        keys.sort_by(|a, b| match (a.parse::<u32>(), b.parse::<u32>()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+            (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+            (Err(_), Err(_)) => a.cmp(b),
+        });
+
+        Ok(keys)
+    }
+
+    /**
+     * `Object.defineProperty(object, key, { value, writable, enumerable, get, set })`.
+     * Fields left out keep their current setting, or default to `false`/`undefined`
+     * when the property is new.
+     */
+    fn define_property(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let object = call
+            .arg(0)
+            .and_then(|object| object.try_as_object())
+            .ok_or_else(|| {
+                EngineError::js("TypeError: Object.defineProperty called on non-object")
+            })?;
+
+        let key = call.arg(1).cloned().unwrap_or(JSValue::Undefined);
+        let key = vm.to_property_key(key)?;
+
+        let descriptor = call
+            .arg(2)
+            .and_then(|descriptor| descriptor.try_as_object())
+            .ok_or_else(|| EngineError::js("TypeError: Property description must be an object"))?;
+
+        let mut field = |name: &str| -> Result<Option<JSValue>, EngineError> {
+            if vm.get_property(descriptor, name)?.is_none() {
+                return Ok(None);
+            }
+
+            vm.get(descriptor, name).map(Some)
+        };
+
+        let value = field("value")?;
+        let writable = field("writable")?;
+        let enumerable = field("enumerable")?;
+        let get = field("get")?;
+        let set = field("set")?;
+
+        if (get.is_some() || set.is_some()) && (value.is_some() || writable.is_some()) {
+            return Err(EngineError::js(
+                "TypeError: Invalid property descriptor. Cannot both specify accessors and a value or writable attribute",
+            ));
+        }
+
+        let accessor = |value: Option<JSValue>, name: &str| match value {
+            None | Some(JSValue::Undefined) => Ok(None),
+            Some(JSValue::Object(function)) if function.try_load(vm)?.call.is_some() => {
+                Ok(Some(function))
+            }
+            Some(_) => Err(EngineError::js(format!(
+                "TypeError: {} must be a function",
+                name
+            ))),
+        };
+
+        let is_accessor = get.is_some() || set.is_some();
+        let get = accessor(get, "Getter")?;
+        let set = accessor(set, "Setter")?;
+
+        let loaded = object.try_load_mut(vm)?;
+        let mut attributes = loaded.property_attributes(&key).unwrap_or_default();
+
+        if let Some(enumerable) = enumerable {
+            attributes.enumerable = enumerable.is_truthy();
+        }
+
+        if is_accessor {
+            attributes.writable = false;
+            attributes.get = get;
+            attributes.set = set;
+            loaded.set_property(key.clone(), JSValue::Undefined);
+        } else {
+            if let Some(writable) = writable {
+                attributes.writable = writable.is_truthy();
+            }
+
+            attributes.get = None;
+            attributes.set = None;
+
+            let value = value
+                .or_else(|| loaded.get_property(&key))
+                .unwrap_or(JSValue::Undefined);
+            loaded.set_property(key.clone(), value);
+        }
+
+        loaded.attributes.insert(key, attributes);
+
+        Ok(JSValue::Object(object))
+    }
+
+    fn is(_: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let undefined = JSValue::Undefined;
+        let left = call.arg(0).unwrap_or(&undefined);
+        let right = call.arg(1).unwrap_or(&undefined);
+
+        Ok(JSValue::Boolean(left.same_value(right)))
     }
 
     fn to_string(_: &mut VM, _: CallContext) -> Result<JSValue, EngineError> {
@@ -76,6 +258,7 @@ impl ObjectClass {
 
 const FUNCTION: &str = "Function";
 
+#[derive(Default)]
 pub struct FunctionClass {}
 
 impl JSModule for FunctionClass {
@@ -86,31 +269,35 @@ impl JSModule for FunctionClass {
     fn init(&mut self, vm: &mut VM) {
         let object_prototype = ObjectClass::prototype(vm);
 
-        let prototype = Object::new()
-            .with_prototype(object_prototype.clone())
-            .alloc(vm);
+        let prototype = Object::new().with_prototype(object_prototype).alloc(vm);
 
-        let to_string = JSValue::native_function(prototype.clone(), Self::to_string, vm);
-        prototype.load_mut(vm).set_property("toString", to_string);
+        let to_string = JSValue::native_function(prototype, Self::to_string, vm);
+        prototype.load_mut(vm).define_property(
+            "toString",
+            to_string,
+            PropertyAttributes::builtin(),
+        );
 
-        ObjectClass::init_methods(vm, prototype.clone(), object_prototype);
+        ObjectClass::init_methods(vm, prototype, object_prototype);
 
         let constructor = Object::new()
-            .with_property(PROTOTYPE, JSValue::from_object_ref(prototype.clone()))
+            .with_property(PROTOTYPE, JSValue::from_object_ref(prototype))
             .alloc(vm);
 
-        prototype
-            .load_mut(vm)
-            .set_property("constructor", JSValue::from_object_ref(constructor.clone()));
+        prototype.load_mut(vm).define_property(
+            "constructor",
+            JSValue::from_object_ref(constructor),
+            PropertyAttributes::builtin(),
+        );
 
         vm.global_this
             .load_mut(vm)
-            .set_property(FUNCTION, JSValue::from_object_ref(constructor.clone()));
+            .set_property(FUNCTION, JSValue::from_object_ref(constructor));
     }
 }
 
 impl FunctionClass {
-    pub fn new() -> impl JSModule {
+    pub fn new() -> Self {
         Self {}
     }
 
@@ -118,7 +305,7 @@ impl FunctionClass {
         Object::new()
             .with_prototype(Self::prototype(vm))
             .with_call_native(call)
-            .with_captured_scope(vm.scopes.len() - 1)
+            .with_captured_scopes(vm.scopes.clone())
     }
 
     pub fn create_from_ast(vm: &mut VM, ast: FunctionDefinitionExpression) -> Object {
@@ -128,7 +315,7 @@ impl FunctionClass {
         Object::new()
             .with_prototype(Self::prototype(vm))
             .with_call_ast(index)
-            .with_captured_scope(vm.scopes.len() - 1)
+            .with_captured_scopes(vm.scopes.clone())
     }
 
     pub fn prototype(vm: &mut VM) -> ObjectRef {
@@ -143,6 +330,18 @@ impl FunctionClass {
 
 const ARRAY: &str = "Array";
 
+const ARRAY_ITERATOR_PROTOTYPE: &str = "ArrayIteratorPrototype";
+const ITERATED_OBJECT: &str = "IteratedObject";
+const ITERATOR_NEXT_INDEX: &str = "ArrayIteratorNextIndex";
+const ITERATION_KIND: &str = "ArrayIterationKind";
+
+/**
+ * The longest array the built-in methods copy into a `Vec`. A length past it can only come
+ * from a sparse array, which fails with a RangeError instead of exhausting memory.
+ */
+pub const MAX_ELEMENTS: usize = 1 << 24;
+
+#[derive(Default)]
 pub struct ArrayClass {}
 
 impl JSModule for ArrayClass {
@@ -162,25 +361,134 @@ impl JSModule for ArrayClass {
                 "pop",
                 JSValue::native_function(FunctionClass::prototype(vm), Self::pop, vm),
             )
+            .with_property(
+                "shift",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::shift, vm),
+            )
+            .with_property(
+                "unshift",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::unshift, vm),
+            )
+            .with_property(
+                "flatMap",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::flat_map, vm),
+            )
+            .with_property(
+                "map",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::map, vm),
+            )
+            .with_property(
+                "filter",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::filter, vm),
+            )
+            .with_property(
+                "forEach",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::for_each, vm),
+            )
+            .with_property(
+                "some",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::some, vm),
+            )
+            .with_property(
+                "every",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::every, vm),
+            )
+            .with_property(
+                "find",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::find, vm),
+            )
+            .with_property(
+                "findLast",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::find_last, vm),
+            )
+            .with_property(
+                "findLastIndex",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::find_last_index, vm),
+            )
+            .with_property(
+                "reduce",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::reduce, vm),
+            )
+            .with_property(
+                "reduceRight",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::reduce_right, vm),
+            )
+            .with_property(
+                "indexOf",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::index_of, vm),
+            )
+            .with_property(
+                "includes",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::includes, vm),
+            )
+            .with_property(
+                "lastIndexOf",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::last_index_of, vm),
+            )
+            .with_property(
+                "at",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::at, vm),
+            )
+            .with_property(
+                "keys",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::keys, vm),
+            )
+            .with_property(
+                "values",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::values, vm),
+            )
+            .with_property(
+                "entries",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::entries, vm),
+            )
+            .with_property(
+                "join",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::join, vm),
+            )
+            .with_property(
+                "toString",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::to_string, vm),
+            )
+            .alloc(vm);
+
+        let iterator_prototype = Object::new()
+            .with_prototype(ObjectClass::prototype(vm))
+            .with_property(
+                "next",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::iterator_next, vm),
+            )
             .alloc(vm);
 
+        prototype.load_mut(vm).set_internal_slot(
+            ARRAY_ITERATOR_PROTOTYPE,
+            JSValue::from_object_ref(iterator_prototype),
+        );
+
         let constructor = Object::new()
-            .with_property(PROTOTYPE, JSValue::from_object_ref(prototype.clone()))
+            .with_property(PROTOTYPE, JSValue::from_object_ref(prototype))
             .with_prototype(FunctionClass::prototype(vm))
+            .with_property(
+                "of",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::of, vm),
+            )
+            .with_call_native(Self::array_constructor_fn)
+            .with_construct(Self::array_constructor_fn)
             .alloc(vm);
 
-        prototype
-            .load_mut(vm)
-            .set_property("constructor", JSValue::from_object_ref(constructor.clone()));
+        prototype.load_mut(vm).define_property(
+            "constructor",
+            JSValue::from_object_ref(constructor),
+            PropertyAttributes::builtin(),
+        );
 
         vm.global_this
             .load_mut(vm)
-            .set_property(ARRAY, JSValue::from_object_ref(constructor.clone()));
+            .set_property(ARRAY, JSValue::from_object_ref(constructor));
     }
 }
 
 impl ArrayClass {
-    pub fn new() -> impl JSModule {
+    pub fn new() -> Self {
         Self {}
     }
 
@@ -195,38 +503,192 @@ impl ArrayClass {
             .with_property("length", JSValue::Number(0.0))
     }
 
+    pub fn is_array(vm: &mut VM, value: &JSValue) -> bool {
+        let prototype = Self::prototype(vm);
+
+        value.try_get_prototype(vm) == Some(prototype)
+    }
+
+    pub fn from_elements(vm: &mut VM, elements: Vec<JSValue>) -> ObjectRef {
+        let mut array = Self::create(vm);
+
+        array.set_property("length", JSValue::Number(elements.len() as f64));
+
+        for (index, element) in elements.into_iter().enumerate() {
+            array.set_property(index.to_string(), element);
+        }
+
+        array.alloc(vm)
+    }
+
+    /**
+     * `Array(n)` and `new Array(n)` make `n` holes, any other arguments become the
+     * elements. A single number that isn't a valid length is a `RangeError`.
+     */
+    pub fn array_constructor_fn(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        if let [JSValue::Number(length)] = call.args.as_slice() {
+            let array = Self::create(vm).alloc(vm);
+            Self::put(vm, array, "length", JSValue::Number(*length))?;
+
+            return Ok(JSValue::Object(array));
+        }
+
+        Ok(JSValue::Object(Self::from_elements(vm, call.args)))
+    }
+
+    /**
+     * `Array.of(...items)`, unlike `Array(7)` a lone number is an element, not a length.
+     */
+    pub fn of(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        Ok(JSValue::Object(Self::from_elements(vm, call.args)))
+    }
+
+    /**
+     * The `length` of an array-like `this`, converted with ToLength: a missing or
+     * non-numeric length is 0, so array methods work on plain objects too.
+     */
+    pub fn length(vm: &VM, array: ObjectRef) -> Result<usize, EngineError> {
+        let length = array
+            .try_load(vm)?
+            .get_property("length")
+            .and_then(|property| property.try_as_number())
+            .unwrap_or(0.0);
+
+        if length.is_nan() || length <= 0.0 {
+            return Ok(0);
+        }
+
+        Ok(length.trunc().min(9007199254740991.0) as usize)
+    }
+
+    /**
+     * Reads the numeric keys `0..length` into a `Vec`, holes become `undefined`.
+     */
+    pub fn elements(vm: &VM, array: ObjectRef) -> Result<Vec<JSValue>, EngineError> {
+        let length = Self::length(vm, array)?;
+
+        if length > MAX_ELEMENTS {
+            return Err(EngineError::js("RangeError: Array is too large"));
+        }
+
+        let object = array.try_load(vm)?;
+
+        Ok((0..length)
+            .map(|index| {
+                object
+                    .get_property(&index.to_string())
+                    .unwrap_or(JSValue::Undefined)
+            })
+            .collect())
+    }
+
+    /**
+     * Replaces the numeric keys and `length` of `array` with `elements`.
+     */
+    pub fn set_elements(
+        vm: &mut VM,
+        array: ObjectRef,
+        elements: Vec<JSValue>,
+    ) -> Result<(), EngineError> {
+        let object = array.try_load_mut(vm)?;
+        let removed: Vec<String> = object
+            .properties
+            .keys()
+            .filter(|key| {
+                Self::array_index(key).is_some_and(|index| index as usize >= elements.len())
+            })
+            .cloned()
+            .collect();
+
+        for key in removed {
+            object.delete_property(&key);
+        }
+
+        object.set_property("length", JSValue::Number(elements.len() as f64));
+
+        for (index, element) in elements.into_iter().enumerate() {
+            object.set_property(index.to_string(), element);
+        }
+
+        Ok(())
+    }
+
+    /**
+     * An ordinary write to an array, keeping `length` in sync: assigning `length` deletes
+     * the elements past the new end, writing an index at or past the end extends it.
+     */
+    pub fn put(
+        vm: &mut VM,
+        array: ObjectRef,
+        key: &str,
+        value: JSValue,
+    ) -> Result<(), EngineError> {
+        if key == "length" {
+            let length = vm.to_number(value)?;
+
+            if length < 0.0 || length.fract() != 0.0 || length > u32::MAX as f64 {
+                return Err(EngineError::js("RangeError: Invalid array length"));
+            }
+
+            let object = array.try_load_mut(vm)?;
+            let removed: Vec<String> = object
+                .properties
+                .keys()
+                .filter(|key| Self::array_index(key).is_some_and(|index| index as f64 >= length))
+                .cloned()
+                .collect();
+
+            for key in removed {
+                object.delete_property(&key);
+            }
+
+            object.set_property("length", JSValue::Number(length));
+
+            return Ok(());
+        }
+
+        let length = Self::length(vm, array)?;
+        let object = array.try_load_mut(vm)?;
+        object.set_property(key, value);
+
+        if let Some(index) = Self::array_index(key)
+            && index as usize >= length
+        {
+            object.set_property("length", JSValue::Number(index as f64 + 1.0));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * The index an array property key stands for, `"01"` and `"-1"` are ordinary keys.
+     */
+    pub fn array_index(key: &str) -> Option<u32> {
+        key.parse::<u32>()
+            .ok()
+            .filter(|index| *index != u32::MAX && index.to_string() == key)
+    }
+
     pub fn push(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
-        let mut length = {
-            call.this
-                .load_mut(vm)
-                .get_property("length")
-                .and_then(|property| property.try_as_number())
-                .expect("Array.length is not a number") as usize
-        };
+        let mut length = Self::length(vm, call.this)?;
 
         for arg in call.args.iter() {
             call.this
                 .load_mut(vm)
-                .set_property(&length.to_string(), arg.clone());
+                .set_property(length.to_string(), arg.clone());
 
             length += 1;
 
             call.this
                 .load_mut(vm)
-                .set_property("length", JSValue::Number(length as f32));
+                .set_property("length", JSValue::Number(length as f64));
         }
 
         Ok(call.args.last().cloned().unwrap_or(JSValue::Undefined))
     }
 
     pub fn pop(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
-        let mut length = {
-            call.this
-                .load(vm)
-                .get_property("length")
-                .and_then(|property| property.try_as_number())
-                .expect("Array.length is not a number") as usize
-        };
+        let mut length = Self::length(vm, call.this)?;
 
         if length == 0 {
             return Ok(JSValue::Undefined);
@@ -243,53 +705,1486 @@ impl ArrayClass {
         call.this
             .load_mut(vm)
             .delete_property(&length.to_string())
-            .set_property("length", JSValue::Number(length as f32));
+            .set_property("length", JSValue::Number(length as f64));
 
         Ok(value)
     }
-}
 
-const BOOLEAN: &str = "Boolean";
+    pub fn shift(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let mut elements = Self::elements(vm, call.this)?;
 
-pub struct BooleanClass {}
+        if elements.is_empty() {
+            Self::set_elements(vm, call.this, elements)?;
 
-impl JSModule for BooleanClass {
-    fn name(&self) -> &str {
-        BOOLEAN
+            return Ok(JSValue::Undefined);
+        }
+
+        let first = elements.remove(0);
+        Self::set_elements(vm, call.this, elements)?;
+
+        Ok(first)
     }
 
-    fn init(&mut self, vm: &mut VM) {
-        let constructor = Object::new()
-            .with_prototype(FunctionClass::prototype(vm))
-            .with_call_native(Self::boolean_constructor_fn) // Boolean({}) = true, Boolean(0) = false, etc as in JS
-            .alloc(vm);
+    /**
+     * The callback argument of an iteration method such as `map`, which must be callable.
+     */
+    fn callback(vm: &VM, call: &CallContext, method: &str) -> Result<ObjectRef, EngineError> {
+        call.arg(0)
+            .and_then(|callback| callback.try_as_object())
+            .filter(|callback| callback.load(vm).call.is_some())
+            .ok_or_else(|| {
+                EngineError::js(format!("TypeError: {} callback is not a function", method))
+            })
+    }
 
-        vm.global_this
-            .load_mut(vm)
-            .set_property(BOOLEAN, JSValue::Object(constructor.clone()));
+    /**
+     * Calls an iteration callback as `callback(element, index, array)`, with the optional
+     * `thisArg` of the iteration method as `this`.
+     */
+    fn call_callback(
+        vm: &mut VM,
+        call: &CallContext,
+        callback: ObjectRef,
+        index: usize,
+        element: JSValue,
+    ) -> Result<JSValue, EngineError> {
+        let this = call
+            .arg(1)
+            .and_then(|this| this.try_as_object())
+            .unwrap_or(vm.global_this);
+
+        vm.call_function(
+            callback,
+            this,
+            vec![
+                element,
+                JSValue::Number(index as f64),
+                JSValue::Object(call.this),
+            ],
+        )
     }
-}
 
-impl BooleanClass {
-    pub fn new() -> impl JSModule {
-        Self {}
+    pub fn map(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let callback = Self::callback(vm, &call, "map")?;
+        let mut result = vec![];
+
+        for (index, element) in Self::elements(vm, call.this)?.into_iter().enumerate() {
+            result.push(Self::call_callback(vm, &call, callback, index, element)?);
+        }
+
+        Ok(JSValue::Object(Self::from_elements(vm, result)))
     }
 
-    pub fn js_value_to_bool(value: &JSValue) -> bool {
-        match value {
-            JSValue::Undefined => false,
-            JSValue::Boolean(b) => *b,
-            JSValue::Number(n) => !n.is_nan() && *n != 0.0,
-            JSValue::String(s) => !s.is_empty(),
-            JSValue::Object(_) => true,
+    pub fn filter(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let callback = Self::callback(vm, &call, "filter")?;
+        let mut result = vec![];
+
+        for (index, element) in Self::elements(vm, call.this)?.into_iter().enumerate() {
+            if Self::call_callback(vm, &call, callback, index, element.clone())?.is_truthy() {
+                result.push(element);
+            }
         }
+
+        Ok(JSValue::Object(Self::from_elements(vm, result)))
     }
 
-    pub fn boolean_constructor_fn(_vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
-        Ok(call
-            .arg(0)
-            .map(Self::js_value_to_bool)
-            .map(JSValue::Boolean)
-            .unwrap_or_else(|| JSValue::Boolean(false)))
+    pub fn for_each(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let callback = Self::callback(vm, &call, "forEach")?;
+
+        for (index, element) in Self::elements(vm, call.this)?.into_iter().enumerate() {
+            Self::call_callback(vm, &call, callback, index, element)?;
+        }
+
+        Ok(JSValue::Undefined)
+    }
+
+    pub fn some(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let callback = Self::callback(vm, &call, "some")?;
+
+        for (index, element) in Self::elements(vm, call.this)?.into_iter().enumerate() {
+            if Self::call_callback(vm, &call, callback, index, element)?.is_truthy() {
+                return Ok(JSValue::Boolean(true));
+            }
+        }
+
+        Ok(JSValue::Boolean(false))
+    }
+
+    pub fn every(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let callback = Self::callback(vm, &call, "every")?;
+
+        for (index, element) in Self::elements(vm, call.this)?.into_iter().enumerate() {
+            if !Self::call_callback(vm, &call, callback, index, element)?.is_truthy() {
+                return Ok(JSValue::Boolean(false));
+            }
+        }
+
+        Ok(JSValue::Boolean(true))
+    }
+
+    pub fn find(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let callback = Self::callback(vm, &call, "find")?;
+
+        for (index, element) in Self::elements(vm, call.this)?.into_iter().enumerate() {
+            if Self::call_callback(vm, &call, callback, index, element.clone())?.is_truthy() {
+                return Ok(element);
+            }
+        }
+
+        Ok(JSValue::Undefined)
+    }
+
+    pub fn find_last(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let callback = Self::callback(vm, &call, "findLast")?;
+
+        Ok(Self::find_last_match(vm, &call, callback)?
+            .map(|(_, element)| element)
+            .unwrap_or(JSValue::Undefined))
+    }
+
+    pub fn find_last_index(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let callback = Self::callback(vm, &call, "findLastIndex")?;
+
+        Ok(JSValue::Number(
+            Self::find_last_match(vm, &call, callback)?
+                .map(|(index, _)| index as f64)
+                .unwrap_or(-1.0),
+        ))
+    }
+
+    /**
+     * The last `(index, element)` the callback accepts, scanning from the end.
+     */
+    fn find_last_match(
+        vm: &mut VM,
+        call: &CallContext,
+        callback: ObjectRef,
+    ) -> Result<Option<(usize, JSValue)>, EngineError> {
+        for (index, element) in Self::elements(vm, call.this)?.into_iter().enumerate().rev() {
+            if Self::call_callback(vm, call, callback, index, element.clone())?.is_truthy() {
+                return Ok(Some((index, element)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn reduce(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        Self::reduce_in_order(vm, call, "reduce", false)
+    }
+
+    pub fn reduce_right(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        Self::reduce_in_order(vm, call, "reduceRight", true)
+    }
+
+    /**
+     * Folds the elements as `reducer(accumulator, element, index, array)`, from the end
+     * when `from_end`. Without an initial value the first visited element seeds it.
+     */
+    fn reduce_in_order(
+        vm: &mut VM,
+        call: CallContext,
+        method: &str,
+        from_end: bool,
+    ) -> Result<JSValue, EngineError> {
+        let reducer = Self::callback(vm, &call, method)?;
+
+        let mut elements: Vec<(usize, JSValue)> = Self::elements(vm, call.this)?
+            .into_iter()
+            .enumerate()
+            .collect();
+
+        if from_end {
+            elements.reverse();
+        }
+
+        let mut elements = elements.into_iter();
+
+        let mut accumulator = match call.arg(1) {
+            Some(initial) => initial.clone(),
+            None => match elements.next() {
+                Some((_, first)) => first,
+                None => {
+                    return Err(EngineError::js(
+                        "TypeError: Reduce of empty array with no initial value",
+                    ));
+                }
+            },
+        };
+
+        for (index, element) in elements {
+            accumulator = vm.call_function(
+                reducer,
+                vm.global_this,
+                vec![
+                    accumulator,
+                    element,
+                    JSValue::Number(index as f64),
+                    JSValue::Object(call.this),
+                ],
+            )?;
+        }
+
+        Ok(accumulator)
+    }
+
+    pub fn flat_map(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let callback = Self::callback(vm, &call, "flatMap")?;
+        let mut result = vec![];
+
+        for (index, element) in Self::elements(vm, call.this)?.into_iter().enumerate() {
+            let mapped = Self::call_callback(vm, &call, callback, index, element)?;
+
+            if Self::is_array(vm, &mapped)
+                && let Some(inner) = mapped.try_as_object()
+            {
+                result.extend(Self::elements(vm, inner)?);
+            } else {
+                result.push(mapped);
+            }
+        }
+
+        Ok(JSValue::Object(Self::from_elements(vm, result)))
+    }
+
+    /**
+     * ToIntegerOrInfinity of an optional index argument, with negatives counted from `length`.
+     */
+    fn relative_index(
+        vm: &mut VM,
+        value: Option<&JSValue>,
+        length: usize,
+        default: f64,
+    ) -> Result<f64, EngineError> {
+        let index = match value {
+            Some(value) => vm.to_number(value.clone())?,
+            None => default,
+        };
+        let index = if index.is_nan() { 0.0 } else { index.trunc() };
+
+        Ok(if index < 0.0 {
+            length as f64 + index
+        } else {
+            index
+        })
+    }
+
+    pub fn index_of(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let elements = Self::elements(vm, call.this)?;
+        let search = call.arg(0).cloned().unwrap_or(JSValue::Undefined);
+        let from = Self::relative_index(vm, call.arg(1), elements.len(), 0.0)?.max(0.0) as usize;
+
+        let index = elements
+            .iter()
+            .enumerate()
+            .skip(from)
+            .find(|(_, element)| element.strict_equals(&search))
+            .map(|(index, _)| index as f64)
+            .unwrap_or(-1.0);
+
+        Ok(JSValue::Number(index))
+    }
+
+    /**
+     * Unlike `indexOf`, compares with SameValueZero so `[NaN].includes(NaN)` is `true`.
+     */
+    pub fn includes(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let elements = Self::elements(vm, call.this)?;
+        let search = call.arg(0).cloned().unwrap_or(JSValue::Undefined);
+        let from = Self::relative_index(vm, call.arg(1), elements.len(), 0.0)?.max(0.0) as usize;
+
+        let found = elements
+            .iter()
+            .skip(from)
+            .any(|element| element.same_value_zero(&search));
+
+        Ok(JSValue::Boolean(found))
+    }
+
+    pub fn last_index_of(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let elements = Self::elements(vm, call.this)?;
+        let search = call.arg(0).cloned().unwrap_or(JSValue::Undefined);
+        let last = elements.len() as f64 - 1.0;
+        let from = Self::relative_index(vm, call.arg(1), elements.len(), last)?.min(last);
+
+        if from < 0.0 {
+            return Ok(JSValue::Number(-1.0));
+        }
+
+        let index = elements
+            .iter()
+            .enumerate()
+            .take(from as usize + 1)
+            .rev()
+            .find(|(_, element)| element.strict_equals(&search))
+            .map(|(index, _)| index as f64)
+            .unwrap_or(-1.0);
+
+        Ok(JSValue::Number(index))
+    }
+
+    pub fn at(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let length = Self::length(vm, call.this)?;
+        let index = Self::relative_index(vm, call.arg(0), length, 0.0)?;
+
+        if index < 0.0 || index >= length as f64 {
+            return Ok(JSValue::Undefined);
+        }
+
+        Ok(call
+            .this
+            .load(vm)
+            .get_property(&(index as usize).to_string())
+            .unwrap_or(JSValue::Undefined))
+    }
+
+    /**
+     * Stringifies every element and joins them with the separator (`","` by default),
+     * `undefined` elements become empty strings.
+     */
+    pub fn join(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let separator = match call.arg(0) {
+            None | Some(JSValue::Undefined) => ",".to_string(),
+            Some(separator) => vm.to_primitive(separator.clone())?.cast_to_string(vm)?,
+        };
+
+        // `a = [1]; a.push(a)` joins the inner `a` as "", like other engines
+        if vm.stringifying.contains(&call.this) {
+            return Ok(JSValue::String(String::new()));
+        }
+
+        vm.stringifying.push(call.this);
+        let parts = Self::stringify_elements(vm, call.this);
+        vm.stringifying.pop();
+
+        Ok(JSValue::String(parts?.join(&separator)))
+    }
+
+    fn stringify_elements(vm: &mut VM, array: ObjectRef) -> Result<Vec<String>, EngineError> {
+        let mut parts = vec![];
+
+        for element in Self::elements(vm, array)? {
+            parts.push(match element {
+                JSValue::Undefined => String::new(),
+                element => element.cast_to_string(vm)?,
+            });
+        }
+
+        Ok(parts)
+    }
+
+    /**
+     * Delegates to `this.join()`, falling back to `Object.prototype.toString`
+     * when `join` was overwritten with something that isn't callable.
+     */
+    pub fn to_string(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let join = vm.get(call.this, "join")?;
+
+        if let Some(join) = join.try_as_object()
+            && join.try_load(vm)?.call.is_some()
+        {
+            return vm.call_function(join, call.this, vec![]);
+        }
+
+        Ok(JSValue::String(ObjectClass::str_fallback()))
+    }
+
+    pub fn unshift(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let mut elements = call.args;
+        elements.extend(Self::elements(vm, call.this)?);
+
+        let length = elements.len();
+        Self::set_elements(vm, call.this, elements)?;
+
+        Ok(JSValue::Number(length as f64))
+    }
+
+    pub fn keys(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        Ok(JSValue::Object(Self::create_iterator(vm, call.this, "key")))
+    }
+
+    pub fn values(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        Ok(JSValue::Object(Self::create_iterator(
+            vm, call.this, "value",
+        )))
+    }
+
+    pub fn entries(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        Ok(JSValue::Object(Self::create_iterator(
+            vm,
+            call.this,
+            "key+value",
+        )))
+    }
+
+    /**
+     * CreateArrayIterator, `kind` is one of `key`, `value` or `key+value`.
+     */
+    fn create_iterator(vm: &mut VM, array: ObjectRef, kind: &str) -> ObjectRef {
+        let iterator_prototype = Self::prototype(vm)
+            .load(vm)
+            .get_internal_slot(ARRAY_ITERATOR_PROTOTYPE)
+            .and_then(|prototype| prototype.try_as_object())
+            .expect("Called create_iterator before Array init");
+
+        Object::new()
+            .with_prototype(iterator_prototype)
+            .with_internal_slot(ITERATED_OBJECT, JSValue::Object(array))
+            .with_internal_slot(ITERATOR_NEXT_INDEX, JSValue::Number(0.0))
+            .with_internal_slot(ITERATION_KIND, JSValue::string(kind))
+            .alloc(vm)
+    }
+
+    /**
+     * %ArrayIteratorPrototype%.next, yields `{ value, done }` and drops the array once exhausted.
+     */
+    pub fn iterator_next(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let iterator = call.this.load(vm);
+
+        let (Some(kind), Some(index)) = (
+            iterator.get_internal_slot(ITERATION_KIND),
+            iterator
+                .get_internal_slot(ITERATOR_NEXT_INDEX)
+                .and_then(|index| index.try_as_number()),
+        ) else {
+            return Err(EngineError::js(
+                "TypeError: next method called on incompatible receiver",
+            ));
+        };
+
+        let array = iterator
+            .get_internal_slot(ITERATED_OBJECT)
+            .and_then(|array| array.try_as_object());
+
+        let index = index as usize;
+
+        let value = match array {
+            Some(array) if index < Self::length(vm, array)? => {
+                let element = array
+                    .load(vm)
+                    .get_property(&index.to_string())
+                    .unwrap_or(JSValue::Undefined);
+
+                call.this
+                    .load_mut(vm)
+                    .set_internal_slot(ITERATOR_NEXT_INDEX, JSValue::Number(index as f64 + 1.0));
+
+                Some(match kind.try_as_string().as_deref() {
+                    Some("key") => JSValue::Number(index as f64),
+                    Some("value") => element,
+                    _ => JSValue::Object(Self::from_elements(
+                        vm,
+                        vec![JSValue::Number(index as f64), element],
+                    )),
+                })
+            }
+            _ => {
+                call.this
+                    .load_mut(vm)
+                    .set_internal_slot(ITERATED_OBJECT, JSValue::Undefined);
+
+                None
+            }
+        };
+
+        let done = value.is_none();
+
+        // result objects are plain script data, so `value` and `done` stay enumerable
+        let mut result = ObjectClass::create(vm);
+        result
+            .set_property("value", value.unwrap_or(JSValue::Undefined))
+            .set_property("done", JSValue::Boolean(done));
+
+        Ok(JSValue::Object(result.alloc(vm)))
+    }
+}
+
+const BOOLEAN: &str = "Boolean";
+
+#[derive(Default)]
+pub struct BooleanClass {}
+
+impl JSModule for BooleanClass {
+    fn name(&self) -> &str {
+        BOOLEAN
+    }
+
+    fn init(&mut self, vm: &mut VM) {
+        let function_prototype = FunctionClass::prototype(vm);
+
+        let prototype = ObjectClass::create(vm)
+            .with_property(
+                "valueOf",
+                JSValue::native_function(function_prototype, Self::value_of, vm),
+            )
+            .with_property(
+                "toString",
+                JSValue::native_function(function_prototype, Self::to_string, vm),
+            )
+            .alloc(vm);
+
+        let constructor = Object::new()
+            .with_prototype(function_prototype)
+            .with_property(PROTOTYPE, JSValue::from_object_ref(prototype))
+            .with_call_native(Self::boolean_constructor_fn) // Boolean({}) = true, Boolean(0) = false, etc as in JS
+            .with_construct(Self::construct)
+            .alloc(vm);
+
+        prototype.load_mut(vm).define_property(
+            "constructor",
+            JSValue::from_object_ref(constructor),
+            PropertyAttributes::builtin(),
+        );
+
+        vm.global_this
+            .load_mut(vm)
+            .set_property(BOOLEAN, JSValue::Object(constructor));
+    }
+}
+
+impl BooleanClass {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn prototype(vm: &mut VM) -> ObjectRef {
+        vm.global_constructor_prototype(BOOLEAN)
+            .expect("Called prototype before Boolean init")
+    }
+
+    /**
+     * Box a primitive boolean so its methods can be called with it as `this`.
+     */
+    pub fn wrap(vm: &mut VM, value: bool) -> ObjectRef {
+        Object::new()
+            .with_prototype(Self::prototype(vm))
+            .with_primitive_value(JSValue::Boolean(value))
+            .alloc(vm)
+    }
+
+    pub fn boolean_constructor_fn(_vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        Ok(call
+            .arg(0)
+            .map(JSValue::is_truthy)
+            .map(JSValue::Boolean)
+            .unwrap_or_else(|| JSValue::Boolean(false)))
+    }
+
+    /**
+     * `new Boolean(x)`, a wrapper object holding ToBoolean(x).
+     */
+    fn construct(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let this = call.this;
+        let value = Self::boolean_constructor_fn(vm, call)?;
+        this.load_mut(vm).primitive_value = Some(value);
+
+        Ok(JSValue::Object(this))
+    }
+
+    fn this_boolean(vm: &VM, call: &CallContext) -> Result<bool, EngineError> {
+        call.this
+            .load(vm)
+            .primitive_value
+            .as_ref()
+            .and_then(|value| value.try_as_boolean())
+            .ok_or_else(|| EngineError::js("TypeError: this is not a Boolean object"))
+    }
+
+    fn value_of(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        Ok(JSValue::Boolean(Self::this_boolean(vm, &call)?))
+    }
+
+    fn to_string(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let value = Self::this_boolean(vm, &call)?;
+
+        Ok(JSValue::string(if value { "true" } else { "false" }))
+    }
+}
+
+const NUMBER: &str = "Number";
+
+#[derive(Default)]
+pub struct NumberClass {}
+
+impl JSModule for NumberClass {
+    fn name(&self) -> &str {
+        NUMBER
+    }
+
+    fn init(&mut self, vm: &mut VM) {
+        let function_prototype = FunctionClass::prototype(vm);
+
+        let prototype = ObjectClass::create(vm)
+            .with_property(
+                "valueOf",
+                JSValue::native_function(function_prototype, Self::value_of, vm),
+            )
+            .with_property(
+                "toString",
+                JSValue::native_function(function_prototype, Self::to_string, vm),
+            )
+            .alloc(vm);
+
+        let constructor = Object::new()
+            .with_prototype(function_prototype)
+            .with_property(PROTOTYPE, JSValue::from_object_ref(prototype))
+            .with_call_native(Self::number_constructor_fn)
+            .with_construct(Self::construct)
+            .alloc(vm);
+
+        // read-only like the global `NaN` and `Infinity`
+        let constants = [
+            ("MAX_SAFE_INTEGER", 9007199254740991.0),
+            ("MIN_SAFE_INTEGER", -9007199254740991.0),
+            ("EPSILON", f64::EPSILON),
+            ("POSITIVE_INFINITY", f64::INFINITY),
+            ("NEGATIVE_INFINITY", f64::NEG_INFINITY),
+            ("NaN", f64::NAN),
+        ];
+
+        for (name, value) in constants {
+            constructor.load_mut(vm).define_property(
+                name,
+                JSValue::Number(value),
+                PropertyAttributes::default(),
+            );
+        }
+
+        // `Number.parseInt` and the global `parseInt` are the same function
+        let parse_int = JSValue::native_function(function_prototype, Self::parse_int, vm);
+        constructor.load_mut(vm).define_property(
+            "parseInt",
+            parse_int.clone(),
+            PropertyAttributes::builtin(),
+        );
+
+        prototype.load_mut(vm).define_property(
+            "constructor",
+            JSValue::from_object_ref(constructor),
+            PropertyAttributes::builtin(),
+        );
+
+        vm.global_this
+            .load_mut(vm)
+            .set_property(NUMBER, JSValue::from_object_ref(constructor))
+            .set_property("parseInt", parse_int);
+    }
+}
+
+impl NumberClass {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn prototype(vm: &mut VM) -> ObjectRef {
+        vm.global_constructor_prototype(NUMBER)
+            .expect("Called prototype before Number init")
+    }
+
+    /**
+     * Box a primitive number so its methods can be called with it as `this`.
+     */
+    pub fn wrap(vm: &mut VM, value: f64) -> ObjectRef {
+        Object::new()
+            .with_prototype(Self::prototype(vm))
+            .with_primitive_value(JSValue::Number(value))
+            .alloc(vm)
+    }
+
+    /**
+     * `Number(x)` coerces with ToNumber, `Number()` is `0`.
+     */
+    pub fn number_constructor_fn(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let number = match call.arg(0) {
+            Some(value) => vm.to_number(value.clone())?,
+            None => 0.0,
+        };
+
+        Ok(JSValue::Number(number))
+    }
+
+    fn construct(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let this = call.this;
+        let value = Self::number_constructor_fn(vm, call)?;
+        this.load_mut(vm).primitive_value = Some(value);
+
+        Ok(JSValue::Object(this))
+    }
+
+    /**
+     * `parseInt(string, radix)`: parses the longest run of leading digits, ignoring the
+     * rest. A missing or `0` radix means 10, or 16 after a `0x`/`0X` prefix. Input
+     * without leading digits, or a radix outside 2..=36, gives `NaN`.
+     */
+    pub fn parse_int(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let string = call
+            .arg(0)
+            .cloned()
+            .unwrap_or(JSValue::Undefined)
+            .cast_to_string(vm)?;
+        let radix = match call.arg(1) {
+            Some(radix) => vm.to_number(radix.clone())?,
+            None => 0.0,
+        };
+        let radix = if radix.is_finite() {
+            radix.trunc()
+        } else {
+            0.0
+        };
+
+        let trimmed = string.trim_start();
+        let (sign, mut digits) = match trimmed.strip_prefix('-') {
+            Some(rest) => (-1.0, rest),
+            None => (1.0, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+
+        // only a default radix, or an explicit 16, accepts the `0x` prefix
+        let (mut radix, strip_prefix) = match radix {
+            0.0 => (10, true),
+            radix if (2.0..=36.0).contains(&radix) => (radix as u32, radix == 16.0),
+            _ => return Ok(JSValue::Number(f64::NAN)),
+        };
+
+        if strip_prefix
+            && let Some(rest) = digits
+                .strip_prefix("0x")
+                .or_else(|| digits.strip_prefix("0X"))
+        {
+            digits = rest;
+            radix = 16;
+        }
+
+        let mut number = None;
+
+        for character in digits.chars() {
+            let Some(digit) = character.to_digit(radix) else {
+                break;
+            };
+
+            number = Some(number.unwrap_or(0.0) * radix as f64 + digit as f64);
+        }
+
+        Ok(JSValue::Number(
+            number.map_or(f64::NAN, |number| sign * number),
+        ))
+    }
+
+    fn this_number(vm: &VM, call: &CallContext) -> Result<f64, EngineError> {
+        call.this
+            .load(vm)
+            .primitive_value
+            .as_ref()
+            .and_then(|value| value.try_as_number())
+            .ok_or_else(|| EngineError::js("TypeError: this is not a Number object"))
+    }
+
+    fn value_of(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        Ok(JSValue::Number(Self::this_number(vm, &call)?))
+    }
+
+    fn to_string(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        Ok(JSValue::String(number_to_string(Self::this_number(
+            vm, &call,
+        )?)))
+    }
+}
+
+/**
+ * xorshift64* generator backing `Math.random`.
+ * Seedable through `VmOptions::rng_seed` so scripts can be replayed deterministically.
+ */
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn from_seed(seed: u64) -> Self {
+        // xorshift gets stuck on a zero state
+        Self {
+            state: if seed == 0 {
+                0x9E37_79B9_7F4A_7C15
+            } else {
+                seed
+            },
+        }
+    }
+
+    pub fn from_entropy() -> Self {
+        Self::from_seed(RandomState::new().build_hasher().finish())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform float in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+const MATH: &str = "Math";
+
+#[derive(Default)]
+pub struct MathClass {}
+
+impl JSModule for MathClass {
+    fn name(&self) -> &str {
+        MATH
+    }
+
+    fn init(&mut self, vm: &mut VM) {
+        let object = ObjectClass::create(vm)
+            .with_property(
+                "random",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::random, vm),
+            )
+            .with_property(
+                "max",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::max, vm),
+            )
+            .with_property(
+                "min",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::min, vm),
+            )
+            .alloc(vm);
+
+        vm.global_this
+            .load_mut(vm)
+            .set_property(MATH, JSValue::Object(object));
+    }
+}
+
+impl MathClass {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn random(vm: &mut VM, _call: CallContext) -> Result<JSValue, EngineError> {
+        Ok(JSValue::Number(vm.rng.next_f64()))
+    }
+
+    /**
+     * Folds every argument, coerced with ToNumber, into `initial` with `pick`.
+     * All arguments are coerced even after a `NaN`, which then wins, as in JS.
+     */
+    fn fold_numbers(
+        vm: &mut VM,
+        call: CallContext,
+        initial: f64,
+        pick: fn(f64, f64) -> f64,
+    ) -> Result<JSValue, EngineError> {
+        let mut result = initial;
+
+        for arg in call.args {
+            let number = vm.to_number(arg)?;
+
+            result = if result.is_nan() || number.is_nan() {
+                f64::NAN
+            } else {
+                pick(result, number)
+            };
+        }
+
+        Ok(JSValue::Number(result))
+    }
+
+    /**
+     * `Math.max()` is `-Infinity`; `+0` is considered larger than `-0`.
+     */
+    pub fn max(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        Self::fold_numbers(vm, call, f64::NEG_INFINITY, |a, b| {
+            if a == b && a == 0.0 {
+                if a.is_sign_positive() { a } else { b }
+            } else {
+                a.max(b)
+            }
+        })
+    }
+
+    /**
+     * `Math.min()` is `Infinity`; `-0` is considered smaller than `+0`.
+     */
+    pub fn min(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        Self::fold_numbers(vm, call, f64::INFINITY, |a, b| {
+            if a == b && a == 0.0 {
+                if a.is_sign_negative() { a } else { b }
+            } else {
+                a.min(b)
+            }
+        })
+    }
+}
+
+/**
+ * Non-standard helpers for tests and examples. Nothing here is registered by default,
+ * see `VmBuilder::with_test_helpers`.
+ */
+pub struct TestHelpers {}
+
+impl TestHelpers {
+    /**
+     * `__range(n)`: the array `[0, 1, ..., n - 1]`. A missing, `NaN` or negative `n`
+     * gives `[]`; `n` past `MAX_ELEMENTS` is a `RangeError`.
+     */
+    pub fn range(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let length = match call.arg(0) {
+            Some(length) => vm.to_number(length.clone())?,
+            None => 0.0,
+        };
+        let length = if length.is_nan() {
+            0.0
+        } else {
+            length.trunc().max(0.0)
+        };
+
+        if length > MAX_ELEMENTS as f64 {
+            return Err(EngineError::js("RangeError: Array is too large"));
+        }
+
+        let elements = (0..length as usize)
+            .map(|index| JSValue::Number(index as f64))
+            .collect();
+
+        Ok(JSValue::Object(ArrayClass::from_elements(vm, elements)))
+    }
+}
+
+const CONSOLE: &str = "console";
+
+#[derive(Default)]
+pub struct ConsoleClass {}
+
+impl JSModule for ConsoleClass {
+    fn name(&self) -> &str {
+        CONSOLE
+    }
+
+    fn init(&mut self, vm: &mut VM) {
+        let object = ObjectClass::create(vm)
+            .with_property(
+                "log",
+                JSValue::native_function(FunctionClass::prototype(vm), Self::log, vm),
+            )
+            .alloc(vm);
+
+        vm.global_this
+            .load_mut(vm)
+            .set_property(CONSOLE, JSValue::Object(object));
+    }
+}
+
+impl ConsoleClass {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /**
+     * Writes the stringified arguments, separated by spaces, to the VM's output sink.
+     */
+    pub fn log(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let mut parts = vec![];
+
+        for arg in call.args {
+            parts.push(arg.cast_to_string(vm)?);
+        }
+
+        vm.print(&parts.join(" "));
+
+        Ok(JSValue::Undefined)
+    }
+}
+
+const DATE: &str = "Date";
+
+const MS_PER_DAY: f64 = 86_400_000.0;
+
+#[derive(Default)]
+pub struct DateClass {}
+
+impl JSModule for DateClass {
+    fn name(&self) -> &str {
+        DATE
+    }
+
+    fn init(&mut self, vm: &mut VM) {
+        let function_prototype = FunctionClass::prototype(vm);
+
+        let prototype = ObjectClass::create(vm)
+            .with_property(
+                "getTime",
+                JSValue::native_function(function_prototype, Self::get_time, vm),
+            )
+            .with_property(
+                "getFullYear",
+                JSValue::native_function(function_prototype, Self::get_full_year, vm),
+            )
+            .with_property(
+                "toISOString",
+                JSValue::native_function(function_prototype, Self::to_iso_string, vm),
+            )
+            .alloc(vm);
+
+        let constructor = Object::new()
+            .with_prototype(function_prototype)
+            .with_property(PROTOTYPE, JSValue::from_object_ref(prototype))
+            .with_property(
+                "now",
+                JSValue::native_function(function_prototype, Self::now, vm),
+            )
+            .with_construct(Self::construct)
+            .alloc(vm);
+
+        prototype.load_mut(vm).define_property(
+            "constructor",
+            JSValue::from_object_ref(constructor),
+            PropertyAttributes::builtin(),
+        );
+
+        vm.global_this
+            .load_mut(vm)
+            .set_property(DATE, JSValue::from_object_ref(constructor));
+    }
+}
+
+impl DateClass {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn system_now() -> f64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as f64)
+            .unwrap_or(0.0)
+    }
+
+    pub fn now(vm: &mut VM, _call: CallContext) -> Result<JSValue, EngineError> {
+        Ok(JSValue::Number((vm.clock)()))
+    }
+
+    fn construct(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let time = match call.arg(0) {
+            Some(value) => value
+                .try_as_number()
+                .ok_or_else(|| EngineError::js("Date expects a number of milliseconds"))?,
+            None => (vm.clock)(),
+        };
+
+        call.this.load_mut(vm).primitive_value = Some(JSValue::Number(time));
+
+        Ok(JSValue::Object(call.this))
+    }
+
+    fn time_value(vm: &VM, call: &CallContext) -> Result<f64, EngineError> {
+        call.this
+            .load(vm)
+            .primitive_value
+            .as_ref()
+            .and_then(|value| value.try_as_number())
+            .ok_or_else(|| EngineError::js("this is not a Date object"))
+    }
+
+    /**
+     * Split a time value into UTC (year, month, day, hours, minutes, seconds, milliseconds).
+     * Uses the days-to-civil conversion from http://howardhinnant.github.io/date_algorithms.html
+     */
+    fn to_utc_parts(time: f64) -> (i64, i64, i64, i64, i64, i64, i64) {
+        let days = (time / MS_PER_DAY).floor() as i64;
+        let ms_in_day = (time - days as f64 * MS_PER_DAY) as i64;
+
+        let z = days + 719_468;
+        let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+        let day_of_era = z - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        (
+            year,
+            month,
+            day,
+            ms_in_day / 3_600_000,
+            ms_in_day / 60_000 % 60,
+            ms_in_day / 1000 % 60,
+            ms_in_day % 1000,
+        )
+    }
+
+    fn get_time(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        Ok(JSValue::Number(Self::time_value(vm, &call)?))
+    }
+
+    fn get_full_year(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let time = Self::time_value(vm, &call)?;
+
+        if time.is_nan() {
+            return Ok(JSValue::Number(f64::NAN));
+        }
+
+        Ok(JSValue::Number(Self::to_utc_parts(time).0 as f64))
+    }
+
+    fn to_iso_string(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let time = Self::time_value(vm, &call)?;
+
+        if !time.is_finite() {
+            return Err(EngineError::js("Invalid time value"));
+        }
+
+        let (year, month, day, hours, minutes, seconds, milliseconds) = Self::to_utc_parts(time);
+
+        Ok(JSValue::String(format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year, month, day, hours, minutes, seconds, milliseconds
+        )))
+    }
+}
+
+/**
+ * Global `encodeURIComponent`/`decodeURIComponent` functions.
+ */
+#[derive(Default)]
+pub struct UriFunctions {}
+
+impl JSModule for UriFunctions {
+    fn name(&self) -> &str {
+        "URI"
+    }
+
+    fn init(&mut self, vm: &mut VM) {
+        let function_prototype = FunctionClass::prototype(vm);
+
+        let encode = JSValue::native_function(function_prototype, Self::encode_uri_component, vm);
+        let decode = JSValue::native_function(function_prototype, Self::decode_uri_component, vm);
+
+        vm.global_this
+            .load_mut(vm)
+            .set_property("encodeURIComponent", encode)
+            .set_property("decodeURIComponent", decode);
+    }
+}
+
+impl UriFunctions {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    fn is_unreserved(byte: u8) -> bool {
+        byte.is_ascii_alphanumeric() || b"-_.!~*'()".contains(&byte)
+    }
+
+    fn string_arg(vm: &mut VM, call: &CallContext) -> Result<String, EngineError> {
+        call.arg(0)
+            .cloned()
+            .unwrap_or(JSValue::Undefined)
+            .cast_to_string(vm)
+    }
+
+    pub fn encode_uri_component(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let mut encoded = String::new();
+
+        for byte in Self::string_arg(vm, &call)?.bytes() {
+            if Self::is_unreserved(byte) {
+                encoded.push(byte as char);
+            } else {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+
+        Ok(JSValue::String(encoded))
+    }
+
+    pub fn decode_uri_component(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let source = Self::string_arg(vm, &call)?;
+        let malformed = || EngineError::js("URIError: URI malformed");
+
+        let bytes = source.as_bytes();
+        let mut decoded: Vec<u8> = vec![];
+        let mut index = 0;
+
+        while index < bytes.len() {
+            if bytes[index] != b'%' {
+                decoded.push(bytes[index]);
+                index += 1;
+                continue;
+            }
+
+            let hex = bytes
+                .get(index + 1..index + 3)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .ok_or_else(malformed)?;
+
+            let hex = std::str::from_utf8(hex).map_err(|_| malformed())?;
+            decoded.push(u8::from_str_radix(hex, 16).map_err(|_| malformed())?);
+            index += 3;
+        }
+
+        String::from_utf8(decoded)
+            .map(JSValue::String)
+            .map_err(|_| malformed())
+    }
+}
+
+const STRING: &str = "String";
+
+/**
+ * String methods work on UTF-16 code units like JS, so only the Basic Multilingual Plane
+ * maps one code unit to one character.
+ */
+#[derive(Default)]
+pub struct StringClass {}
+
+impl JSModule for StringClass {
+    fn name(&self) -> &str {
+        STRING
+    }
+
+    fn init(&mut self, vm: &mut VM) {
+        let function_prototype = FunctionClass::prototype(vm);
+
+        let prototype = ObjectClass::create(vm)
+            .with_property(
+                "charCodeAt",
+                JSValue::native_function(function_prototype, Self::char_code_at, vm),
+            )
+            .with_property(
+                "at",
+                JSValue::native_function(function_prototype, Self::at, vm),
+            )
+            .with_property(
+                "codePointAt",
+                JSValue::native_function(function_prototype, Self::code_point_at, vm),
+            )
+            .with_property(
+                "search",
+                JSValue::native_function(function_prototype, Self::search, vm),
+            )
+            .with_property(
+                "replaceAll",
+                JSValue::native_function(function_prototype, Self::replace_all, vm),
+            )
+            .with_property(
+                "valueOf",
+                JSValue::native_function(function_prototype, Self::value_of, vm),
+            )
+            .with_property(
+                "toString",
+                JSValue::native_function(function_prototype, Self::value_of, vm),
+            )
+            .alloc(vm);
+
+        let constructor = Object::new()
+            .with_prototype(function_prototype)
+            .with_property(PROTOTYPE, JSValue::from_object_ref(prototype))
+            .with_property(
+                "fromCharCode",
+                JSValue::native_function(function_prototype, Self::from_char_code, vm),
+            )
+            .with_call_native(Self::string_constructor_fn)
+            .with_construct(Self::construct)
+            .alloc(vm);
+
+        prototype.load_mut(vm).define_property(
+            "constructor",
+            JSValue::from_object_ref(constructor),
+            PropertyAttributes::builtin(),
+        );
+
+        vm.global_this
+            .load_mut(vm)
+            .set_property(STRING, JSValue::from_object_ref(constructor));
+    }
+}
+
+impl StringClass {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn prototype(vm: &mut VM) -> ObjectRef {
+        vm.global_constructor_prototype(STRING)
+            .expect("Called prototype before String init")
+    }
+
+    /**
+     * Box a primitive string so its methods can be called with it as `this`.
+     */
+    pub fn wrap(vm: &mut VM, value: String) -> ObjectRef {
+        let length = value.encode_utf16().count();
+
+        Object::new()
+            .with_prototype(Self::prototype(vm))
+            .with_property("length", JSValue::Number(length as f64))
+            .with_primitive_value(JSValue::String(value))
+            .alloc(vm)
+    }
+
+    /**
+     * `String(x)` coerces with ToString, `String()` is `""`.
+     */
+    pub fn string_constructor_fn(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let string = match call.arg(0) {
+            Some(value) => value.clone().cast_to_string(vm)?,
+            None => String::new(),
+        };
+
+        Ok(JSValue::String(string))
+    }
+
+    fn construct(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let this = call.this;
+        let value = Self::string_constructor_fn(vm, call)?;
+        let length = value
+            .try_as_string()
+            .unwrap_or_default()
+            .encode_utf16()
+            .count();
+
+        this.load_mut(vm)
+            .set_property("length", JSValue::Number(length as f64))
+            .primitive_value = Some(value);
+
+        Ok(JSValue::Object(this))
+    }
+
+    /**
+     * `valueOf` and `toString` both unwrap the primitive, only String objects are accepted.
+     */
+    fn value_of(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        match call.this.load(vm).primitive_value.clone() {
+            Some(JSValue::String(string)) => Ok(JSValue::String(string)),
+            _ => Err(EngineError::js("TypeError: this is not a String object")),
+        }
+    }
+
+    fn this_string(vm: &mut VM, call: &CallContext) -> Result<String, EngineError> {
+        match call.this.load(vm).primitive_value.clone() {
+            Some(JSValue::String(string)) => Ok(string),
+            _ => JSValue::Object(call.this).cast_to_string(vm),
+        }
+    }
+
+    pub fn from_char_code(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let mut code_units: Vec<u16> = vec![];
+
+        for arg in call.args {
+            let code = vm.to_number(arg)?;
+
+            code_units.push(if code.is_finite() {
+                code as i64 as u16
+            } else {
+                0
+            });
+        }
+
+        Ok(JSValue::String(String::from_utf16_lossy(&code_units)))
+    }
+
+    /**
+     * Like `Array.prototype.at`, over Unicode scalar values rather than UTF-16 units.
+     */
+    pub fn at(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let chars: Vec<char> = Self::this_string(vm, &call)?.chars().collect();
+        let index = ArrayClass::relative_index(vm, call.arg(0), chars.len(), 0.0)?;
+
+        if index < 0.0 || index >= chars.len() as f64 {
+            return Ok(JSValue::Undefined);
+        }
+
+        Ok(JSValue::String(chars[index as usize].to_string()))
+    }
+
+    /**
+     * Stand-in for `search` while there are no regular expressions: the argument is
+     * matched as a plain substring. Returns the UTF-16 index of the first match or `-1`.
+     */
+    pub fn search(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let string = Self::this_string(vm, &call)?;
+        let needle = match call.arg(0) {
+            Some(arg) => arg.clone().cast_to_string(vm)?,
+            None => String::new(),
+        };
+
+        let index = match string.find(&needle) {
+            Some(offset) => string[..offset].encode_utf16().count() as f64,
+            None => -1.0,
+        };
+
+        Ok(JSValue::Number(index))
+    }
+
+    /**
+     * Replaces every occurrence of `search`, both arguments coerced to strings. An empty
+     * `search` inserts the replacement around every character, as in JS. `$` patterns
+     * in the replacement are not expanded.
+     */
+    pub fn replace_all(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let string = Self::this_string(vm, &call)?;
+        let search = call
+            .arg(0)
+            .cloned()
+            .unwrap_or(JSValue::Undefined)
+            .cast_to_string(vm)?;
+        let replacement = call
+            .arg(1)
+            .cloned()
+            .unwrap_or(JSValue::Undefined)
+            .cast_to_string(vm)?;
+
+        Ok(JSValue::String(string.replace(&search, &replacement)))
+    }
+
+    /**
+     * The Unicode scalar value at `index`, counted in scalar values; no negative indexing.
+     */
+    pub fn code_point_at(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let string = Self::this_string(vm, &call)?;
+        let index = match call.arg(0) {
+            Some(arg) => vm.to_number(arg.clone())?,
+            None => 0.0,
+        };
+        let index = if index.is_nan() { 0.0 } else { index.trunc() };
+
+        if index < 0.0 {
+            return Ok(JSValue::Undefined);
+        }
+
+        Ok(string
+            .chars()
+            .nth(index as usize)
+            .map(|character| JSValue::Number(character as u32 as f64))
+            .unwrap_or(JSValue::Undefined))
+    }
+
+    pub fn char_code_at(vm: &mut VM, call: CallContext) -> Result<JSValue, EngineError> {
+        let string = Self::this_string(vm, &call)?;
+
+        let index = match call.arg(0) {
+            Some(arg) => vm.to_number(arg.clone())?,
+            None => 0.0,
+        };
+        let index = if index.is_nan() { 0.0 } else { index };
+
+        if index < 0.0 {
+            return Ok(JSValue::Number(f64::NAN));
+        }
+
+        Ok(string
+            .encode_utf16()
+            .nth(index as usize)
+            .map(|code| JSValue::Number(code as f64))
+            .unwrap_or(JSValue::Number(f64::NAN)))
     }
 }
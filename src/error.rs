@@ -1,50 +1,123 @@
-use std::backtrace::Backtrace;
+use std::{backtrace::Backtrace, cell::Cell};
+
+thread_local! {
+    static CAPTURE_BACKTRACES: Cell<Option<bool>> = const { Cell::new(None) };
+}
+
+/**
+ * Makes errors created on this thread capture a backtrace always (`true`) or never
+ * (`false`) instead of following `RUST_BACKTRACE`, until the returned guard is dropped.
+ * Capturing is expensive, so scripts that throw in a loop should leave it off.
+ */
+pub fn capture_backtraces(capture: bool) -> BacktraceGuard {
+    let previous = CAPTURE_BACKTRACES.with(|cell| cell.replace(Some(capture)));
+
+    BacktraceGuard { previous }
+}
+
+/**
+ * Restores the backtrace setting that was active before `capture_backtraces`.
+ */
+pub struct BacktraceGuard {
+    previous: Option<bool>,
+}
+
+impl Drop for BacktraceGuard {
+    fn drop(&mut self) {
+        CAPTURE_BACKTRACES.with(|cell| cell.set(self.previous));
+    }
+}
+
+fn capture_backtrace() -> Backtrace {
+    match CAPTURE_BACKTRACES.with(Cell::get) {
+        Some(true) => Backtrace::force_capture(),
+        Some(false) => Backtrace::disabled(),
+        None => Backtrace::capture(),
+    }
+}
+
+/**
+ * Category of an `EngineError`, for embedders that need to branch on it.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Lexer,
+    Ast,
+    Runtime,
+}
+
+/**
+ * Character offsets into the source, `start..end`.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
 
 #[derive(Debug)]
 pub struct ASTError {
     pub message: String,
     pub backtrace: Backtrace,
+    pub span: Option<Span>,
 }
 
 #[derive(Debug)]
 pub struct JSError {
     pub message: String,
     pub backtrace: Backtrace,
+    pub span: Option<Span>,
 }
 
 #[derive(Debug)]
 pub struct LexerError {
     pub message: String,
     pub backtrace: Backtrace,
+    pub span: Option<Span>,
 }
 
+/// Variants are boxed to keep `Result<JSValue, EngineError>` small, since every
+/// level of a deeply nested expression holds a few of them on the stack.
 #[derive(Debug)]
 pub enum EngineError {
-    Ast(ASTError),
-    JS(JSError),
-    Lexer(LexerError),
+    Ast(Box<ASTError>),
+    JS(Box<JSError>),
+    Lexer(Box<LexerError>),
 }
 
 impl EngineError {
     pub fn ast<T: ToString>(message: T) -> Self {
-        EngineError::Ast(ASTError {
+        EngineError::Ast(Box::new(ASTError {
             message: message.to_string(),
-            backtrace: Backtrace::capture(),
-        })
+            backtrace: capture_backtrace(),
+            span: None,
+        }))
     }
 
     pub fn js<T: ToString>(message: T) -> Self {
-        EngineError::JS(JSError {
+        EngineError::JS(Box::new(JSError {
             message: message.to_string(),
-            backtrace: Backtrace::capture(),
-        })
+            backtrace: capture_backtrace(),
+            span: None,
+        }))
     }
 
     pub fn lexer<T: ToString>(message: T) -> Self {
-        EngineError::Lexer(LexerError {
+        EngineError::Lexer(Box::new(LexerError {
             message: message.to_string(),
-            backtrace: Backtrace::capture(),
-        })
+            backtrace: capture_backtrace(),
+            span: None,
+        }))
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        match &mut self {
+            EngineError::Ast(err) => err.span = Some(span),
+            EngineError::JS(err) => err.span = Some(span),
+            EngineError::Lexer(err) => err.span = Some(span),
+        }
+
+        self
     }
 
     pub fn message(&self) -> &str {
@@ -54,4 +127,54 @@ impl EngineError {
             EngineError::Lexer(err) => &err.message,
         }
     }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            EngineError::Ast(_) => ErrorKind::Ast,
+            EngineError::JS(_) => ErrorKind::Runtime,
+            EngineError::Lexer(_) => ErrorKind::Lexer,
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            EngineError::Ast(err) => err.span,
+            EngineError::JS(err) => err.span,
+            EngineError::Lexer(err) => err.span,
+        }
+    }
+
+    pub fn backtrace(&self) -> &Backtrace {
+        match self {
+            EngineError::Ast(err) => &err.backtrace,
+            EngineError::JS(err) => &err.backtrace,
+            EngineError::Lexer(err) => &err.backtrace,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::backtrace::BacktraceStatus;
+
+    use crate::error::{CAPTURE_BACKTRACES, EngineError, capture_backtraces};
+
+    #[test]
+    fn test_backtrace_capture_toggle() {
+        let disabled = capture_backtraces(false);
+        let error = EngineError::js("no trace");
+        assert_eq!(error.backtrace().status(), BacktraceStatus::Disabled);
+
+        {
+            let _enabled = capture_backtraces(true);
+            let error = EngineError::ast("traced");
+            assert_eq!(error.backtrace().status(), BacktraceStatus::Captured);
+        }
+
+        let error = EngineError::js("no trace again");
+        assert_eq!(error.backtrace().status(), BacktraceStatus::Disabled);
+
+        drop(disabled);
+        assert_eq!(CAPTURE_BACKTRACES.with(|cell| cell.get()), None);
+    }
 }
@@ -0,0 +1,73 @@
+//! Object storage backing the VM.
+//! The VM only talks to the `Heap` trait, so embedders can plug in an arena or slab allocator.
+
+use crate::vm::Object;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeapStats {
+    pub live: usize,
+    pub free: usize,
+    pub total: usize,
+}
+
+pub trait Heap {
+    /**
+     * Stores `object` and returns its address. Addresses must stay valid until `free`.
+     */
+    fn alloc(&mut self, object: Object) -> usize;
+    fn get(&self, address: usize) -> Option<&Object>;
+    fn get_mut(&mut self, address: usize) -> Option<&mut Object>;
+    fn free(&mut self, address: usize);
+    fn stats(&self) -> HeapStats;
+}
+
+/**
+ * Default heap: a `Vec` of slots with a free list for reusing freed addresses.
+ */
+#[derive(Default)]
+pub struct VecHeap {
+    slots: Vec<Option<Object>>,
+    free_list: Vec<usize>,
+}
+
+impl VecHeap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Heap for VecHeap {
+    fn alloc(&mut self, object: Object) -> usize {
+        if let Some(free_address) = self.free_list.pop() {
+            self.slots[free_address] = Some(object);
+            return free_address;
+        }
+
+        self.slots.push(Some(object));
+        self.slots.len() - 1
+    }
+
+    fn get(&self, address: usize) -> Option<&Object> {
+        self.slots.get(address).and_then(|slot| slot.as_ref())
+    }
+
+    fn get_mut(&mut self, address: usize) -> Option<&mut Object> {
+        self.slots.get_mut(address).and_then(|slot| slot.as_mut())
+    }
+
+    fn free(&mut self, address: usize) {
+        if let Some(slot) = self.slots.get_mut(address)
+            && slot.take().is_some()
+        {
+            self.free_list.push(address);
+        }
+    }
+
+    fn stats(&self) -> HeapStats {
+        HeapStats {
+            live: self.slots.iter().filter(|slot| slot.is_some()).count(),
+            free: self.free_list.len(),
+            total: self.slots.len(),
+        }
+    }
+}
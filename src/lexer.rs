@@ -1,4 +1,4 @@
-use crate::error::EngineError;
+use crate::error::{EngineError, Span};
 
 #[derive(Debug, Clone)]
 pub struct IdentifierToken {
@@ -7,19 +7,36 @@ pub struct IdentifierToken {
 
 #[derive(Debug, Clone)]
 pub struct NumericLiteralToken {
-    pub value: f32,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct StringLiteralToken {
+    pub value: String,
 }
 
 #[derive(Debug, Clone)]
 pub enum Token {
     Identifier(IdentifierToken),
     NumericLiteral(NumericLiteralToken),
+    StringLiteral(StringLiteralToken),
     Equal,
     LetKeyword,
     IfKeyword,
     ElseKeyword,
     FunctionKeyword,
     ReturnKeyword,
+    NewKeyword,
+    ForKeyword,
+    BreakKeyword,
+    ContinueKeyword,
+    SwitchKeyword,
+    CaseKeyword,
+    DefaultKeyword,
+    TypeofKeyword,
+    TryKeyword,
+    CatchKeyword,
+    FinallyKeyword,
     Semicolon,
     Slash,
     Plus,
@@ -30,12 +47,20 @@ pub enum Token {
     GreaterThanEqual,
     AndAnd,
     OrOr,
+    /// Logical assignments `&&=`, `||=` and `??=`.
+    AndAndEqual,
+    OrOrEqual,
+    QuestionQuestionEqual,
     EqualEqual,
     EqualEqualEqual,
     BangEqual,
     BangEqualEqual,
     Arrow,
+    QuestionDot,
+    Question,
     Star,
+    StarStar,
+    Percent,
     LBrace,
     RBrace,
     LBracket,
@@ -46,6 +71,8 @@ pub enum Token {
     RParen,
     End,
     Dot,
+    /// `...` of a spread element.
+    DotDotDot,
 }
 
 impl Token {
@@ -64,6 +91,23 @@ impl Token {
             None
         }
     }
+
+    pub fn try_as_string_literal(&self) -> Option<&StringLiteralToken> {
+        if let Token::StringLiteral(t) = self {
+            Some(t)
+        } else {
+            None
+        }
+    }
+}
+
+/**
+ * A token together with the character range it was read from.
+ */
+#[derive(Debug, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
 }
 
 pub struct Lexer {
@@ -82,23 +126,66 @@ impl Lexer {
         c
     }
 
-    fn skip_whitespace(&mut self) {
+    /**
+     * Skips whitespace, `// line` and `/* block */` comments between tokens.
+     */
+    fn skip_whitespace(&mut self) -> Result<(), EngineError> {
         loop {
-            if let Some(c) = self.peek()
-                && c.is_whitespace()
-            {
-                self.advance();
-            } else {
-                break;
+            match (self.peek(), self.source.get(self.pos + 1)) {
+                (Some(c), _) if c.is_whitespace() => {
+                    self.advance();
+                }
+                (Some('/'), Some('/')) => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.advance();
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    let start = self.pos;
+                    self.pos += 2;
+
+                    loop {
+                        match self.advance() {
+                            Some('*') if self.match_char('/') => break,
+                            Some(_) => {}
+                            None => {
+                                return Err(EngineError::lexer("Unterminated block comment")
+                                    .with_span(Span {
+                                        start,
+                                        end: self.source.len(),
+                                    }));
+                            }
+                        }
+                    }
+                }
+                _ => return Ok(()),
             }
         }
     }
 
+    /**
+     * Approximates JS `ID_Start`: any Unicode letter plus `$` and `_`.
+     */
+    fn is_identifier_start(character: char) -> bool {
+        character.is_alphabetic() || character == '$' || character == '_'
+    }
+
+    /**
+     * Approximates JS `ID_Continue`, including the ZWNJ/ZWJ joiners.
+     */
+    fn is_identifier_part(character: char) -> bool {
+        character.is_alphanumeric()
+            || character == '$'
+            || character == '_'
+            || character == '\u{200C}'
+            || character == '\u{200D}'
+    }
+
     fn parse_identifier(&mut self) -> Token {
         let mut name = String::new();
 
         while let Some(character) = self.peek()
-            && character.is_alphanumeric()
+            && Self::is_identifier_part(character)
         {
             name.push(character);
             self.advance();
@@ -110,6 +197,17 @@ impl Lexer {
             "return" => Token::ReturnKeyword,
             "if" => Token::IfKeyword,
             "else" => Token::ElseKeyword,
+            "new" => Token::NewKeyword,
+            "for" => Token::ForKeyword,
+            "break" => Token::BreakKeyword,
+            "continue" => Token::ContinueKeyword,
+            "switch" => Token::SwitchKeyword,
+            "case" => Token::CaseKeyword,
+            "default" => Token::DefaultKeyword,
+            "typeof" => Token::TypeofKeyword,
+            "try" => Token::TryKeyword,
+            "catch" => Token::CatchKeyword,
+            "finally" => Token::FinallyKeyword,
             _ => Token::Identifier(IdentifierToken { name }),
         }
     }
@@ -118,19 +216,66 @@ impl Lexer {
         let mut str_number = String::new();
 
         while let Some(character) = self.peek()
-            && (character.is_digit(10) || (character == '.'))
+            && (character.is_ascii_digit() || (character == '.'))
         {
             str_number.push(character);
             self.advance();
         }
 
         let parsed = str_number
-            .parse::<f32>()
-            .map_err(|_| EngineError::lexer(format!("Failed to parse {} into f32", str_number)))?;
+            .parse::<f64>()
+            .map_err(|_| EngineError::lexer(format!("Failed to parse {} into f64", str_number)))?;
 
         Ok(Token::NumericLiteral(NumericLiteralToken { value: parsed }))
     }
 
+    fn parse_string_literal(&mut self) -> Result<Token, EngineError> {
+        let quote = self.advance();
+        let mut value = String::new();
+
+        loop {
+            let character = self
+                .advance()
+                .ok_or_else(|| EngineError::lexer("Unterminated string literal"))?;
+
+            if Some(character) == quote {
+                break;
+            }
+
+            if character == '\\' {
+                let escaped = self
+                    .advance()
+                    .ok_or_else(|| EngineError::lexer("Unterminated string literal"))?;
+
+                value.push(match escaped {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    '0' => '\0',
+                    other => other,
+                });
+
+                continue;
+            }
+
+            value.push(character);
+        }
+
+        Ok(Token::StringLiteral(StringLiteralToken { value }))
+    }
+
+    /**
+     * Whether the previous character ends an operand, so a `.` right after it is member access.
+     */
+    fn follows_value(&self) -> bool {
+        self.pos
+            .checked_sub(1)
+            .and_then(|index| self.source.get(index))
+            .is_some_and(|&character| {
+                Self::is_identifier_part(character) || character == ')' || character == ']'
+            })
+    }
+
     fn match_char(&mut self, expected: char) -> bool {
         let matches = self.peek().map(|char| char == expected).unwrap_or(false);
         if matches {
@@ -142,8 +287,18 @@ impl Lexer {
     fn next_token(&mut self) -> Result<Token, EngineError> {
         self.peek()
             .map(|character| match character {
-                character if character.is_alphabetic() => Ok(self.parse_identifier()),
-                character if character.is_digit(10) => self.parse_numeric_literal(),
+                character if Self::is_identifier_start(character) => Ok(self.parse_identifier()),
+                character if character.is_ascii_digit() => self.parse_numeric_literal(),
+                // `.5` is a number, but `obj.prop` and `obj.123` are member accesses
+                '.' if self
+                    .source
+                    .get(self.pos + 1)
+                    .is_some_and(|character| character.is_ascii_digit())
+                    && !self.follows_value() =>
+                {
+                    self.parse_numeric_literal()
+                }
+                '"' | '\'' => self.parse_string_literal(),
                 ';' => {
                     self.advance();
                     Ok(Token::Semicolon)
@@ -158,6 +313,10 @@ impl Lexer {
                 }
                 '.' => {
                     self.advance();
+                    if self.peek() == Some('.') && self.source.get(self.pos + 1) == Some(&'.') {
+                        self.pos += 2;
+                        return Ok(Token::DotDotDot);
+                    }
                     Ok(Token::Dot)
                 }
                 '-' => {
@@ -166,8 +325,16 @@ impl Lexer {
                 }
                 '*' => {
                     self.advance();
+                    if self.match_char('*') {
+                        return Ok(Token::StarStar);
+                    }
+
                     Ok(Token::Star)
                 }
+                '%' => {
+                    self.advance();
+                    Ok(Token::Percent)
+                }
                 ',' => {
                     self.advance();
                     Ok(Token::Comma)
@@ -225,7 +392,7 @@ impl Lexer {
                         return Ok(Token::BangEqual);
                     }
 
-                    Err(EngineError::lexer(format!("Invalid Bang usage")))
+                    Err(EngineError::lexer("Invalid Bang usage"))
                 }
                 '>' => {
                     self.advance();
@@ -243,21 +410,46 @@ impl Lexer {
 
                     Ok(Token::LessThan)
                 }
+                // `?.` followed by a digit would be a conditional with a `.5`-style number
+                '?' if self.source.get(self.pos + 1) == Some(&'.')
+                    && !self
+                        .source
+                        .get(self.pos + 2)
+                        .is_some_and(|character| character.is_ascii_digit()) =>
+                {
+                    self.advance();
+                    self.advance();
+                    Ok(Token::QuestionDot)
+                }
+                '?' => {
+                    self.advance();
+                    if self.peek() == Some('?') && self.source.get(self.pos + 1) == Some(&'=') {
+                        self.pos += 2;
+                        return Ok(Token::QuestionQuestionEqual);
+                    }
+                    Ok(Token::Question)
+                }
                 '&' => {
                     self.advance();
                     if self.match_char('&') {
+                        if self.match_char('=') {
+                            return Ok(Token::AndAndEqual);
+                        }
                         return Ok(Token::AndAnd);
                     }
 
-                    Err(EngineError::lexer(format!("Invalid And (&) usage")))
+                    Err(EngineError::lexer("Invalid And (&) usage"))
                 }
                 '|' => {
                     self.advance();
                     if self.match_char('|') {
+                        if self.match_char('=') {
+                            return Ok(Token::OrOrEqual);
+                        }
                         return Ok(Token::OrOr);
                     }
 
-                    Err(EngineError::lexer(format!("Invalid Or (|) usage")))
+                    Err(EngineError::lexer("Invalid Or (|) usage"))
                 }
                 _ => Err(EngineError::lexer(format!(
                     "Invalid character: {}",
@@ -268,22 +460,43 @@ impl Lexer {
     }
 
     pub fn tokenize(source: &str) -> Result<Vec<Token>, EngineError> {
-        let mut tokens: Vec<Token> = vec![];
+        Ok(Self::tokenize_spanned(source)?
+            .into_iter()
+            .map(|spanned| spanned.token)
+            .collect())
+    }
+
+    pub fn tokenize_spanned(source: &str) -> Result<Vec<SpannedToken>, EngineError> {
+        let mut tokens: Vec<SpannedToken> = vec![];
         let mut lexer = Self {
             pos: 0,
             source: source.chars().collect(),
         };
 
         loop {
-            lexer.skip_whitespace();
-            let token = lexer.next_token()?;
-
-            if let Token::End = token {
-                tokens.push(token);
+            lexer.skip_whitespace()?;
+
+            let start = lexer.pos;
+            let token = lexer.next_token().map_err(|err| {
+                err.with_span(Span {
+                    start,
+                    end: lexer.pos.max(start + 1).min(lexer.source.len()),
+                })
+            })?;
+
+            let is_end = matches!(token, Token::End);
+
+            tokens.push(SpannedToken {
+                token,
+                span: Span {
+                    start,
+                    end: lexer.pos.min(lexer.source.len()),
+                },
+            });
+
+            if is_end {
                 break;
             }
-
-            tokens.push(token);
         }
 
         Ok(tokens)
@@ -310,6 +523,98 @@ mod tests {
         assert!(matches!(tokens[7], Token::End));
     }
 
+    #[test]
+    fn test_token_spans() {
+        let tokens = Lexer::tokenize_spanned("let ab = 'x';").unwrap();
+        let spans: Vec<(usize, usize)> = tokens
+            .iter()
+            .map(|spanned| (spanned.span.start, spanned.span.end))
+            .collect();
+
+        // let, ab, =, 'x', ;, End
+        assert_eq!(
+            spans,
+            vec![(0, 3), (4, 6), (7, 8), (9, 12), (12, 13), (13, 13)]
+        );
+    }
+
+    #[test]
+    fn test_error_span() {
+        let err = Lexer::tokenize("let a = #;").unwrap_err();
+
+        assert_eq!(err.span(), Some(Span { start: 8, end: 9 }));
+
+        let err = Lexer::tokenize("'abc").unwrap_err();
+
+        assert_eq!(err.span(), Some(Span { start: 0, end: 4 }));
+    }
+
+    #[test]
+    fn test_question_dot() {
+        let tokens = Lexer::tokenize("a?.b?.()").unwrap();
+
+        assert_eq!(tokens.len(), 7); // a, ?., b, ?., (, ), End
+        assert!(matches!(tokens[1], Token::QuestionDot));
+        assert!(matches!(tokens[3], Token::QuestionDot));
+        assert!(matches!(tokens[4], Token::LParen));
+    }
+
+    #[test]
+    fn test_question_before_number_is_conditional() {
+        let tokens = Lexer::tokenize("a?.5:1").unwrap();
+
+        assert_eq!(tokens.len(), 6); // a, ?, .5, :, 1, End
+        assert!(matches!(tokens[1], Token::Question));
+        assert_eq!(tokens[2].try_as_numeric_literal().unwrap().value, 0.5);
+        assert!(matches!(tokens[3], Token::Colon));
+    }
+
+    #[test]
+    fn test_leading_and_trailing_dot_numbers() {
+        let tokens = Lexer::tokenize(".5 5. 1.25").unwrap();
+
+        assert_eq!(tokens.len(), 4); // .5, 5., 1.25, End
+        assert_eq!(tokens[0].try_as_numeric_literal().unwrap().value, 0.5);
+        assert_eq!(tokens[1].try_as_numeric_literal().unwrap().value, 5.0);
+        assert_eq!(tokens[2].try_as_numeric_literal().unwrap().value, 1.25);
+    }
+
+    #[test]
+    fn test_dot_before_identifier_is_member_access() {
+        let tokens = Lexer::tokenize("obj.prop").unwrap();
+
+        assert_eq!(tokens.len(), 4); // obj, ., prop, End
+        assert!(matches!(tokens[1], Token::Dot));
+        assert_eq!(tokens[2].try_as_identifier().unwrap().name, "prop");
+    }
+
+    #[test]
+    fn test_logical_assignment_operators() {
+        let tokens = Lexer::tokenize("a &&= b ||= c ??= d ? e : f").unwrap();
+
+        assert!(matches!(tokens[1], Token::AndAndEqual));
+        assert!(matches!(tokens[3], Token::OrOrEqual));
+        assert!(matches!(tokens[5], Token::QuestionQuestionEqual));
+        assert!(matches!(tokens[7], Token::Question));
+    }
+
+    #[test]
+    fn test_dot_dot_dot() {
+        let tokens = Lexer::tokenize("[...arr]").unwrap();
+
+        assert_eq!(tokens.len(), 5); // [, ..., arr, ], End
+        assert!(matches!(tokens[1], Token::DotDotDot));
+        assert_eq!(tokens[2].try_as_identifier().unwrap().name, "arr");
+    }
+
+    #[test]
+    fn test_percent() {
+        let tokens = Lexer::tokenize("7 % 3").unwrap();
+
+        assert_eq!(tokens.len(), 4); // 7, %, 3, End
+        assert!(matches!(tokens[1], Token::Percent));
+    }
+
     #[test]
     fn test_numeric_literals() {
         let source = "123 456 789 32.5";
@@ -324,6 +629,29 @@ mod tests {
         assert!(matches!(tokens[4], Token::End));
     }
 
+    #[test]
+    fn test_string_literals() {
+        let source = r#""hello" 'world' "" "a\"b" 'line\n'"#;
+        let tokens = Lexer::tokenize(source).unwrap();
+
+        assert_eq!(tokens.len(), 6); // 5 strings + End
+        assert_eq!(tokens[0].try_as_string_literal().unwrap().value, "hello");
+        assert_eq!(tokens[1].try_as_string_literal().unwrap().value, "world");
+        assert_eq!(tokens[2].try_as_string_literal().unwrap().value, "");
+        assert_eq!(tokens[3].try_as_string_literal().unwrap().value, "a\"b");
+        assert_eq!(tokens[4].try_as_string_literal().unwrap().value, "line\n");
+
+        assert!(matches!(tokens[5], Token::End));
+    }
+
+    #[test]
+    fn test_unterminated_string_literal() {
+        let result = Lexer::tokenize("'abc");
+
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().message(), "Unterminated string literal");
+    }
+
     #[test]
     fn test_identifiers() {
         let source = "foo bar baz123";
@@ -402,7 +730,7 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err().message(),
-            "Failed to parse 12.34.56 into f32"
+            "Failed to parse 12.34.56 into f64"
         );
     }
 
@@ -522,6 +850,95 @@ mod tests {
         assert!(matches!(tokens[1], Token::End));
     }
 
+    #[test]
+    fn test_loop_keywords() {
+        let tokens = Lexer::tokenize("for break continue").unwrap();
+
+        assert_eq!(tokens.len(), 4); // for, break, continue, End
+        assert!(matches!(tokens[0], Token::ForKeyword));
+        assert!(matches!(tokens[1], Token::BreakKeyword));
+        assert!(matches!(tokens[2], Token::ContinueKeyword));
+    }
+
+    #[test]
+    fn test_switch_keywords() {
+        let tokens = Lexer::tokenize("switch case default").unwrap();
+
+        assert_eq!(tokens.len(), 4); // switch, case, default, End
+        assert!(matches!(tokens[0], Token::SwitchKeyword));
+        assert!(matches!(tokens[1], Token::CaseKeyword));
+        assert!(matches!(tokens[2], Token::DefaultKeyword));
+    }
+
+    #[test]
+    fn test_typeof_keyword() {
+        let tokens = Lexer::tokenize("typeof typeofx").unwrap();
+
+        assert_eq!(tokens.len(), 3); // typeof, typeofx, End
+        assert!(matches!(tokens[0], Token::TypeofKeyword));
+        assert_eq!(tokens[1].try_as_identifier().unwrap().name, "typeofx");
+    }
+
+    #[test]
+    fn test_try_keywords() {
+        let tokens = Lexer::tokenize("try catch finally").unwrap();
+
+        assert_eq!(tokens.len(), 4); // try, catch, finally, End
+        assert!(matches!(tokens[0], Token::TryKeyword));
+        assert!(matches!(tokens[1], Token::CatchKeyword));
+        assert!(matches!(tokens[2], Token::FinallyKeyword));
+    }
+
+    #[test]
+    fn test_new_keyword() {
+        let source = "new";
+        let tokens = Lexer::tokenize(source).unwrap();
+
+        assert_eq!(tokens.len(), 2); // new, End
+        assert!(matches!(tokens[0], Token::NewKeyword));
+        assert!(matches!(tokens[1], Token::End));
+    }
+
+    #[test]
+    fn test_dollar_and_underscore_identifiers() {
+        for name in ["$foo", "_bar", "a$b", "$", "_", "a_1$"] {
+            let tokens = Lexer::tokenize(name).unwrap();
+
+            assert_eq!(tokens.len(), 2, "{name}"); // identifier, End
+            assert_eq!(tokens[0].try_as_identifier().unwrap().name, name);
+        }
+    }
+
+    #[test]
+    fn test_dollar_identifiers_next_to_operators() {
+        let tokens = Lexer::tokenize("$x+a$;").unwrap();
+
+        assert_eq!(tokens.len(), 5); // $x, +, a$, ;, End
+        assert_eq!(tokens[0].try_as_identifier().unwrap().name, "$x");
+        assert!(matches!(tokens[1], Token::Plus));
+        assert_eq!(tokens[2].try_as_identifier().unwrap().name, "a$");
+        assert!(matches!(tokens[3], Token::Semicolon));
+    }
+
+    #[test]
+    fn test_unicode_identifiers() {
+        let tokens = Lexer::tokenize("café π日本").unwrap();
+
+        assert_eq!(tokens.len(), 3); // café, π日本, End
+        assert_eq!(tokens[0].try_as_identifier().unwrap().name, "café");
+        assert_eq!(tokens[1].try_as_identifier().unwrap().name, "π日本");
+    }
+
+    #[test]
+    fn test_identifier_cannot_start_with_digit() {
+        let tokens = Lexer::tokenize("1abc").unwrap();
+
+        assert_eq!(tokens.len(), 3); // 1, abc, End
+        assert_eq!(tokens[0].try_as_numeric_literal().unwrap().value, 1.0);
+        assert_eq!(tokens[1].try_as_identifier().unwrap().name, "abc");
+        assert!(matches!(tokens[2], Token::End));
+    }
+
     #[test]
     fn test_equal() {
         let source = "=";
@@ -802,4 +1219,36 @@ mod tests {
         assert!(matches!(tokens[10], Token::Semicolon));
         assert!(matches!(tokens[11], Token::End));
     }
+
+    #[test]
+    fn test_comments_are_skipped() {
+        let tokens = Lexer::tokenize("a / b; // trailing / comment").unwrap();
+
+        assert_eq!(tokens.len(), 5); // a, /, b, ;, End
+        assert!(matches!(tokens[1], Token::Slash));
+        assert!(matches!(tokens[4], Token::End));
+
+        let tokens = Lexer::tokenize("a /* block\n comment */ / /**/b").unwrap();
+
+        assert_eq!(tokens.len(), 4); // a, /, b, End
+        assert!(matches!(tokens[1], Token::Slash));
+        assert_eq!(tokens[2].try_as_identifier().unwrap().name, "b");
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let err = Lexer::tokenize("a /* never closed").unwrap_err();
+
+        assert_eq!(err.message(), "Unterminated block comment");
+        assert_eq!(err.span(), Some(Span { start: 2, end: 17 }));
+    }
+
+    #[test]
+    fn test_star_star() {
+        let tokens = Lexer::tokenize("2 ** 3 * 4").unwrap();
+
+        assert_eq!(tokens.len(), 6); // 2, **, 3, *, 4, End
+        assert!(matches!(tokens[1], Token::StarStar));
+        assert!(matches!(tokens[3], Token::Star));
+    }
 }
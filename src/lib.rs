@@ -0,0 +1,55 @@
+pub mod ast;
+pub mod ecma;
+pub mod error;
+pub mod heap;
+pub mod lexer;
+pub mod number;
+/// The single execution engine: everything that runs JS goes through `vm::VM`.
+pub mod vm;
+
+pub use error::{EngineError, Span};
+pub use lexer::{SpannedToken, Token};
+
+/**
+ * Lexes `source` without parsing it, for tooling such as syntax highlighters.
+ */
+pub fn tokenize(source: &str) -> Result<Vec<Token>, EngineError> {
+    lexer::Lexer::tokenize(source)
+}
+
+/**
+ * Like `tokenize`, but pairs every token with its character span in `source`.
+ */
+pub fn tokenize_spanned(source: &str) -> Result<Vec<SpannedToken>, EngineError> {
+    lexer::Lexer::tokenize_spanned(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Token, tokenize, tokenize_spanned};
+
+    #[test]
+    fn test_tokenize_program() {
+        let tokens = tokenize("let x = f(1);").unwrap();
+
+        assert!(matches!(tokens[0], Token::LetKeyword));
+        assert!(matches!(tokens[1], Token::Identifier(_)));
+        assert!(matches!(tokens[2], Token::Equal));
+        assert!(matches!(tokens[3], Token::Identifier(_)));
+        assert!(matches!(tokens[4], Token::LParen));
+        assert!(matches!(tokens[5], Token::NumericLiteral(_)));
+        assert!(matches!(tokens[6], Token::RParen));
+        assert!(matches!(tokens[7], Token::Semicolon));
+        assert!(matches!(tokens[8], Token::End));
+    }
+
+    #[test]
+    fn test_tokenize_spans_are_monotonic() {
+        let tokens = tokenize_spanned("let add = (a, b) => { return a + b; };").unwrap();
+
+        for pair in tokens.windows(2) {
+            assert!(pair[0].span.start <= pair[0].span.end);
+            assert!(pair[0].span.end <= pair[1].span.start);
+        }
+    }
+}
@@ -1,7 +1 @@
-pub mod ast;
-pub mod ecma;
-pub mod error;
-pub mod lexer;
-pub mod vm;
-
 fn main() {}
@@ -0,0 +1,17 @@
+//! Number formatting shared by the parser (numeric object keys) and the VM.
+
+/**
+ * JS Number::toString for the cases Rust formats differently:
+ * `NaN`, the infinities and negative zero (which prints as `0`).
+ */
+pub fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        (if n > 0.0 { "Infinity" } else { "-Infinity" }).to_string()
+    } else if n == 0.0 {
+        "0".to_string()
+    } else {
+        n.to_string()
+    }
+}
@@ -1,13 +1,29 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{
+    cell::{RefCell, RefMut},
+    cmp::Ordering,
+    collections::HashMap,
+    rc::Rc,
+};
 
 use crate::{
-    ast::{ASTParser, Expression, FunctionDefinitionExpression, ObjectPropertyName, Statement},
-    ecma::{ArrayClass, BooleanClass, FunctionClass, JSModule, ObjectClass, PROTOTYPE},
-    error::EngineError,
+    ast::{
+        ASTParser, BinaryExpression, BlockStatement, Expression, ForOfStatement, ForStatement,
+        FunctionCallExpression, FunctionDefinitionExpression, ObjectPropertyName, SourceLocation,
+        Statement, TryStatement,
+    },
+    ecma::{
+        ArrayClass, BooleanClass, ConsoleClass, DateClass, FunctionClass, JSModule, MathClass,
+        NumberClass, ObjectClass, PROTOTYPE, Rng, StringClass, TestHelpers, UriFunctions,
+    },
+    error::{self, EngineError, ErrorKind},
+    heap::{Heap, VecHeap},
     lexer::Token,
+    number::number_to_string,
 };
 
-#[derive(Clone, Copy, Debug)]
+pub use crate::heap::HeapStats;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct ObjectRef {
     heap_address: usize,
 }
@@ -24,12 +40,22 @@ impl ObjectRef {
     pub fn load_mut(self, vm: &mut VM) -> &mut Object {
         vm.heap_get_mut(self)
     }
+
+    pub fn try_load(self, vm: &VM) -> Result<&Object, EngineError> {
+        vm.try_heap_get(self)
+    }
+
+    pub fn try_load_mut(self, vm: &mut VM) -> Result<&mut Object, EngineError> {
+        vm.try_heap_get_mut(self)
+    }
 }
 
 pub struct CallContext {
     pub args: Vec<JSValue>,
     pub this: ObjectRef,
     pub ast_definition: Option<usize>,
+    /// The constructor when invoked through `new`.
+    pub new_target: Option<ObjectRef>,
 }
 
 impl CallContext {
@@ -38,6 +64,7 @@ impl CallContext {
             args,
             this,
             ast_definition: None,
+            new_target: None,
         }
     }
 
@@ -46,9 +73,15 @@ impl CallContext {
             args,
             this,
             ast_definition: Some(ast_definition),
+            new_target: None,
         }
     }
 
+    pub fn with_new_target(mut self, new_target: ObjectRef) -> Self {
+        self.new_target = Some(new_target);
+        self
+    }
+
     pub fn arg(&self, index: usize) -> Option<&JSValue> {
         self.args.get(index)
     }
@@ -64,12 +97,62 @@ pub enum Call {
 
 pub type Construct = NativeFunction;
 
+/**
+ * Attributes of a property created by `Object.defineProperty`.
+ * Properties without an entry are plain writable, enumerable data properties.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct PropertyAttributes {
+    pub writable: bool,
+    pub enumerable: bool,
+    pub get: Option<ObjectRef>,
+    pub set: Option<ObjectRef>,
+}
+
+impl PropertyAttributes {
+    pub fn data() -> Self {
+        Self {
+            writable: true,
+            enumerable: true,
+            get: None,
+            set: None,
+        }
+    }
+
+    /**
+     * Methods and `length` of built-ins: writable, but skipped by `Object.keys` and spread.
+     */
+    pub fn builtin() -> Self {
+        Self {
+            writable: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_accessor(&self) -> bool {
+        self.get.is_some() || self.set.is_some()
+    }
+}
+
 pub struct Object {
-    pub captured_scope: Option<usize>,
+    /// Scope chain a function was created in, its body runs on top of it.
+    pub captured_scopes: Vec<ScopeRef>,
     pub properties: HashMap<String, JSValue>,
     pub prototype: Option<ObjectRef>,
     pub call: Option<Call>,
     pub construct: Option<Construct>,
+    /// Primitive wrapped by the object, e.g. the time value of a `Date`.
+    pub primitive_value: Option<JSValue>,
+    /// Engine-only state hidden from scripts, e.g. `[[ArrayIteratorNextIndex]]`.
+    pub internal_slots: HashMap<String, JSValue>,
+    /// Non-default attributes, keyed like `properties`.
+    pub attributes: HashMap<String, PropertyAttributes>,
+}
+
+impl Default for Object {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Object {
@@ -79,7 +162,10 @@ impl Object {
             prototype: None,
             call: None,
             construct: None,
-            captured_scope: None,
+            captured_scopes: vec![],
+            primitive_value: None,
+            internal_slots: HashMap::new(),
+            attributes: HashMap::new(),
         }
     }
 
@@ -107,8 +193,8 @@ impl Object {
         self
     }
 
-    pub fn with_captured_scope(mut self, scope_index: usize) -> Object {
-        self.captured_scope = Some(scope_index);
+    pub fn with_captured_scopes(mut self, scopes: Vec<ScopeRef>) -> Object {
+        self.captured_scopes = scopes;
         self
     }
 
@@ -117,8 +203,16 @@ impl Object {
         self
     }
 
+    pub fn with_primitive_value(mut self, value: JSValue) -> Object {
+        self.primitive_value = Some(value);
+        self
+    }
+
+    /**
+     * Adds a built-in property, see `PropertyAttributes::builtin`.
+     */
     pub fn with_property(mut self, key: impl Into<String>, value: JSValue) -> Self {
-        self.properties.insert(key.into(), value);
+        self.define_property(key, value, PropertyAttributes::builtin());
         self
     }
 
@@ -127,8 +221,24 @@ impl Object {
         self
     }
 
+    /**
+     * Sets `key` with non-default `attributes`, e.g. read-only globals.
+     */
+    pub fn define_property(
+        &mut self,
+        key: impl Into<String>,
+        value: JSValue,
+        attributes: PropertyAttributes,
+    ) -> &mut Self {
+        let key = key.into();
+        self.properties.insert(key.clone(), value);
+        self.attributes.insert(key, attributes);
+        self
+    }
+
     pub fn delete_property(&mut self, key: &str) -> &mut Self {
         self.properties.remove(key);
+        self.attributes.remove(key);
         self
     }
 
@@ -136,6 +246,36 @@ impl Object {
         self.properties.get(key).cloned()
     }
 
+    /**
+     * Attributes of an own property, `None` if the object doesn't have it.
+     */
+    pub fn property_attributes(&self, key: &str) -> Option<PropertyAttributes> {
+        if !self.properties.contains_key(key) {
+            return None;
+        }
+
+        Some(
+            self.attributes
+                .get(key)
+                .cloned()
+                .unwrap_or_else(PropertyAttributes::data),
+        )
+    }
+
+    pub fn with_internal_slot(mut self, key: impl Into<String>, value: JSValue) -> Self {
+        self.internal_slots.insert(key.into(), value);
+        self
+    }
+
+    pub fn set_internal_slot(&mut self, key: impl Into<String>, value: JSValue) -> &mut Self {
+        self.internal_slots.insert(key.into(), value);
+        self
+    }
+
+    pub fn get_internal_slot(&self, key: &str) -> Option<JSValue> {
+        self.internal_slots.get(key).cloned()
+    }
+
     pub fn set_prototype(&mut self, prototype: ObjectRef) -> &mut Self {
         self.prototype = Some(prototype);
         self
@@ -145,14 +285,14 @@ impl Object {
 #[derive(Clone, Debug)]
 pub enum JSValue {
     String(String),
-    Number(f32),
+    Number(f64),
     Undefined,
     Object(ObjectRef),
     Boolean(bool),
 }
 
 impl JSValue {
-    pub fn try_as_number(&self) -> Option<f32> {
+    pub fn try_as_number(&self) -> Option<f64> {
         match self {
             JSValue::Number(n) => Some(*n),
             _ => None,
@@ -166,26 +306,26 @@ impl JSValue {
     pub fn native_function(prototype: ObjectRef, func: NativeFunction, vm: &mut VM) -> JSValue {
         JSValue::Object(
             Object::new()
-                .with_prototype(prototype.clone())
+                .with_prototype(prototype)
                 .with_call_native(func)
                 .alloc(vm),
         )
     }
 
     pub fn from_object_ref(object_ref: ObjectRef) -> JSValue {
-        JSValue::Object(object_ref.clone())
+        JSValue::Object(object_ref)
     }
 
     pub fn try_as_object(&self) -> Option<ObjectRef> {
         match self {
-            JSValue::Object(obj) => Some(obj.clone()),
+            JSValue::Object(obj) => Some(*obj),
             _ => None,
         }
     }
 
     pub fn try_get_prototype(&self, vm: &VM) -> Option<ObjectRef> {
         match self {
-            JSValue::Object(obj) => obj.load(vm).prototype.clone(),
+            JSValue::Object(obj) => obj.load(vm).prototype,
             _ => None,
         }
     }
@@ -204,157 +344,656 @@ impl JSValue {
         }
     }
 
-    pub fn add(&self, other: &JSValue) -> JSValue {
-        if let JSValue::Number(self_number) = self
-            && let JSValue::Number(other_number) = other
-        {
-            return JSValue::Number(*self_number + *other_number);
+    /**
+     * JS ToBoolean. Shared by `Boolean(x)` and conditionals so they can't disagree.
+     */
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            JSValue::Undefined => false,
+            JSValue::Boolean(b) => *b,
+            JSValue::Number(n) => !n.is_nan() && *n != 0.0,
+            JSValue::String(s) => !s.is_empty(),
+            JSValue::Object(_) => true,
         }
+    }
 
-        unimplemented!()
+    /**
+     * JS ToNumber for primitives. Objects must go through `VM::to_primitive` first
+     * and convert to `NaN` here.
+     */
+    pub fn to_number(&self) -> f64 {
+        match self {
+            JSValue::Number(n) => *n,
+            JSValue::Boolean(b) => {
+                if *b {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            JSValue::String(s) => Self::string_to_number(s),
+            JSValue::Undefined | JSValue::Object(_) => f64::NAN,
+        }
     }
 
-    pub fn sub(&self, other: &JSValue) -> JSValue {
-        if let JSValue::Number(self_number) = self
-            && let JSValue::Number(other_number) = other
+    /**
+     * StringToNumber: decimal literals with optional sign and exponent, `Infinity`,
+     * and unsigned `0x`/`0o`/`0b` integers. Surrounding whitespace is ignored and
+     * an empty string is `0`; anything else, including Rust-only forms like `inf`, is `NaN`.
+     */
+    fn string_to_number(s: &str) -> f64 {
+        let trimmed = s.trim();
+
+        if trimmed.is_empty() {
+            return 0.0;
+        }
+
+        let radix = match trimmed.get(..2) {
+            Some("0x" | "0X") => Some(16),
+            Some("0o" | "0O") => Some(8),
+            Some("0b" | "0B") => Some(2),
+            _ => None,
+        };
+
+        if let Some(radix) = radix {
+            let digits = &trimmed[2..];
+
+            if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+                return f64::NAN;
+            }
+
+            return digits.chars().fold(0.0, |number, c| {
+                number * radix as f64 + c.to_digit(radix).unwrap() as f64
+            });
+        }
+
+        let unsigned = trimmed.strip_prefix(['+', '-']).unwrap_or(trimmed);
+
+        if unsigned == "Infinity" {
+            return if trimmed.starts_with('-') {
+                f64::NEG_INFINITY
+            } else {
+                f64::INFINITY
+            };
+        }
+
+        if !unsigned
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'))
         {
-            return JSValue::Number(*self_number - *other_number);
+            return f64::NAN;
         }
 
-        unimplemented!()
+        trimmed.parse().unwrap_or(f64::NAN)
     }
 
-    pub fn multiply(&self, other: &JSValue) -> JSValue {
-        if let JSValue::Number(self_number) = self
-            && let JSValue::Number(other_number) = other
-        {
-            return JSValue::Number(*self_number * *other_number);
+    /**
+     * `+` on primitives: concatenates when either side is a string, otherwise adds numerically.
+     */
+    pub fn add(&self, other: &JSValue) -> JSValue {
+        if matches!(self, JSValue::String(_)) || matches!(other, JSValue::String(_)) {
+            return JSValue::String(self.primitive_to_string() + &other.primitive_to_string());
         }
 
-        unimplemented!()
+        JSValue::Number(self.to_number() + other.to_number())
+    }
+
+    pub fn sub(&self, other: &JSValue) -> JSValue {
+        JSValue::Number(self.to_number() - other.to_number())
+    }
+
+    pub fn multiply(&self, other: &JSValue) -> JSValue {
+        JSValue::Number(self.to_number() * other.to_number())
     }
 
     pub fn divide(&self, other: &JSValue) -> JSValue {
-        if let JSValue::Number(self_number) = self
-            && let JSValue::Number(other_number) = other
+        JSValue::Number(self.to_number() / other.to_number())
+    }
+
+    pub fn remainder(&self, other: &JSValue) -> JSValue {
+        JSValue::Number(self.to_number() % other.to_number())
+    }
+
+    /**
+     * `**` via `powf`, except where JS differs from IEEE `pow`: `x ** NaN` is `NaN`
+     * even for `x = 1`, and so is `(±1) ** ±Infinity`. `NaN ** 0` stays `1`.
+     */
+    pub fn pow(&self, other: &JSValue) -> JSValue {
+        let base = self.to_number();
+        let exponent = other.to_number();
+
+        let result = if exponent == 0.0 {
+            1.0
+        } else if exponent.is_nan() || (base.abs() == 1.0 && exponent.is_infinite()) {
+            f64::NAN
+        } else {
+            base.powf(exponent)
+        };
+
+        JSValue::Number(result)
+    }
+
+    /**
+     * Relational comparison on primitives. Two strings compare lexicographically,
+     * anything else numerically; `None` means a `NaN` was involved.
+     */
+    pub fn compare(&self, other: &JSValue) -> Option<Ordering> {
+        if let JSValue::String(self_string) = self
+            && let JSValue::String(other_string) = other
         {
-            return JSValue::Number(*self_number / *other_number);
+            return Some(self_string.cmp(other_string));
         }
 
-        unimplemented!()
+        self.to_number().partial_cmp(&other.to_number())
     }
 
-    pub fn cast_to_string(self, vm: &mut VM) -> Result<String, EngineError> {
-        let res = match self {
-            JSValue::String(s) => s,
-            JSValue::Number(n) => n.to_string(),
-            JSValue::Object(object) => object
-                .load(vm)
-                .get_property("toString")
-                .and_then(|property| property.try_as_object())
-                .map(|object| {
-                    vm.call_function(object, object.clone(), vec![])
-                        .map(|v| v.try_as_string())
-                })
-                .unwrap_or_else(|| Ok(Some(ObjectClass::str_fallback())))?
-                .unwrap_or_else(ObjectClass::str_fallback),
+    /**
+     * IsStrictlyEqual (`===`): no coercion, objects compare by identity.
+     */
+    pub fn strict_equals(&self, other: &JSValue) -> bool {
+        match (self, other) {
+            (JSValue::Number(a), JSValue::Number(b)) => a == b,
+            (JSValue::String(a), JSValue::String(b)) => a == b,
+            (JSValue::Boolean(a), JSValue::Boolean(b)) => a == b,
+            (JSValue::Undefined, JSValue::Undefined) => true,
+            (JSValue::Object(a), JSValue::Object(b)) => a.heap_address == b.heap_address,
+            _ => false,
+        }
+    }
+
+    /**
+     * SameValue: `===` except `NaN` equals itself and `+0` differs from `-0`.
+     */
+    pub fn same_value(&self, other: &JSValue) -> bool {
+        match (self, other) {
+            (JSValue::Number(a), JSValue::Number(b)) => {
+                (a.is_nan() && b.is_nan()) || a.to_bits() == b.to_bits()
+            }
+            _ => self.strict_equals(other),
+        }
+    }
+
+    /**
+     * SameValueZero: `===` except `NaN` equals itself, used by `includes`.
+     */
+    pub fn same_value_zero(&self, other: &JSValue) -> bool {
+        match (self, other) {
+            (JSValue::Number(a), JSValue::Number(b)) if a.is_nan() && b.is_nan() => true,
+            _ => self.strict_equals(other),
+        }
+    }
+
+    fn primitive_to_string(&self) -> String {
+        match self {
+            JSValue::String(s) => s.clone(),
+            JSValue::Number(n) => number_to_string(*n),
             JSValue::Undefined => "undefined".to_string(),
-            JSValue::Boolean(bool) => (if bool { "true" } else { "false" }).to_string(),
+            JSValue::Boolean(bool) => (if *bool { "true" } else { "false" }).to_string(),
+            JSValue::Object(_) => ObjectClass::str_fallback(),
+        }
+    }
+
+    /**
+     * ToString for any value. Objects go through their `toString`, one without a callable
+     * `toString` becomes `ObjectClass::str_fallback()`.
+     */
+    pub fn cast_to_string(self, vm: &mut VM) -> Result<String, EngineError> {
+        let JSValue::Object(object) = self else {
+            return Ok(self.primitive_to_string());
+        };
+
+        let Some(function) = vm
+            .get_property(object, "toString")?
+            .and_then(|property| property.try_as_object())
+        else {
+            return Ok(ObjectClass::str_fallback());
+        };
+
+        if function.try_load(vm)?.call.is_none() {
+            return Ok(ObjectClass::str_fallback());
+        }
+
+        Ok(match vm.call_function(function, object, vec![])? {
+            JSValue::Object(_) => ObjectClass::str_fallback(),
+            primitive => primitive.primitive_to_string(),
+        })
+    }
+
+    /**
+     * REPL-style rendering for hosts: strings quoted, arrays as `[1, 2]`, objects as
+     * `{ a: 1 }`, functions as `[Function]`. Reads properties without running getters
+     * or `toString`; an object already being printed further up is `[Circular]`, and an
+     * array whose elements can't be read, e.g. one past `MAX_ELEMENTS`, is `[Array(n)]`.
+     */
+    pub fn to_display_string(&self, vm: &mut VM) -> String {
+        self.display(vm, &mut vec![])
+    }
+
+    fn display(&self, vm: &mut VM, seen: &mut Vec<ObjectRef>) -> String {
+        let object = match self {
+            JSValue::String(string) => return Self::quote(string),
+            JSValue::Object(object) => *object,
+            primitive => return primitive.primitive_to_string(),
+        };
+
+        if seen.contains(&object) {
+            return "[Circular]".to_string();
+        }
+
+        let Ok(loaded) = object.try_load(vm) else {
+            return "[Object]".to_string();
+        };
+
+        if loaded.call.is_some() {
+            return "[Function]".to_string();
+        }
+
+        if seen.len() >= MAX_DISPLAY_DEPTH {
+            return "[Object]".to_string();
+        }
+
+        seen.push(object);
+
+        let rendered = if ArrayClass::is_array(vm, self) {
+            match ArrayClass::elements(vm, object) {
+                Ok(elements) => {
+                    let parts: Vec<String> = elements
+                        .iter()
+                        .map(|element| element.display(vm, seen))
+                        .collect();
+
+                    format!("[{}]", parts.join(", "))
+                }
+                Err(_) => match ArrayClass::length(vm, object) {
+                    Ok(length) => format!("[Array({})]", length),
+                    Err(_) => "[Array]".to_string(),
+                },
+            }
+        } else {
+            let mut parts = vec![];
+
+            for key in ObjectClass::own_enumerable_keys(vm, object).unwrap_or_default() {
+                let loaded = object.load(vm);
+                let attributes = loaded.property_attributes(&key).unwrap_or_default();
+
+                let value = match (attributes.get.is_some(), attributes.set.is_some()) {
+                    (true, true) => "[Getter/Setter]".to_string(),
+                    (true, false) => "[Getter]".to_string(),
+                    (false, true) => "[Setter]".to_string(),
+                    (false, false) => loaded
+                        .get_property(&key)
+                        .unwrap_or(JSValue::Undefined)
+                        .display(vm, seen),
+                };
+
+                parts.push(format!("{}: {}", Self::display_key(&key), value));
+            }
+
+            if parts.is_empty() {
+                "{}".to_string()
+            } else {
+                format!("{{ {} }}", parts.join(", "))
+            }
         };
 
-        Ok(res)
+        seen.pop();
+
+        rendered
+    }
+
+    fn quote(string: &str) -> String {
+        let mut quoted = String::from('"');
+
+        for character in string.chars() {
+            match character {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                '\t' => quoted.push_str("\\t"),
+                character => quoted.push(character),
+            }
+        }
+
+        quoted.push('"');
+        quoted
+    }
+
+    /**
+     * Object keys print bare when they are identifiers or array indices, quoted otherwise.
+     */
+    fn display_key(key: &str) -> String {
+        let mut chars = key.chars();
+        let is_identifier = chars
+            .next()
+            .is_some_and(|first| first.is_alphabetic() || first == '$' || first == '_')
+            && chars.all(|character| {
+                character.is_alphanumeric() || character == '$' || character == '_'
+            });
+
+        if is_identifier || ArrayClass::array_index(key).is_some() {
+            key.to_string()
+        } else {
+            Self::quote(key)
+        }
     }
 }
 
+/// The left-hand side of an assignment, see `VM::resolve_assignment_target`.
+enum AssignmentTarget {
+    Variable(String),
+    Member(ObjectRef, String),
+}
+
+/// Scope binding of `new.target`, not a valid identifier so scripts can't shadow it.
+const NEW_TARGET: &str = "new.target";
+
+/// Nesting limit for `JSValue::to_display_string`, deeper objects print as `[Object]`.
+const MAX_DISPLAY_DEPTH: usize = 32;
+
+#[derive(Clone)]
 pub struct Scope {
     pub variables: HashMap<String, JSValue>,
 }
 
+/// Scopes are shared between the running code and every function that captured them.
+pub type ScopeRef = Rc<RefCell<Scope>>;
+
+impl Default for Scope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Scope {
     pub fn new() -> Self {
         Scope {
             variables: HashMap::new(),
         }
     }
+
+    pub fn new_ref() -> ScopeRef {
+        Rc::new(RefCell::new(Self::new()))
+    }
+}
+
+/// Where script output such as `console.log` lines is written.
+pub type OutputSink = Box<dyn FnMut(&str)>;
+
+#[derive(Default)]
+pub struct VmOptions {
+    /// Maximum depth of nested function calls before a call errors out. Unlimited when unset,
+    /// so only the native stack bounds recursion; a debug build needs roughly 16 KiB per call.
+    pub recursion_limit: Option<usize>,
+    /// Reading a variable that was never declared errors instead of yielding `undefined`.
+    pub strict_undeclared: bool,
+    /// Top-level `let` declarations are also set as properties on `globalThis`.
+    pub global_lets: bool,
+    /// Native functions registered on `globalThis` before any script runs.
+    pub globals: Vec<(String, NativeFunction)>,
+    /// Maximum number of statements and expressions a single `evaluate_source` may execute.
+    pub step_limit: Option<usize>,
+    /// Seed for `Math.random`; entropy-seeded when unset.
+    pub rng_seed: Option<u64>,
+    /// Milliseconds since the Unix epoch used by `Date`; the system clock when unset.
+    pub clock: Option<Box<dyn Fn() -> f64>>,
+    /// Object storage; a `VecHeap` when unset.
+    pub heap: Option<Box<dyn Heap>>,
+    /// Overrides `RUST_BACKTRACE` for errors created while this VM runs, see `error::capture_backtraces`.
+    pub backtraces: Option<bool>,
+    /// Receives every line printed by scripts, e.g. through `console.log`; stdout when unset.
+    pub output: Option<OutputSink>,
+}
+
+#[derive(Default)]
+pub struct VmBuilder {
+    options: VmOptions,
+}
+
+impl VmBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_recursion_limit(mut self, limit: usize) -> Self {
+        self.options.recursion_limit = Some(limit);
+        self
+    }
+
+    pub fn with_strict_undeclared(mut self, strict: bool) -> Self {
+        self.options.strict_undeclared = strict;
+        self
+    }
+
+    pub fn with_global_lets(mut self, global_lets: bool) -> Self {
+        self.options.global_lets = global_lets;
+        self
+    }
+
+    pub fn with_global(mut self, name: impl Into<String>, function: NativeFunction) -> Self {
+        self.options.globals.push((name.into(), function));
+        self
+    }
+
+    /**
+     * Registers the non-standard `TestHelpers` as globals, e.g. `__range(n)`.
+     */
+    pub fn with_test_helpers(self) -> Self {
+        self.with_global("__range", TestHelpers::range)
+    }
+
+    pub fn with_step_limit(mut self, limit: usize) -> Self {
+        self.options.step_limit = Some(limit);
+        self
+    }
+
+    pub fn with_rng_seed(mut self, seed: u64) -> Self {
+        self.options.rng_seed = Some(seed);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: impl Fn() -> f64 + 'static) -> Self {
+        self.options.clock = Some(Box::new(clock));
+        self
+    }
+
+    pub fn with_heap(mut self, heap: impl Heap + 'static) -> Self {
+        self.options.heap = Some(Box::new(heap));
+        self
+    }
+
+    pub fn with_backtraces(mut self, capture: bool) -> Self {
+        self.options.backtraces = Some(capture);
+        self
+    }
+
+    pub fn with_output(mut self, output: impl FnMut(&str) + 'static) -> Self {
+        self.options.output = Some(Box::new(output));
+        self
+    }
+
+    pub fn build(self) -> VM {
+        VM::with_options(self.options)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopSignal {
+    Break,
+    Continue,
 }
 
 pub struct VM {
-    pub scopes: Vec<Scope>,
+    pub options: VmOptions,
+    pub scopes: Vec<ScopeRef>,
     pub global_this: ObjectRef,
     pub modules: HashMap<String, Box<dyn JSModule>>,
-    pub heap: Vec<Option<Object>>,
-    pub heap_free: Vec<usize>,
+    pub heap: Box<dyn Heap>,
     pub function_definitions: Vec<Rc<FunctionDefinitionExpression>>,
     pub exit_current_call: bool,
+    /// Set by `break`/`continue` and consumed by the innermost loop.
+    pub loop_signal: Option<LoopSignal>,
+    pub call_depth: usize,
+    /// Arrays whose `join` is currently running, see `ArrayClass::join`.
+    pub stringifying: Vec<ObjectRef>,
+    pub steps: usize,
+    pub rng: Rng,
+    pub clock: Box<dyn Fn() -> f64>,
+    pub output: OutputSink,
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VM {
     pub fn new() -> Self {
-        let global_this = Object::new();
-        let mut heap: Vec<Option<Object>> = vec![];
-        heap.push(Some(global_this));
+        VmBuilder::default().build()
+    }
+
+    pub fn with_options(mut options: VmOptions) -> Self {
+        let mut heap = options
+            .heap
+            .take()
+            .unwrap_or_else(|| Box::new(VecHeap::new()));
+        let global_this = ObjectRef::new(heap.alloc(Object::new()));
+
+        let rng = options
+            .rng_seed
+            .map(Rng::from_seed)
+            .unwrap_or_else(Rng::from_entropy);
+
+        let clock = options
+            .clock
+            .take()
+            .unwrap_or_else(|| Box::new(DateClass::system_now));
+
+        let output = options
+            .output
+            .take()
+            .unwrap_or_else(|| Box::new(|line: &str| println!("{}", line)));
 
         let mut vm = Self {
+            options,
+            rng,
+            clock,
+            output,
             function_definitions: vec![],
             scopes: vec![],
-            global_this: ObjectRef::new(0),
+            global_this,
             modules: HashMap::new(),
             heap,
-            heap_free: vec![],
             exit_current_call: false,
+            loop_signal: None,
+            call_depth: 0,
+            stringifying: vec![],
+            steps: 0,
         };
 
         vm.register_module(ObjectClass::new());
         vm.register_module(FunctionClass::new());
         vm.register_module(ArrayClass::new());
         vm.register_module(BooleanClass::new());
+        vm.register_module(NumberClass::new());
+        vm.register_module(MathClass::new());
+        vm.register_module(DateClass::new());
+        vm.register_module(UriFunctions::new());
+        vm.register_module(StringClass::new());
+        vm.register_module(ConsoleClass::new());
+
+        vm.global_this
+            .load_mut(&mut vm)
+            .define_property(
+                "undefined",
+                JSValue::Undefined,
+                PropertyAttributes::default(),
+            )
+            .define_property(
+                "Infinity",
+                JSValue::Number(f64::INFINITY),
+                PropertyAttributes::default(),
+            )
+            .define_property(
+                "NaN",
+                JSValue::Number(f64::NAN),
+                PropertyAttributes::default(),
+            )
+            .set_property("globalThis", JSValue::Object(global_this));
 
-        vm.scopes.push(Scope::new());
+        vm.scopes.push(Scope::new_ref());
+        vm.set_variable("this", JSValue::Object(vm.global_this));
+
+        for (name, function) in vm.options.globals.clone() {
+            let value =
+                JSValue::native_function(FunctionClass::prototype(&mut vm), function, &mut vm);
+            vm.global_this.load_mut(&mut vm).set_property(name, value);
+        }
 
         vm
     }
 
     pub fn heap_alloc(&mut self, object: Object) -> ObjectRef {
-        if let Some(free_address) = self.heap_free.pop() {
-            self.heap[free_address] = Some(object);
-            return ObjectRef::new(free_address);
-        }
-
-        self.heap.push(Some(object));
-        ObjectRef::new(self.heap.len() - 1)
+        ObjectRef::new(self.heap.alloc(object))
     }
 
     pub fn heap_get(&self, object_ref: ObjectRef) -> &Object {
-        self.heap
-            .get(object_ref.heap_address)
-            .expect(
-                format!(
-                    "Invalid heap address: {}. This is likely a bug in GC handling",
-                    object_ref.heap_address
-                )
-                .as_str(),
+        self.heap.get(object_ref.heap_address).unwrap_or_else(|| {
+            panic!(
+                "No object at heap address: {}. This is likely a bug in GC handling",
+                object_ref.heap_address
             )
-            .as_ref()
-            .expect("Object at heap address is None. This is likely a bug in GC handling")
+        })
     }
 
     pub fn heap_get_mut(&mut self, object_ref: ObjectRef) -> &mut Object {
         self.heap
             .get_mut(object_ref.heap_address)
-            .expect(
-                format!(
-                    "Invalid heap address: {}. This is likely a bug in GC handling",
+            .unwrap_or_else(|| {
+                panic!(
+                    "No object at heap address: {}. This is likely a bug in GC handling",
                     object_ref.heap_address
                 )
-                .as_str(),
-            )
-            .as_mut()
-            .expect("Object at heap address is None. This is likely a bug in GC handling")
-    }
-
-    pub fn heap_free(&mut self, object_ref: ObjectRef) {
-        self.heap[object_ref.heap_address] = None;
-        self.heap_free.push(object_ref.heap_address);
+            })
+    }
+
+    /**
+     * Checked counterpart of `heap_get` used while evaluating scripts,
+     * so a dangling reference surfaces as an error instead of a panic.
+     */
+    pub fn try_heap_get(&self, object_ref: ObjectRef) -> Result<&Object, EngineError> {
+        self.heap
+            .get(object_ref.heap_address)
+            .ok_or_else(|| Self::dangling_ref_error(object_ref))
+    }
+
+    pub fn try_heap_get_mut(&mut self, object_ref: ObjectRef) -> Result<&mut Object, EngineError> {
+        self.heap
+            .get_mut(object_ref.heap_address)
+            .ok_or_else(|| Self::dangling_ref_error(object_ref))
+    }
+
+    fn dangling_ref_error(object_ref: ObjectRef) -> EngineError {
+        EngineError::js(format!(
+            "Dangling object reference to heap address {}",
+            object_ref.heap_address
+        ))
+    }
+
+    pub fn heap_free(&mut self, object_ref: ObjectRef) {
+        self.heap.free(object_ref.heap_address);
+    }
+
+    /**
+     * Total number of heap slots, including freed ones waiting for reuse.
+     */
+    pub fn heap_len(&self) -> usize {
+        self.heap.stats().total
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        self.heap.stats()
     }
 
     fn register_module(&mut self, module: impl JSModule + 'static) {
@@ -362,69 +1001,311 @@ impl VM {
 
         module_instance.init(self);
 
+        // pin the prototype now, so scripts reassigning `X` or `X.prototype` can't redirect the engine
+        let name = module_instance.name();
+        if let Some(prototype) = self
+            .global_this
+            .load(self)
+            .get_property(name)
+            .and_then(|value| value.try_as_object())
+            .and_then(|object| object.load(self).get_property(PROTOTYPE))
+        {
+            self.global_this
+                .load_mut(self)
+                .set_internal_slot(Self::intrinsic_prototype_slot(name), prototype);
+        }
+
         self.modules.insert(
             module_instance.name().to_string(),
             Box::new(module_instance),
         );
     }
 
+    /**
+     * Runtime error raised by the node at `location`, tagged with its span and line.
+     */
+    fn located_error(message: String, location: Option<SourceLocation>) -> EngineError {
+        match location {
+            Some(location) => EngineError::js(format!("{} at line {}", message, location.line))
+                .with_span(location.span),
+            None => EngineError::js(message),
+        }
+    }
+
+    /**
+     * JS ToPrimitive: objects are asked for `valueOf`, then `toString`,
+     * and the first non-object result wins.
+     */
+    pub fn to_primitive(&mut self, value: JSValue) -> Result<JSValue, EngineError> {
+        match value {
+            JSValue::Object(object) => self.ordinary_to_primitive(object, ["valueOf", "toString"]),
+            primitive => Ok(primitive),
+        }
+    }
+
+    /**
+     * JS ToPropertyKey: like `to_primitive` with the string hint, so objects are asked
+     * for `toString` before `valueOf`, then the result is converted to a string.
+     */
+    pub fn to_property_key(&mut self, value: JSValue) -> Result<String, EngineError> {
+        let primitive = match value {
+            JSValue::Object(object) => {
+                self.ordinary_to_primitive(object, ["toString", "valueOf"])?
+            }
+            primitive => primitive,
+        };
+
+        Ok(primitive.primitive_to_string())
+    }
+
+    fn ordinary_to_primitive(
+        &mut self,
+        object: ObjectRef,
+        methods: [&str; 2],
+    ) -> Result<JSValue, EngineError> {
+        for method in methods {
+            let Some(function) = self
+                .get_property(object, method)?
+                .and_then(|property| property.try_as_object())
+            else {
+                continue;
+            };
+
+            if function.try_load(self)?.call.is_none() {
+                continue;
+            }
+
+            let result = self.call_function(function, object, vec![])?;
+
+            if !matches!(result, JSValue::Object(_)) {
+                return Ok(result);
+            }
+        }
+
+        Err(EngineError::js(
+            "TypeError: Cannot convert object to primitive value",
+        ))
+    }
+
+    /**
+     * The `typeof` result for an already evaluated value.
+     */
+    pub fn type_of(&self, value: &JSValue) -> Result<&'static str, EngineError> {
+        Ok(match value {
+            JSValue::Undefined => "undefined",
+            JSValue::Number(_) => "number",
+            JSValue::String(_) => "string",
+            JSValue::Boolean(_) => "boolean",
+            JSValue::Object(object) if object.try_load(self)?.call.is_some() => "function",
+            JSValue::Object(_) => "object",
+        })
+    }
+
+    /**
+     * JS ToNumber for any value, running objects through `to_primitive` first.
+     */
+    pub fn to_number(&mut self, value: JSValue) -> Result<f64, EngineError> {
+        Ok(self.to_primitive(value)?.to_number())
+    }
+
+    /**
+     * IsLooselyEqual (`==`): booleans become numbers, objects are compared to
+     * primitives through `to_primitive`, and a string meets a number as a number.
+     */
+    pub fn loose_equals(&mut self, left: JSValue, right: JSValue) -> Result<bool, EngineError> {
+        match (left, right) {
+            (JSValue::Boolean(left), right) => {
+                self.loose_equals(JSValue::Number(if left { 1.0 } else { 0.0 }), right)
+            }
+            (left, JSValue::Boolean(right)) => {
+                self.loose_equals(left, JSValue::Number(if right { 1.0 } else { 0.0 }))
+            }
+            (JSValue::Object(left), JSValue::Object(right)) => Ok(left == right),
+            (JSValue::Undefined, right) | (right, JSValue::Undefined) => {
+                Ok(matches!(right, JSValue::Undefined))
+            }
+            (JSValue::Object(object), primitive) | (primitive, JSValue::Object(object)) => {
+                let converted = self.to_primitive(JSValue::Object(object))?;
+                self.loose_equals(converted, primitive)
+            }
+            (JSValue::String(left), JSValue::Number(right))
+            | (JSValue::Number(right), JSValue::String(left)) => {
+                Ok(JSValue::String(left).to_number() == right)
+            }
+            (left, right) => Ok(left.strict_equals(&right)),
+        }
+    }
+
+    /**
+     * Look up a property on an object, falling back to its prototype chain.
+     */
+    pub fn get_property(
+        &self,
+        object: ObjectRef,
+        key: &str,
+    ) -> Result<Option<JSValue>, EngineError> {
+        let mut current = Some(object);
+
+        while let Some(object) = current {
+            let loaded = object.try_load(self)?;
+
+            if let Some(value) = loaded.get_property(key) {
+                return Ok(Some(value));
+            }
+
+            current = loaded.prototype;
+        }
+
+        Ok(None)
+    }
+
+    /**
+     * Script-visible property read: like `get_property`, but runs getters with `object` as `this`.
+     */
+    pub fn get(&mut self, object: ObjectRef, key: &str) -> Result<JSValue, EngineError> {
+        let mut current = Some(object);
+
+        while let Some(holder) = current {
+            let loaded = holder.try_load(self)?;
+
+            if let Some(attributes) = loaded.property_attributes(key) {
+                if !attributes.is_accessor() {
+                    return Ok(loaded.get_property(key).unwrap_or(JSValue::Undefined));
+                }
+
+                return match attributes.get {
+                    Some(getter) => self.call_function(getter, object, vec![]),
+                    None => Ok(JSValue::Undefined),
+                };
+            }
+
+            current = loaded.prototype;
+        }
+
+        Ok(JSValue::Undefined)
+    }
+
+    /**
+     * Script-visible property write. Setters found on the prototype chain are called,
+     * writes to read-only or getter-only properties are silently ignored.
+     */
+    pub fn put(&mut self, object: ObjectRef, key: &str, value: JSValue) -> Result<(), EngineError> {
+        let mut current = Some(object);
+
+        while let Some(holder) = current {
+            let loaded = holder.try_load(self)?;
+
+            if let Some(attributes) = loaded.property_attributes(key) {
+                if attributes.is_accessor() {
+                    if let Some(setter) = attributes.set {
+                        self.call_function(setter, object, vec![value])?;
+                    }
+
+                    return Ok(());
+                }
+
+                if !attributes.writable {
+                    return Ok(());
+                }
+
+                break;
+            }
+
+            current = loaded.prototype;
+        }
+
+        if ArrayClass::is_array(self, &JSValue::Object(object)) {
+            return ArrayClass::put(self, object, key, value);
+        }
+
+        object.try_load_mut(self)?.set_property(key, value);
+
+        Ok(())
+    }
+
+    fn intrinsic_prototype_slot(name: &str) -> String {
+        format!("%{}.prototype%", name)
+    }
+
+    /**
+     * The prototype the `name` module registered at init, unaffected by later script writes.
+     */
     pub fn global_constructor_prototype(&self, name: &str) -> Option<ObjectRef> {
         self.global_this
             .load(self)
-            .get_property(name)
-            .and_then(|value| value.try_as_object())
-            .and_then(|object| object.load(self).get_property(PROTOTYPE))
+            .get_internal_slot(&Self::intrinsic_prototype_slot(name))
             .and_then(|value| value.try_as_object())
     }
 
     /**
      * Get the value of a variable by searching through the scopes from innermost to outermost.
      * If the variable is not found in any scope, it attempts to retrieve it from the global object.
-     * If still not found, it returns JSValue::Undefined.
+     * If still not found, it returns JSValue::Undefined, or an error when `strict_undeclared` is set.
      */
-    fn get_variable(&self, name: &str) -> JSValue {
+    fn get_variable(&self, name: &str) -> Result<JSValue, EngineError> {
         for scope in self.scopes.iter().rev() {
-            if let Some(value) = scope.variables.get(name) {
-                return value.clone();
+            if let Some(value) = scope.borrow().variables.get(name) {
+                return Ok(value.clone());
             }
         }
 
-        self.global_this
-            .load(self)
-            .get_property(name)
-            .unwrap_or_else(|| JSValue::Undefined)
+        match self.global_this.load(self).get_property(name) {
+            Some(value) => Ok(value),
+            None if self.options.strict_undeclared => {
+                Err(EngineError::js(format!("{} is not defined", name)))
+            }
+            None => Ok(JSValue::Undefined),
+        }
     }
 
-    fn get_current_scope_mut(&mut self) -> &mut Scope {
+    /**
+     * Whether `get_variable` finds `name` in a scope or on the global object.
+     */
+    fn is_declared(&self, name: &str) -> bool {
+        self.scopes
+            .iter()
+            .any(|scope| scope.borrow().variables.contains_key(name))
+            || self.global_this.load(self).get_property(name).is_some()
+    }
+
+    fn get_current_scope_mut(&mut self) -> RefMut<'_, Scope> {
         if self.scopes.is_empty() {
-            self.scopes.push(Scope::new());
+            self.scopes.push(Scope::new_ref());
         }
 
-        self.scopes.last_mut().unwrap()
+        self.scopes.last().unwrap().borrow_mut()
+    }
+
+    /**
+     * Writes one line to the output sink configured with `VmBuilder::with_output`.
+     */
+    pub fn print(&mut self, line: &str) {
+        (self.output)(line);
     }
 
     pub fn get_variable_from_global(&self, name: &str) -> Option<JSValue> {
         self.global_this.load(self).get_property(name)
     }
 
+    /**
+     * Updates the innermost scope declaring `name`, resolving it the same way `get_variable` does.
+     */
     fn assign_variable(&mut self, name: &str, value: JSValue) -> Result<(), EngineError> {
-        for scope in self.scopes.iter_mut().rev() {
+        for scope in self.scopes.iter().rev() {
+            let mut scope = scope.borrow_mut();
+
             if scope.variables.contains_key(name) {
                 scope.variables.insert(name.to_string(), value);
                 return Ok(());
             }
         }
 
-        self.global_this
-            .load_mut(self)
-            .get_property(name)
-            .map(|_| {
-                self.global_this.load_mut(self).set_property(name, value);
-                ()
-            })
-            .ok_or_else(|| {
-                EngineError::js(format!("Tried to assign to undefined variable '{}'", name))
-            })
+        if self.global_this.load(self).get_property(name).is_none() {
+            return Err(EngineError::js(format!("{} is not defined", name)));
+        }
+
+        // goes through `put` so read-only globals such as `undefined` stay unchanged
+        self.put(self.global_this, name, value)
     }
 
     pub fn set_variable(&mut self, name: impl Into<String>, value: JSValue) {
@@ -433,21 +1314,205 @@ impl VM {
             .insert(name.into(), value);
     }
 
+    /**
+     * Run `constructor` as in `new constructor(...args)`.
+     * Native constructors use their `construct` hook, AST functions are called with a fresh `this`.
+     * An object returned by the constructor replaces the freshly allocated one.
+     */
+    pub fn construct(
+        &mut self,
+        constructor: ObjectRef,
+        args: Vec<JSValue>,
+    ) -> Result<JSValue, EngineError> {
+        let prototype = match self
+            .get_property(constructor, PROTOTYPE)?
+            .and_then(|value| value.try_as_object())
+        {
+            Some(prototype) => prototype,
+            None => ObjectClass::prototype(self),
+        };
+
+        let this = Object::new().with_prototype(prototype).alloc(self);
+        let constructor_object = constructor.try_load(self)?;
+
+        let call = CallContext::new(args, this).with_new_target(constructor);
+
+        let result = if let Some(construct) = constructor_object.construct {
+            construct(self, call)?
+        } else if let Some(Call::AST(_)) = constructor_object.call {
+            self.call_with_context(constructor, call)?
+        } else {
+            return Err(EngineError::js(
+                "Tried to construct a non-constructor object",
+            ));
+        };
+
+        match result {
+            JSValue::Object(_) => Ok(result),
+            _ => Ok(JSValue::Object(this)),
+        }
+    }
+
     pub fn call_function(
         &mut self,
         function: ObjectRef,
         this: ObjectRef,
         args: Vec<JSValue>,
     ) -> Result<JSValue, EngineError> {
-        let function_object = function.load(self);
+        self.call_with_context(function, CallContext::new(args, this))
+    }
+
+    /**
+     * Calls a function value obtained from script, e.g. a callback the host kept around.
+     * `this` defaults to `globalThis`; a value that isn't callable is a `TypeError`.
+     * A call made while no script is running gets a fresh step budget.
+     */
+    pub fn call(
+        &mut self,
+        function: &JSValue,
+        this: Option<ObjectRef>,
+        args: Vec<JSValue>,
+    ) -> Result<JSValue, EngineError> {
+        let function = match function {
+            JSValue::Object(function) if function.try_load(self)?.call.is_some() => *function,
+            _ => {
+                return Err(EngineError::js(format!(
+                    "TypeError: {} is not a function",
+                    function.clone().cast_to_string(self)?
+                )));
+            }
+        };
+
+        let this = this.unwrap_or(self.global_this);
+        let _backtraces = self.options.backtraces.map(error::capture_backtraces);
+
+        if self.call_depth == 0 {
+            self.steps = 0;
+        }
+
+        self.call_function(function, this, args)
+    }
+
+    /**
+     * The values `for...of` and spread visit: array elements (holes as `undefined`),
+     * the characters of a string, or what an iterator object (one with a callable
+     * `next`, e.g. `[].entries()`) yields. Anything else is a `TypeError`.
+     */
+    pub fn iterate(&mut self, value: &JSValue) -> Result<Vec<JSValue>, EngineError> {
+        if let JSValue::String(string) = value {
+            return Ok(string
+                .chars()
+                .map(|character| JSValue::string(character.to_string()))
+                .collect());
+        }
+
+        if let JSValue::Object(array) = value
+            && ArrayClass::is_array(self, value)
+        {
+            return ArrayClass::elements(self, *array);
+        }
+
+        if let JSValue::Object(iterator) = value
+            && let Some(next) = self.get(*iterator, "next")?.try_as_object()
+            && next.try_load(self)?.call.is_some()
+        {
+            return self.drain_iterator(*iterator, next);
+        }
+
+        Err(EngineError::js(format!(
+            "TypeError: {} is not iterable",
+            value.clone().cast_to_string(self)?
+        )))
+    }
+
+    /**
+     * Calls `iterator.next()` until a result has a truthy `done`, collecting each `value`.
+     */
+    fn drain_iterator(
+        &mut self,
+        iterator: ObjectRef,
+        next: ObjectRef,
+    ) -> Result<Vec<JSValue>, EngineError> {
+        let mut values = vec![];
+
+        loop {
+            self.step()?;
+
+            let Some(result) = self.call_function(next, iterator, vec![])?.try_as_object() else {
+                return Err(EngineError::js(
+                    "TypeError: Iterator result is not an object",
+                ));
+            };
+
+            if self.get(result, "done")?.is_truthy() {
+                return Ok(values);
+            }
+
+            values.push(self.get(result, "value")?);
+        }
+    }
+
+    /**
+     * `{ ...source }`: copies the own enumerable properties of `source` through `get`,
+     * so getters run. A string spreads its characters to index keys, other
+     * primitives and `undefined` add nothing.
+     */
+    fn copy_spread_properties(
+        &mut self,
+        target: &mut Object,
+        source: JSValue,
+    ) -> Result<(), EngineError> {
+        match source {
+            JSValue::Object(source) => {
+                for key in ObjectClass::own_enumerable_keys(self, source)? {
+                    let value = self.get(source, &key)?;
+                    target.set_property(key, value);
+                }
+            }
+            JSValue::String(_) => {
+                for (index, value) in self.iterate(&source)?.into_iter().enumerate() {
+                    target.set_property(index.to_string(), value);
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Like `call_function`, with a prepared `CallContext`, e.g. one carrying `new_target`.
+     */
+    pub fn call_with_context(
+        &mut self,
+        function: ObjectRef,
+        call_ctx: CallContext,
+    ) -> Result<JSValue, EngineError> {
+        if let Some(limit) = self.options.recursion_limit
+            && self.call_depth >= limit
+        {
+            return Err(EngineError::js("Maximum call stack size exceeded"));
+        }
+
+        self.call_depth += 1;
+        let res = self.invoke_function(function, call_ctx);
+        self.call_depth -= 1;
+
+        res
+    }
+
+    fn invoke_function(
+        &mut self,
+        function: ObjectRef,
+        call_ctx: CallContext,
+    ) -> Result<JSValue, EngineError> {
+        let function_object = function.try_load(self)?;
 
         let call = function_object
             .call
             .as_ref()
             .ok_or_else(|| EngineError::js("Tried to call a non-callable object"))?;
 
-        let call_ctx = CallContext::new(args, this);
-
         match call {
             Call::Native(native_function) => native_function(self, call_ctx),
             Call::AST(ast) => {
@@ -459,7 +1524,27 @@ impl VM {
                     })?
                     .clone();
 
-                self.scopes.push(Scope::new());
+                // the body sees the scopes the function was created in, not the caller's
+                let captured_scopes = function_object.captured_scopes.clone();
+                let caller_scopes = std::mem::replace(&mut self.scopes, captured_scopes);
+                self.scopes.push(Scope::new_ref());
+
+                // a named function expression can refer to itself, parameters shadow the name
+                if let Some(name) = definition.name() {
+                    self.set_variable(name, JSValue::Object(function));
+                }
+
+                // arrow functions keep the `this` and `new.target` of the scope they run in
+                if !definition.is_arrow() {
+                    self.set_variable("this", JSValue::Object(call_ctx.this));
+                    self.set_variable(
+                        NEW_TARGET,
+                        call_ctx
+                            .new_target
+                            .map(JSValue::Object)
+                            .unwrap_or(JSValue::Undefined),
+                    );
+                }
 
                 for (arg_index, arg_name) in definition.arguments.iter().enumerate() {
                     let arg_value = call_ctx
@@ -469,97 +1554,384 @@ impl VM {
                     self.set_variable(arg_name, arg_value);
                 }
 
-                let res = self.execute_statement(&Statement::block(definition.block.body.clone()));
+                // the body gets a scope nested in the parameters' one, so its `let`s can shadow them
+                self.scopes.push(Scope::new_ref());
+                let res = self.execute_block(&definition.block.body);
 
-                self.scopes.pop();
+                self.scopes = caller_scopes;
+                self.exit_current_call = false;
 
                 res
             }
         }
     }
 
-    pub fn execute_expression(&mut self, expression: &Expression) -> Result<JSValue, EngineError> {
-        match expression {
-            Expression::Identifier(identifier) => {
-                let value = match identifier.name.as_str() {
-                    "true" => JSValue::Boolean(true),
-                    "false" => JSValue::Boolean(false),
-                    str => self.get_variable(str),
-                };
-
-                Ok(value)
-            }
-            Expression::Binary(binary) => {
-                if matches!(binary.operator, Token::Equal) {
-                    let right = self.execute_expression(&binary.right)?;
-
-                    if let Some(identifier) = binary.left.try_as_identifier() {
-                        self.assign_variable(&identifier.name, right.clone())?;
-                        return Ok(right);
-                    }
-
-                    if let Some(property_access) = binary.left.try_as_property_access() {
-                        self.execute_expression(&property_access.expression)?
-                            .try_as_object()
-                            .ok_or_else(|| {
-                                EngineError::js(format!(
-                                    "Tried to access property of non-object: {:#?}",
-                                    property_access.expression
-                                ))
-                            })?
-                            .load_mut(self)
-                            .set_property(&property_access.property, right.clone());
+    fn step(&mut self) -> Result<(), EngineError> {
+        self.steps += 1;
 
-                        return Ok(right);
-                    }
+        if let Some(limit) = self.options.step_limit
+            && self.steps > limit
+        {
+            return Err(EngineError::js("execution step limit exceeded"));
+        }
 
-                    if let Some(element_access) = binary.left.try_as_element_access() {
-                        let object = self
-                            .execute_expression(&element_access.expression)?
-                            .try_as_object()
-                            .ok_or_else(|| {
-                                EngineError::js(format!(
-                                    "Tried to access element of non-object: {:#?}",
-                                    element_access.expression
-                                ))
-                            })?;
-
-                        let key = self.execute_expression(&element_access.element)?;
-                        let key_string = key.cast_to_string(self)?;
-
-                        object
-                            .load_mut(self)
-                            .set_property(key_string, right.clone());
+        Ok(())
+    }
 
-                        return Ok(right);
-                    }
+    /**
+     * Convert a value to an object for property access, wrapping primitive strings,
+     * numbers and booleans. Returns None for values that have no object form yet.
+     */
+    pub fn to_object(&mut self, value: JSValue) -> Option<ObjectRef> {
+        match value {
+            JSValue::Object(object) => Some(object),
+            JSValue::String(string) => Some(StringClass::wrap(self, string)),
+            JSValue::Number(number) => Some(NumberClass::wrap(self, number)),
+            JSValue::Boolean(boolean) => Some(BooleanClass::wrap(self, boolean)),
+            _ => None,
+        }
+    }
 
-                    return Err(EngineError::js(format!(
-                        "Invalid left-hand side in assignment: {:#?}",
-                        binary.left
-                    )));
+    /**
+     * Evaluates one link of a member/call chain. None means an optional link
+     * (`a?.b`, `a?.[k]`, `f?.()`) found `undefined`, which skips the rest of the chain,
+     * so `a?.b.c` is `undefined` rather than an error.
+     */
+    fn execute_chain(&mut self, expression: &Expression) -> Result<Option<JSValue>, EngineError> {
+        match expression {
+            Expression::PropertyAccess(_) | Expression::ElementAccess(_) => {
+                match self.resolve_member(expression)? {
+                    Some((object, key)) => Ok(Some(self.get(object, &key)?)),
+                    None => Ok(None),
+                }
+            }
+            Expression::FunctionCall(function_call) => self.execute_call(function_call),
+            expression => Ok(Some(self.execute_expression(expression)?)),
+        }
+    }
+
+    /**
+     * A call link of a member/call chain, None when the chain short-circuits.
+     */
+    fn execute_call(
+        &mut self,
+        function_call: &FunctionCallExpression,
+    ) -> Result<Option<JSValue>, EngineError> {
+        // method calls bind the object the function was read from as `this`
+        let (function, this) = match function_call.function.as_ref() {
+            Expression::PropertyAccess(_) | Expression::ElementAccess(_) => {
+                match self.resolve_member(&function_call.function)? {
+                    Some((object, key)) => (self.get(object, &key)?, object),
+                    None => return Ok(None),
+                }
+            }
+            function => match self.execute_chain(function)? {
+                Some(function) => (function, self.global_this),
+                None => return Ok(None),
+            },
+        };
+
+        if function_call.optional && matches!(function, JSValue::Undefined) {
+            return Ok(None);
+        }
+
+        let callable = match function.try_as_object() {
+            Some(object) => object.try_load(self)?.call.is_some().then_some(object),
+            None => None,
+        };
+
+        let function_object = callable.ok_or_else(|| {
+            Self::located_error(
+                format!("Tried to call non-function: {:#?}", function_call.function),
+                function_call.location,
+            )
+        })?;
+
+        let mut args: Vec<JSValue> = vec![];
+
+        for expr in &function_call.arguments {
+            args.push(self.execute_expression(expr)?);
+        }
+
+        self.call_function(function_object, this, args).map(Some)
+    }
+
+    /**
+     * Evaluate the object and key of a property or element access expression.
+     * Returns None for any other kind of expression, or when the chain it belongs
+     * to short-circuits, see `execute_chain`.
+     */
+    fn resolve_member(
+        &mut self,
+        expression: &Expression,
+    ) -> Result<Option<(ObjectRef, String)>, EngineError> {
+        match expression {
+            Expression::PropertyAccess(property_access) => {
+                let Some(value) = self.execute_chain(&property_access.expression)? else {
+                    return Ok(None);
+                };
+
+                if property_access.optional && matches!(value, JSValue::Undefined) {
+                    return Ok(None);
+                }
+
+                let object = self.to_object(value).ok_or_else(|| {
+                    EngineError::js(format!(
+                        "Tried to access property of non-object: {:#?}",
+                        property_access.expression
+                    ))
+                })?;
+
+                Ok(Some((object, property_access.property.clone())))
+            }
+            Expression::ElementAccess(element_access) => {
+                let Some(value) = self.execute_chain(&element_access.expression)? else {
+                    return Ok(None);
+                };
+
+                if element_access.optional && matches!(value, JSValue::Undefined) {
+                    return Ok(None);
                 }
 
-                let left = self.execute_expression(&binary.left)?;
-                let right = self.execute_expression(&binary.right)?;
+                let object = self.to_object(value).ok_or_else(|| {
+                    EngineError::js(format!(
+                        "Tried to access element of non-object: {:#?}",
+                        element_access.expression
+                    ))
+                })?;
+
+                let key = self.execute_expression(&element_access.element)?;
+                let key_string = self.to_property_key(key)?;
+
+                Ok(Some((object, key_string)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /**
+     * Resolves the left-hand side of an assignment. The object and key of a member
+     * target are evaluated here, before the right-hand side, like JS.
+     */
+    fn resolve_assignment_target(
+        &mut self,
+        left: &Expression,
+    ) -> Result<AssignmentTarget, EngineError> {
+        if let Some(identifier) = left.try_as_identifier() {
+            return Ok(AssignmentTarget::Variable(identifier.name.clone()));
+        }
+
+        if let Some(property_access) = left.try_as_property_access() {
+            let object = self
+                .execute_expression(&property_access.expression)?
+                .try_as_object()
+                .ok_or_else(|| {
+                    EngineError::js(format!(
+                        "Tried to access property of non-object: {:#?}",
+                        property_access.expression
+                    ))
+                })?;
+
+            return Ok(AssignmentTarget::Member(
+                object,
+                property_access.property.clone(),
+            ));
+        }
+
+        if let Some(element_access) = left.try_as_element_access() {
+            let object = self
+                .execute_expression(&element_access.expression)?
+                .try_as_object()
+                .ok_or_else(|| {
+                    EngineError::js(format!(
+                        "Tried to access element of non-object: {:#?}",
+                        element_access.expression
+                    ))
+                })?;
+
+            let key = self.execute_expression(&element_access.element)?;
+            let key_string = self.to_property_key(key)?;
+
+            return Ok(AssignmentTarget::Member(object, key_string));
+        }
+
+        Err(Self::located_error(
+            format!(
+                "Invalid left-hand side in assignment: cannot assign to a {}",
+                left.kind_name()
+            ),
+            left.try_as_function_call().and_then(|call| call.location),
+        ))
+    }
+
+    fn read_assignment_target(
+        &mut self,
+        target: &AssignmentTarget,
+    ) -> Result<JSValue, EngineError> {
+        match target {
+            AssignmentTarget::Variable(name) => self.get_variable(name),
+            AssignmentTarget::Member(object, key) => self.get(*object, key),
+        }
+    }
+
+    fn write_assignment_target(
+        &mut self,
+        target: &AssignmentTarget,
+        value: JSValue,
+    ) -> Result<(), EngineError> {
+        match target {
+            AssignmentTarget::Variable(name) => self.assign_variable(name, value),
+            AssignmentTarget::Member(object, key) => self.put(*object, key, value),
+        }
+    }
+
+    fn execute_binary(&mut self, binary: &BinaryExpression) -> Result<JSValue, EngineError> {
+        if matches!(binary.operator, Token::Equal) {
+            let target = self.resolve_assignment_target(&binary.left)?;
+            let right = self.execute_expression(&binary.right)?;
+            self.write_assignment_target(&target, right.clone())?;
+
+            return Ok(right);
+        }
+
+        // `a &&= b` and friends only evaluate and assign `b` when `a` doesn't decide the result
+        if matches!(
+            binary.operator,
+            Token::AndAndEqual | Token::OrOrEqual | Token::QuestionQuestionEqual
+        ) {
+            let target = self.resolve_assignment_target(&binary.left)?;
+            let current = self.read_assignment_target(&target)?;
+
+            let assign = match binary.operator {
+                Token::AndAndEqual => current.is_truthy(),
+                Token::OrOrEqual => !current.is_truthy(),
+                _ => matches!(current, JSValue::Undefined),
+            };
+
+            if !assign {
+                return Ok(current);
+            }
+
+            let right = self.execute_expression(&binary.right)?;
+            self.write_assignment_target(&target, right.clone())?;
+
+            return Ok(right);
+        }
+
+        let left = self.execute_expression(&binary.left)?;
+
+        // short-circuit, yielding the deciding operand rather than a boolean
+        match binary.operator {
+            Token::AndAnd if !left.is_truthy() => return Ok(left),
+            Token::OrOr if left.is_truthy() => return Ok(left),
+            Token::AndAnd | Token::OrOr => {
+                return self.execute_expression(&binary.right);
+            }
+            _ => {}
+        }
+
+        let right = self.execute_expression(&binary.right)?;
+
+        match binary.operator {
+            Token::EqualEqualEqual => {
+                return Ok(JSValue::Boolean(left.strict_equals(&right)));
+            }
+            Token::BangEqualEqual => {
+                return Ok(JSValue::Boolean(!left.strict_equals(&right)));
+            }
+            Token::EqualEqual => {
+                return Ok(JSValue::Boolean(self.loose_equals(left, right)?));
+            }
+            Token::BangEqual => {
+                return Ok(JSValue::Boolean(!self.loose_equals(left, right)?));
+            }
+            _ => {}
+        }
+
+        let left = self.to_primitive(left)?;
+        let right = self.to_primitive(right)?;
+
+        match binary.operator {
+            Token::Plus => Ok(left.add(&right)),
+            Token::Minus => Ok(left.sub(&right)),
+            Token::Star => Ok(left.multiply(&right)),
+            Token::Slash => Ok(left.divide(&right)),
+            Token::Percent => Ok(left.remainder(&right)),
+            Token::StarStar => Ok(left.pow(&right)),
+            Token::LessThan => Ok(JSValue::Boolean(matches!(
+                left.compare(&right),
+                Some(Ordering::Less)
+            ))),
+            Token::LessThanEqual => Ok(JSValue::Boolean(matches!(
+                left.compare(&right),
+                Some(Ordering::Less | Ordering::Equal)
+            ))),
+            Token::GreaterThan => Ok(JSValue::Boolean(matches!(
+                left.compare(&right),
+                Some(Ordering::Greater)
+            ))),
+            Token::GreaterThanEqual => Ok(JSValue::Boolean(matches!(
+                left.compare(&right),
+                Some(Ordering::Greater | Ordering::Equal)
+            ))),
+            _ => unimplemented!(),
+        }
+    }
+
+    pub fn execute_expression(&mut self, expression: &Expression) -> Result<JSValue, EngineError> {
+        self.step()?;
+
+        match expression {
+            Expression::Identifier(identifier) => {
+                let value = match identifier.name.as_str() {
+                    "true" => JSValue::Boolean(true),
+                    "false" => JSValue::Boolean(false),
+                    str => self.get_variable(str)?,
+                };
+
+                Ok(value)
+            }
+            Expression::Binary(binary) => self.execute_binary(binary),
+            Expression::Unary(unary) if matches!(unary.operator, Token::TypeofKeyword) => {
+                // An undeclared identifier is `"undefined"` even with `strict_undeclared`.
+                let operand = match unary.operand.as_ref() {
+                    Expression::Identifier(identifier)
+                        if !matches!(identifier.name.as_str(), "true" | "false")
+                            && !self.is_declared(&identifier.name) =>
+                    {
+                        JSValue::Undefined
+                    }
+                    operand => self.execute_expression(operand)?,
+                };
+
+                Ok(JSValue::string(self.type_of(&operand)?))
+            }
+            Expression::Unary(unary) => {
+                let operand = self.execute_expression(&unary.operand)?;
+                let operand = self.to_number(operand)?;
 
-                match binary.operator {
-                    Token::Plus => Ok(left.add(&right)),
-                    Token::Minus => Ok(left.sub(&right)),
-                    Token::Star => Ok(left.multiply(&right)),
-                    Token::Slash => Ok(left.divide(&right)),
+                match unary.operator {
+                    Token::Minus => Ok(JSValue::Number(-operand)),
+                    Token::Plus => Ok(JSValue::Number(operand)),
                     _ => unimplemented!(),
                 }
             }
             Expression::NumericLiteral(numeric) => Ok(JSValue::Number(numeric.value)),
+            Expression::StringLiteral(string) => Ok(JSValue::string(&string.value)),
             Expression::ObjectLiteral(object_literal) => {
                 let mut object = ObjectClass::create(self);
 
                 for prop in object_literal.properties.iter() {
                     let name = match &prop.name {
+                        ObjectPropertyName::Spread => {
+                            let source = self.execute_expression(&prop.value)?;
+                            self.copy_spread_properties(&mut object, source)?;
+                            continue;
+                        }
                         ObjectPropertyName::Name(string) => string,
                         ObjectPropertyName::Computed(expression) => {
-                            &self.execute_expression(expression)?.cast_to_string(self)?
+                            let key = self.execute_expression(expression)?;
+                            &self.to_property_key(key)?
                         }
                     };
 
@@ -572,930 +1944,4723 @@ impl VM {
                 let array = ArrayClass::create(self).alloc(self);
 
                 for element in &array_literal.elements {
-                    let value = self.execute_expression(&element)?;
-                    ArrayClass::push(self, CallContext::new(vec![value], array.clone()))?;
+                    let values = match element {
+                        Expression::Spread(spread) => {
+                            let iterable = self.execute_expression(&spread.expression)?;
+                            self.iterate(&iterable)?
+                        }
+                        _ => vec![self.execute_expression(element)?],
+                    };
+
+                    ArrayClass::push(self, CallContext::new(values, array))?;
                 }
 
                 Ok(JSValue::Object(array))
             }
-            Expression::PropertyAccess(property_access) => {
-                let value = self
-                    .execute_expression(&property_access.expression)?
-                    .try_as_object()
-                    .ok_or_else(|| {
-                        EngineError::js(format!(
-                            "Tried to access property of non-object: {:#?}",
-                            property_access.expression
-                        ))
-                    })?
-                    .load(self)
-                    .get_property(&property_access.property)
-                    .unwrap_or(JSValue::Undefined);
-
-                Ok(value)
-            }
-            Expression::ElementAccess(element_access) => {
-                let object = self
-                    .execute_expression(&element_access.expression)?
-                    .try_as_object()
-                    .ok_or_else(|| {
-                        EngineError::js(format!(
-                            "Tried to access element of non-object: {:#?}",
-                            element_access.expression
-                        ))
-                    })?;
-
-                let key = self.execute_expression(&element_access.element)?;
-                let key_string = key.cast_to_string(self)?;
-
-                Ok(object
-                    .load(self)
-                    .get_property(&key_string)
-                    .unwrap_or(JSValue::Undefined))
-            }
-            Expression::FunctionCall(function_call) => {
-                let function_object = self
-                    .execute_expression(&function_call.function)?
+            Expression::PropertyAccess(_) | Expression::ElementAccess(_) => Ok(self
+                .execute_chain(expression)?
+                .unwrap_or(JSValue::Undefined)),
+            Expression::FunctionCall(function_call) => Ok(self
+                .execute_call(function_call)?
+                .unwrap_or(JSValue::Undefined)),
+            Expression::New(new_expression) => {
+                let constructor = self
+                    .execute_expression(&new_expression.constructor)?
                     .try_as_object()
                     .ok_or_else(|| {
                         EngineError::js(format!(
-                            "Tried to call non-function: {:#?}",
-                            function_call.function
+                            "Tried to construct non-object: {:#?}",
+                            new_expression.constructor
                         ))
                     })?;
 
                 let mut args: Vec<JSValue> = vec![];
 
-                for expr in &function_call.arguments {
-                    args.push(self.execute_expression(&expr)?);
+                for expr in &new_expression.arguments {
+                    args.push(self.execute_expression(expr)?);
                 }
 
-                self.exit_current_call = false;
-                self.call_function(function_object, self.global_this, args)
+                self.construct(constructor, args)
+            }
+            Expression::Conditional(conditional) => {
+                if self.execute_expression(&conditional.test)?.is_truthy() {
+                    self.execute_expression(&conditional.consequent)
+                } else {
+                    self.execute_expression(&conditional.alternate)
+                }
             }
+            Expression::Spread(_) => Err(EngineError::js(
+                "Spread syntax is only supported in array literals",
+            )),
+            Expression::NewTarget => Ok(self
+                .scopes
+                .iter()
+                .rev()
+                .find_map(|scope| scope.borrow().variables.get(NEW_TARGET).cloned())
+                .unwrap_or(JSValue::Undefined)),
             Expression::FunctionDefinition(function_definition) => Ok(JSValue::Object(
                 FunctionClass::create_from_ast(self, function_definition.clone()).alloc(self),
             )),
         }
     }
 
+    /**
+     * Runs `body` in order, stopping early on `return`, `break` or `continue`.
+     * Borrows the statements so calling a function never copies its AST.
+     */
+    fn execute_block(&mut self, body: &[Statement]) -> Result<JSValue, EngineError> {
+        for statement in body {
+            let value = self.execute_statement(statement)?;
+
+            if self.exit_current_call || self.loop_signal.is_some() {
+                return Ok(value);
+            }
+        }
+
+        Ok(JSValue::Undefined)
+    }
+
+    /**
+     * Runs a `{ ... }` block in a scope of its own, so its `let`s don't leak out of it.
+     */
+    fn execute_scoped_block(&mut self, block: &BlockStatement) -> Result<JSValue, EngineError> {
+        self.scopes.push(Scope::new_ref());
+        let result = self.execute_block(&block.body);
+        self.scopes.pop();
+
+        result
+    }
+
+    /**
+     * Runs a `for` loop inside the scope pushed for it. Every iteration swaps that scope
+     * for a copy before the update clause, so closures created during an iteration
+     * keep seeing that iteration's bindings.
+     */
+    fn execute_for(&mut self, for_statement: &ForStatement) -> Result<JSValue, EngineError> {
+        if let Some(init) = &for_statement.init {
+            self.execute_statement(init)?;
+        }
+
+        let mut first = true;
+
+        loop {
+            let iteration = self.scopes.last().unwrap().borrow().clone();
+            *self.scopes.last_mut().unwrap() = Rc::new(RefCell::new(iteration));
+
+            // `continue` lands here too, so the update clause still runs
+            if !first && let Some(update) = &for_statement.update {
+                self.execute_expression(update)?;
+            }
+
+            first = false;
+
+            if let Some(condition) = &for_statement.condition
+                && !self.execute_expression(condition)?.is_truthy()
+            {
+                break;
+            }
+
+            let value = self.execute_statement(&for_statement.body)?;
+
+            if self.exit_current_call {
+                return Ok(value);
+            }
+
+            if let Some(LoopSignal::Break) = self.loop_signal.take() {
+                break;
+            }
+        }
+
+        Ok(JSValue::Undefined)
+    }
+
+    /**
+     * Runs `for...of` over the values from `VM::iterate`, taken up front. Each iteration
+     * binds the value in a fresh scope, like the per-iteration copies of `execute_for`.
+     */
+    fn execute_for_of(&mut self, for_of: &ForOfStatement) -> Result<JSValue, EngineError> {
+        let iterable = self.execute_expression(&for_of.iterable)?;
+
+        for value in self.iterate(&iterable)? {
+            self.scopes.push(Scope::new_ref());
+            self.set_variable(for_of.binding.clone(), value);
+            let result = self.execute_statement(&for_of.body);
+            self.scopes.pop();
+
+            let value = result?;
+
+            if self.exit_current_call {
+                return Ok(value);
+            }
+
+            if let Some(LoopSignal::Break) = self.loop_signal.take() {
+                break;
+            }
+        }
+
+        Ok(JSValue::Undefined)
+    }
+
+    /**
+     * Runs `try`/`catch`/`finally`. There is no `throw` yet, so `catch` receives runtime
+     * errors, bound as their message. `finally` always runs. A `return`, `break` or error
+     * pending from the earlier blocks resumes after it, unless `finally` itself returns,
+     * breaks or fails, which then wins as in JS. Each block is scoped like a block statement,
+     * with the `catch` binding in a scope around its block.
+     */
+    fn execute_try(&mut self, try_statement: &TryStatement) -> Result<JSValue, EngineError> {
+        let mut result = self.execute_scoped_block(&try_statement.block);
+
+        if let Some(handler) = &try_statement.handler
+            && let Err(error) = &result
+            && error.kind() == ErrorKind::Runtime
+        {
+            let message = JSValue::string(error.message());
+
+            self.scopes.push(Scope::new_ref());
+
+            if let Some(param) = &handler.param {
+                self.set_variable(param.clone(), message);
+            }
+
+            result = self.execute_scoped_block(&handler.body);
+            self.scopes.pop();
+        }
+
+        let Some(finalizer) = &try_statement.finalizer else {
+            return result;
+        };
+
+        let exit_current_call = std::mem::take(&mut self.exit_current_call);
+        let loop_signal = self.loop_signal.take();
+
+        let value = self.execute_scoped_block(finalizer)?;
+
+        if self.exit_current_call || self.loop_signal.is_some() {
+            return Ok(value);
+        }
+
+        self.exit_current_call = exit_current_call;
+        self.loop_signal = loop_signal;
+
+        result
+    }
+
     pub fn execute_statement(&mut self, statement: &Statement) -> Result<JSValue, EngineError> {
+        self.step()?;
+
         match statement {
             Statement::Let(let_statement) => {
-                let value = self.execute_expression(&let_statement.value)?;
-                self.set_variable(let_statement.name.clone(), value);
+                for declaration in &let_statement.declarations {
+                    let value = match &declaration.value {
+                        Some(value) => self.execute_expression(value)?,
+                        None => JSValue::Undefined,
+                    };
+
+                    if self.options.global_lets && self.scopes.len() == 1 {
+                        self.global_this
+                            .load_mut(self)
+                            .set_property(declaration.name.clone(), value.clone());
+                    }
+
+                    self.set_variable(declaration.name.clone(), value);
+                }
+
                 Ok(JSValue::Undefined)
             }
             Statement::Expression(expression_statement) => {
                 self.execute_expression(&expression_statement.expression)
             }
             Statement::Return(return_statement) => {
-                let return_value = self.execute_expression(&return_statement.expression);
+                let return_value = match &return_statement.expression {
+                    Some(expression) => self.execute_expression(expression),
+                    None => Ok(JSValue::Undefined),
+                };
                 self.exit_current_call = true;
 
                 return_value
             }
-            Statement::Block(block_statement) => {
-                for statement in &block_statement.body {
-                    let value = self.execute_statement(statement)?;
+            Statement::Block(block_statement) => self.execute_scoped_block(block_statement),
+            Statement::For(for_statement) => {
+                // `let`s of the loop head and body live in a scope of their own
+                self.scopes.push(Scope::new_ref());
+                let result = self.execute_for(for_statement);
+                self.scopes.pop();
+
+                result
+            }
+            Statement::ForOf(for_of) => self.execute_for_of(for_of),
+            Statement::Try(try_statement) => self.execute_try(try_statement),
+            Statement::Switch(switch_statement) => {
+                let discriminant = self.execute_expression(&switch_statement.discriminant)?;
+
+                // every `case` is tried in source order before falling back to `default`
+                let mut start = None;
+
+                for (index, case) in switch_statement.cases.iter().enumerate() {
+                    if let Some(test) = &case.test
+                        && self.execute_expression(test)?.strict_equals(&discriminant)
+                    {
+                        start = Some(index);
+                        break;
+                    }
+                }
+
+                let start = start.or_else(|| {
+                    switch_statement
+                        .cases
+                        .iter()
+                        .position(|case| case.test.is_none())
+                });
+
+                let Some(start) = start else {
+                    return Ok(JSValue::Undefined);
+                };
+
+                // once matched, fall through the remaining clauses, including `default`
+                for case in &switch_statement.cases[start..] {
+                    for statement in &case.body {
+                        let value = self.execute_statement(statement)?;
+
+                        if self.exit_current_call {
+                            return Ok(value);
+                        }
+
+                        match self.loop_signal {
+                            Some(LoopSignal::Break) => {
+                                self.loop_signal = None;
+                                return Ok(JSValue::Undefined);
+                            }
+                            // `continue` belongs to the enclosing loop
+                            Some(LoopSignal::Continue) => return Ok(JSValue::Undefined),
+                            None => {}
+                        }
+                    }
+                }
+
+                Ok(JSValue::Undefined)
+            }
+            Statement::Break => {
+                self.loop_signal = Some(LoopSignal::Break);
+                Ok(JSValue::Undefined)
+            }
+            Statement::Continue => {
+                self.loop_signal = Some(LoopSignal::Continue);
+                Ok(JSValue::Undefined)
+            }
+            Statement::If(if_statement) => {
+                if self
+                    .execute_expression(&if_statement.condition)?
+                    .is_truthy()
+                {
+                    return self.execute_statement(&if_statement.then);
+                }
+
+                match &if_statement.else_ {
+                    Some(else_) => self.execute_statement(else_),
+                    None => Ok(JSValue::Undefined),
+                }
+            }
+        }
+    }
+
+    pub fn evaluate_source(&mut self, source: &str) -> Result<JSValue, EngineError> {
+        let _backtraces = self.options.backtraces.map(error::capture_backtraces);
+        let ast = ASTParser::parse_from_source(source)?;
+        self.steps = 0;
+
+        let mut result = JSValue::Undefined;
+
+        // the first error aborts the script instead of being replaced by later results
+        for statement in ast.iter() {
+            result = self.execute_statement(statement)?;
+        }
+
+        Ok(result)
+    }
+
+    /**
+     * Parses and executes `source` against the innermost scope, so `let`s and
+     * functions it defines stay visible to later calls. The step budget is only
+     * reset when no script is running, so native functions can use this without
+     * handing the surrounding script a fresh budget.
+     */
+    pub fn run_in_current_scope(&mut self, source: &str) -> Result<JSValue, EngineError> {
+        let _backtraces = self.options.backtraces.map(error::capture_backtraces);
+        let ast = ASTParser::parse_from_source(source)?;
+
+        if self.call_depth == 0 {
+            self.steps = 0;
+        }
+
+        let mut result = JSValue::Undefined;
+
+        for statement in ast.iter() {
+            result = self.execute_statement(statement)?;
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{
+        ast::{ASTParser, Statement},
+        ecma::ArrayClass,
+        error::{EngineError, ErrorKind},
+        heap::{Heap, HeapStats, VecHeap},
+        vm::{CallContext, JSValue, Object, VM, VmBuilder},
+    };
+
+    #[test]
+    fn test_evaluate_numeric_literal() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("42;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 42.0);
+    }
+
+    #[test]
+    fn test_evaluate_without_trailing_semicolon() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("let x = 40; x + 2").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 42.0);
+    }
+
+    #[test]
+    fn test_evaluate_addition() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("5 + 3;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 8.0);
+    }
+
+    #[test]
+    fn test_evaluate_subtraction() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("10 - 4;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 6.0);
+    }
+
+    #[test]
+    fn test_evaluate_multiplication() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("6 * 7;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 42.0);
+    }
+
+    #[test]
+    fn test_evaluate_division() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("20 / 4;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 5.0);
+    }
+
+    #[test]
+    fn test_evaluate_complex_expression() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("2 + 3 * 4;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 14.0); // 2 + (3 * 4) = 14
+    }
+
+    #[test]
+    fn test_evaluate_parenthesized_expression() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("(5 + 3) * 2;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 16.0); // (5 + 3) * 2 = 16
+    }
+
+    #[test]
+    fn test_evaluate_let_statement() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("let x = 42; x;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 42.0);
+    }
+
+    #[test]
+    fn test_evaluate_let_with_expression() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("let y = 10 + 5; y;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 15.0);
+    }
+
+    #[test]
+    fn test_evaluate_variable_in_expression() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("let x = 10; x + 5;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 15.0);
+    }
+
+    #[test]
+    fn test_evaluate_multiple_variables() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("let a = 5; let b = 3; a * b;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 15.0);
+    }
+
+    #[test]
+    fn test_evaluate_chained_operations() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("1 + 2 + 3;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 6.0);
+    }
+
+    #[test]
+    fn test_evaluate_variable_reassignment() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("let x = 10; let x = 20; x;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 20.0);
+    }
+
+    #[test]
+    fn test_inner_function_assigns_outer_variable() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let outer = function () {
+                let count = 1;
+                let bump = function () {
+                    count = count + 1;
+                };
+                bump();
+                bump();
+                return count;
+            };
+            outer();
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 3.0);
+
+        let result = ctx
+            .evaluate_source(
+                "let total = 0; let add = function (n) { total = total + n; }; add(5); total;",
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 5.0);
+    }
+
+    #[test]
+    fn test_reassign_function_parameter() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let x = 100;
+            let inc = function (x) {
+                x = x + 1;
+                return x;
+            };
+            inc(5);
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 6.0);
+
+        // the parameter shadowed the outer `x`, which is untouched
+        let result = ctx.evaluate_source("x;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 100.0);
+    }
+
+    #[test]
+    fn test_assignment_updates_nearest_declaration() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let x = 1;
+            let f = function () {
+                let x = 10;
+                x = 20;
+                return x;
+            };
+            f() + x;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 21.0);
+    }
+
+    #[test]
+    fn test_assignment_to_undeclared_variable_errors() {
+        let mut ctx = VM::new();
+        let error = ctx
+            .evaluate_source("let f = function () { missing = 1; }; f();")
+            .unwrap_err();
+        assert_eq!(error.message(), "missing is not defined");
+    }
+
+    #[test]
+    fn test_error_two_calls_deep_propagates() {
+        let mut ctx = VM::new();
+        let scopes = ctx.scopes.len();
+
+        let error = ctx
+            .evaluate_source(
+                r#"
+            let reached = 0;
+            let inner = function () {
+                missing();
+            };
+            let middle = function () {
+                inner();
+                reached = 1;
+            };
+            middle();
+            reached = 2;
+            reached;
+        "#,
+            )
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Runtime);
+        assert!(error.message().starts_with("Tried to call non-function"));
+
+        // neither the rest of `middle` nor the statements after the call ran
+        let result = ctx.evaluate_source("reached;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 0.0);
+
+        assert_eq!(ctx.scopes.len(), scopes);
+        assert_eq!(ctx.call_depth, 0);
+        assert!(!ctx.exit_current_call);
+    }
+
+    #[test]
+    fn test_repeated_calls_do_not_grow_scopes() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source(
+            r#"
+            let early = function (n) {
+                let local = n;
+                for (let i = 0; i < 10; i = i + 1) {
+                    if (i === n) {
+                        return local;
+                    };
+                };
+                return 0 - 1;
+            };
+            let seen = function () {
+                return leaked;
+            };
+        "#,
+        )
+        .unwrap();
+        let scopes = ctx.scopes.len();
+
+        for n in 0..50 {
+            let result = ctx.evaluate_source(&format!("early({});", n % 3)).unwrap();
+            assert_eq!(result.try_as_number().unwrap(), (n % 3) as f64);
+            assert_eq!(ctx.scopes.len(), scopes);
+        }
+
+        // `local` and the loop counter died with each call
+        let result = ctx
+            .evaluate_source("let probe = function () { let leaked = 1; }; probe(); seen();")
+            .unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+        assert!(matches!(
+            ctx.evaluate_source("local;").unwrap(),
+            JSValue::Undefined
+        ));
+        assert_eq!(ctx.scopes.len(), scopes);
+    }
+
+    #[test]
+    fn test_long_sum_expression() {
+        let mut ctx = VM::new();
+        let source = format!(
+            "{};",
+            (1..=100)
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(" + ")
+        );
+        let result = ctx.evaluate_source(&source).unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 5050.0);
+
+        let nested = format!("{}1{};", "(".repeat(50), " + 1)".repeat(50));
+        let result = ctx.evaluate_source(&nested).unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 51.0);
+    }
+
+    #[test]
+    fn test_call_non_function_reports_line() {
+        let mut ctx = VM::new();
+        let error = ctx
+            .evaluate_source("let a = 1;\nlet b = {};\n\nb.missing(a);")
+            .unwrap_err();
+        assert!(
+            error.message().ends_with(" at line 4"),
+            "{}",
+            error.message()
+        );
+        let span = error.span().unwrap();
+        assert_eq!((span.start, span.end), (24, 36));
+
+        let error = ctx.evaluate_source("let c = 2;\nb();").unwrap_err();
+        assert!(
+            error.message().ends_with(" at line 2"),
+            "{}",
+            error.message()
+        );
+
+        // the line is relative to the source that defined the failing call
+        ctx.evaluate_source("let f = function () {\n\n  return a();\n};")
+            .unwrap();
+        let error = ctx.evaluate_source("f();").unwrap_err();
+        assert!(
+            error.message().ends_with(" at line 3"),
+            "{}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_assignment_to_call_result_errors() {
+        let mut ctx = VM::new();
+        let error = ctx
+            .evaluate_source("let foo = function () { return 1; };\nfoo() = 1;")
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Runtime);
+        assert_eq!(
+            error.message(),
+            "Invalid left-hand side in assignment: cannot assign to a function call at line 2"
+        );
+
+        let error = ctx.evaluate_source("1 = 2;").unwrap_err();
+        assert_eq!(
+            error.message(),
+            "Invalid left-hand side in assignment: cannot assign to a numeric literal"
+        );
+    }
+
+    #[test]
+    fn test_evaluate_complex_with_variables() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source("let a = 2; let b = 3; let c = 4; a + b * c;")
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 14.0); // 2 + (3 * 4) = 14
+    }
+
+    // Function tests
+    #[test]
+    fn test_function_definition() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let f = function() { return 42; };
+                f();
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 42.0);
+    }
+
+    #[test]
+    fn test_named_function_expression_recursion() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let factorial = function fact(n) {
+                    if (n <= 1) { return 1; };
+                    return n * fact(n - 1);
+                };
+                factorial(5);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 120.0);
+
+        // the name is only bound inside the body
+        let result = ctx.evaluate_source("fact;").unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+
+        let result = ctx
+            .evaluate_source("let g = function same(same) { return same; }; g(7);")
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 7.0);
+    }
+
+    #[test]
+    fn test_function_with_parameters() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let add = function(a, b) { return a + b; };
+                add(5, 3);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 8.0);
+    }
+
+    #[test]
+    fn test_function_with_multiple_parameters() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let calc = function(a, b, c) { return a + b * c; };
+                calc(2, 3, 4);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 14.0);
+    }
+
+    #[test]
+    fn test_function_closure() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let x = 10;
+                let f = function(y) { return x + y; };
+                f(5);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 15.0);
+    }
+
+    #[test]
+    fn test_function_no_parameters() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let getVal = function() { return 100; };
+                getVal();
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 100.0);
+    }
+
+    #[test]
+    fn test_function_nested_calls() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let double = function(x) { return x * 2; };
+                let quad = function(x) { return double(double(x)); };
+                quad(5);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 20.0);
+    }
+
+    // Object tests
+    #[test]
+    fn test_object_literal_empty() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("let obj = {}; obj;").unwrap();
+        assert!(result.try_as_object().is_some());
+    }
+
+    #[test]
+    fn test_object_literal_with_properties() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source("let obj = { x: 10, y: 20 };").unwrap();
+        let result = ctx.evaluate_source("obj.x;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 10.0);
+    }
+
+    #[test]
+    fn test_object_property_access() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let person = { age: 25 };
+                person.age;
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 25.0);
+    }
+
+    #[test]
+    fn test_object_property_assignment() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let obj = { val: 10 };
+                obj.val = 20;
+                obj.val;
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 20.0);
+    }
+
+    #[test]
+    fn test_object_nested_properties() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let obj = { a: 1, b: 2, c: 3 };
+                obj.a + obj.b + obj.c;
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 6.0);
+    }
+
+    #[test]
+    fn test_object_dynamic_property_assignment() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let obj = {};
+                obj.newProp = 42;
+                obj.newProp;
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 42.0);
+    }
+
+    // Array tests
+    #[test]
+    fn test_array_literal_empty() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("let arr = []; arr;").unwrap();
+        assert!(result.try_as_object().is_some());
+    }
+
+    #[test]
+    fn test_array_literal_with_elements() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source("let arr = [1, 2, 3];").unwrap();
+        let result = ctx.evaluate_source("arr[0];").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_array_element_access() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source("let arr = [10, 20, 30];").unwrap();
+        let result = ctx.evaluate_source("arr[1];").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 20.0);
+    }
+
+    #[test]
+    fn test_array_element_assignment() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let arr = [1, 2, 3];
+                arr[1] = 99;
+                arr[1];
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 99.0);
+    }
+
+    #[test]
+    fn test_array_with_expressions() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source("let arr = [1 + 1, 2 * 2, 3 + 3];")
+            .unwrap();
+        let result = ctx.evaluate_source("arr[2];").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 6.0);
+    }
+
+    #[test]
+    fn test_array_index_with_variable() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source("let arr = [10, 20, 30];").unwrap();
+        ctx.evaluate_source("let i = 2;").unwrap();
+        let result = ctx.evaluate_source("arr[i];").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 30.0);
+    }
+
+    // Return statement tests
+    #[test]
+    fn test_return_simple() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let f = function() { return 5; };
+                f();
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 5.0);
+    }
+
+    #[test]
+    fn test_return_expression() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let f = function(x) { return x * 2; };
+                f(7);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 14.0);
+    }
+
+    #[test]
+    fn test_return_early() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let f = function() {
+                    return 10;
+                    return 20;
+                };
+                f();
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 10.0);
+    }
+
+    #[test]
+    fn test_return_from_nested_block() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let f = function() { { return 42; } };
+                f();
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 42.0);
+    }
+
+    #[test]
+    fn test_return_with_computation() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let f = function(a, b) { return a * b + 10; };
+                f(3, 4);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 22.0);
+    }
+
+    // Block statement tests
+    #[test]
+    fn test_block_simple() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let f = function() { return 42; };
+                f();
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 42.0);
+    }
+
+    #[test]
+    fn test_block_with_variable() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let f = function() {
+                    let x = 10;
+                    return x;
+                };
+                f();
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 10.0);
+    }
+
+    #[test]
+    fn test_block_multiple_statements() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let f = function() {
+                    let a = 5;
+                    let b = 3;
+                    return a + b;
+                };
+                f();
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 8.0);
+    }
+
+    #[test]
+    fn test_block_nested() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let f = function() {
+                    let x = 1;
+                    let y = 2;
+                    return x + y;
+                };
+                f();
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 3.0);
+    }
+
+    #[test]
+    fn test_block_in_function() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let f = function() {
+                    let x = 10;
+                    let y = 20;
+                    return 30;
+                };
+                f();
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 30.0);
+    }
+
+    // Combined tests
+    #[test]
+    fn test_function_returning_object() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source(
+            r#"
+            let f = function() { return { val: 42 }; };
+        "#,
+        )
+        .unwrap();
+        let result = ctx.evaluate_source("f().val;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 42.0);
+    }
+
+    #[test]
+    fn test_function_returning_array() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source(
+            r#"
+            let f = function() { return [1, 2, 3]; };
+        "#,
+        )
+        .unwrap();
+        ctx.evaluate_source("let result = f();").unwrap();
+        let result = ctx.evaluate_source("result[1];").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 2.0);
+    }
+
+    #[test]
+    fn test_array_of_functions() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source(
+            r#"
+            let f1 = function() { return 10; };
+            let f2 = function() { return 20; };
+            let arr = [f1, f2];
+        "#,
+        )
+        .unwrap();
+        ctx.evaluate_source("let fn = arr[0];").unwrap();
+        let result = ctx.evaluate_source("fn();").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 10.0);
+    }
+
+    #[test]
+    fn test_object_with_function_property() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let obj = { method: function(x) { return x * 2; } };
+                obj.method(5);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 10.0);
+    }
+
+    #[test]
+    fn test_complex_nested_structure() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source("let obj = { arr: [1, 2, { inner: 42 }] };")
+            .unwrap();
+        ctx.evaluate_source("let arrVal = obj.arr;").unwrap();
+        ctx.evaluate_source("let innerObj = arrVal[2];").unwrap();
+        let result = ctx.evaluate_source("innerObj.inner;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 42.0);
+    }
+
+    #[test]
+    fn test_function_with_block_and_return() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let f = function(x) {
+                    {
+                        let y = x * 2;
+                        return y + 5;
+                    }
+                };
+                f(10);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 25.0);
+    }
+
+    // Nested function tests with returns
+    #[test]
+    fn test_nested_function_simple_return() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let outer = function() {
+                    let inner = function() { return 42; };
+                    return inner();
+                };
+                outer();
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 42.0);
+    }
+
+    #[test]
+    fn test_nested_function_return_with_parameter() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let outer = function(x) {
+                    let inner = function(y) { return x + y; };
+                    return inner(10);
+                };
+                outer(5);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 15.0);
+    }
+
+    #[test]
+    fn test_nested_function_return_function() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source(
+            r#"
+            let makeAdder = function(x) {
+                let inner = function(y) {
+                    let sum = 5 + 3;
+                    return sum;
+                };
+                return inner;
+            };
+        "#,
+        )
+        .unwrap();
+        ctx.evaluate_source("let add5 = makeAdder(5);").unwrap();
+        let result = ctx.evaluate_source("add5(3);").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 8.0);
+    }
+
+    #[test]
+    fn test_nested_function_multiple_levels() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let level1 = function(a) {
+                    let level2 = function(b) {
+                        let level3 = function(c) {
+                            return a + b + c;
+                        };
+                        return level3(3);
+                    };
+                    return level2(2);
+                };
+                level1(1);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 6.0);
+    }
+
+    #[test]
+    fn test_nested_function_early_return() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let outer = function(x) {
+                    let inner = function() { return x * 2; };
+                    return inner();
+                    return 999;
+                };
+                outer(7);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 14.0);
+    }
+
+    #[test]
+    fn test_nested_function_with_computation() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let outer = function(x) {
+                    let inner = function(y) { return y * 2; };
+                    return inner(x) + 10;
+                };
+                outer(5);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 20.0);
+    }
+
+    #[test]
+    fn test_nested_function_return_nested_call() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let double = function(x) { return x * 2; };
+                let quadruple = function(x) {
+                    return double(double(x));
+                };
+                quadruple(3);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 12.0);
+    }
+
+    #[test]
+    fn test_nested_function_closure_with_return() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source(
+            r#"
+            let outer = function(x) {
+                let inner = function() { return 50; };
+                return inner;
+            };
+        "#,
+        )
+        .unwrap();
+        ctx.evaluate_source("let fn = outer(5);").unwrap();
+        let result = ctx.evaluate_source("fn();").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 50.0);
+    }
+
+    #[test]
+    fn test_nested_function_multiple_returns() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let outer = function(x) {
+                    let inner1 = function() { return x + 1; };
+                    let inner2 = function() { return x + 2; };
+                    return inner1() + inner2();
+                };
+                outer(10);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 23.0);
+    }
+
+    #[test]
+    fn test_nested_function_return_with_block() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let outer = function(x) {
+                    let inner = function(y) {
+                        let z = y + 5;
+                        return z * 2;
+                    };
+                    return inner(x);
+                };
+                outer(3);
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 16.0);
+    }
+
+    // Boolean tests
+    #[test]
+    fn test_boolean_literal_true() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("true;").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_boolean_literal_false() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("false;").unwrap();
+        assert!(!result.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_boolean_constructor_with_truthy_values() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("Boolean(1);").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source("Boolean('hello');").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source("Boolean({});").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_boolean_constructor_with_falsy_values() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("Boolean(0);").unwrap();
+        assert!(!result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source("Boolean('');").unwrap();
+        assert!(!result.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_boolean_constructor_with_undefined() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("Boolean();").unwrap();
+        // Boolean() without arguments should return false, matching JavaScript behavior
+        assert!(!result.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_boolean_in_variable() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("let x = true; x;").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_boolean_constructor_matches_js_truthiness() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("Boolean(0 / 0);").unwrap();
+        assert!(!result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source(r#"Boolean("");"#).unwrap();
+        assert!(!result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source("Boolean([]);").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_if_statement_uses_truthiness() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let pick = function(value) {
+                if (value) { return 1; } else { return 0; };
+            };
+            pick([]) * 10000 + pick(0) * 1000 + pick("") * 100 + pick(0 / 0) * 10 + pick("a");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 10001.0);
+    }
+
+    #[test]
+    fn test_if_statement_without_else() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source("let x = 1; if (0) { x = 2; }; x;")
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_if_branches_on_boolean_and_comparison_results() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source(
+            r#"
+            let sign = function (n) {
+                if (n < 0) {
+                    return "negative";
+                } else if (n === 0) {
+                    return "zero";
+                } else {
+                    return "positive";
+                };
+            };
+            let flag = function (value) {
+                if (value) { return "taken"; };
+                return "untaken";
+            };
+        "#,
+        )
+        .unwrap();
+
+        for (source, expected) in [
+            ("sign(0 - 5);", "negative"),
+            ("sign(0);", "zero"),
+            ("sign(3);", "positive"),
+            ("flag(true);", "taken"),
+            ("flag(false);", "untaken"),
+            ("flag(2 >= 2);", "taken"),
+            ("flag(1 !== 1);", "untaken"),
+            ("flag(Boolean(1) && 2 > 1);", "taken"),
+        ] {
+            let result = ctx.evaluate_source(source).unwrap();
+            assert_eq!(result.try_as_string().unwrap(), expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn test_boolean_constructor_with_number() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("Boolean(42);").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source("Boolean(-1);").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_coercion_functions() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source(r#"Number("3.5");"#).unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 3.5);
+
+        let result = ctx.evaluate_source("Number();").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 0.0);
+
+        let result = ctx.evaluate_source(r#"Number("x");"#).unwrap();
+        assert!(result.try_as_number().unwrap().is_nan());
+
+        let result = ctx.evaluate_source("String(true);").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "true");
+
+        let result = ctx.evaluate_source("String(5) + String();").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "5");
+
+        let result = ctx.evaluate_source(r#"Boolean("");"#).unwrap();
+        assert!(!result.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_wrapper_objects() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(r#"let n = new Number("4"); n + 1;"#)
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 5.0);
+
+        let result = ctx.evaluate_source("n === 4;").unwrap();
+        assert!(!result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source("n.toString();").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "4");
+
+        let result = ctx
+            .evaluate_source(r#"let s = new String(12); s.length + s + s.charCodeAt(0);"#)
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "21249");
+
+        // a wrapped `false` is still an object, so truthy
+        let result = ctx
+            .evaluate_source("let b = new Boolean(0); Boolean(b) + b.valueOf() + b.toString();")
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "1false");
+
+        let error = ctx
+            .evaluate_source("let fake = { valueOf: Number.prototype.valueOf }; fake.valueOf();")
+            .unwrap_err();
+        assert_eq!(error.message(), "TypeError: this is not a Number object");
+    }
+
+    #[test]
+    fn test_script_writes_do_not_redirect_intrinsics() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let Point = function (x) { this.x = x; };
+            let p = new Point(1);
+            p.constructor = 1;
+            new Point(2).x;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 2.0);
+
+        let result = ctx
+            .evaluate_source(
+                r#"
+            Array.prototype = {};
+            Array = 1;
+            Object.prototype.constructor = 1;
+            let arr = [1, 2];
+            arr.push(3);
+            arr.length + ({}).toString();
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "3[object Object]");
+
+        let result = ctx.evaluate_source(r#""ab".charCodeAt(1);"#).unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 98.0);
+    }
+
+    // VM options tests
+    #[test]
+    fn test_recursion_limit() {
+        let mut ctx = VmBuilder::new().with_recursion_limit(10).build();
+        let result = ctx.evaluate_source(
+            r#"
+            let f = function(n) { return f(n + 1); };
+            f(0);
+        "#,
+        );
+        assert_eq!(
+            result.unwrap_err().message(),
+            "Maximum call stack size exceeded"
+        );
+        assert_eq!(ctx.call_depth, 0);
+    }
+
+    #[test]
+    fn test_recursion_unlimited_by_default() {
+        // deeper than a 2 MiB test thread allows in debug builds
+        let depth = std::thread::Builder::new()
+            .stack_size(16 << 20)
+            .spawn(|| {
+                let mut ctx = VM::new();
+                let result = ctx
+                    .evaluate_source(
+                        r#"
+                    let n = function(i) {
+                        if (i === 0) { return 0; };
+                        return n(i - 1) + 1;
+                    };
+                    n(150);
+                "#,
+                    )
+                    .unwrap();
+                result.try_as_number().unwrap()
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(depth, 150.0);
+    }
+
+    #[test]
+    fn test_recursion_within_limit() {
+        let mut ctx = VmBuilder::new().with_recursion_limit(3).build();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let a = function() { return 1; };
+                let b = function() { return a() + 1; };
+                let c = function() { return b() + 1; };
+                c();
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 3.0);
+    }
+
+    #[test]
+    fn test_strict_undeclared() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("missing;").unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+
+        let mut ctx = VmBuilder::new().with_strict_undeclared(true).build();
+        let result = ctx.evaluate_source("missing;");
+        assert_eq!(result.unwrap_err().message(), "missing is not defined");
+
+        let result = ctx.evaluate_source("typeof missing;").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "undefined");
+    }
+
+    #[test]
+    fn test_typeof_propagates_errors() {
+        // the statement and `typeof` use up the budget before the identifier is read
+        let mut ctx = VmBuilder::new().with_step_limit(2).build();
+        ctx.evaluate_source("let a = 1;").unwrap();
+        let err = ctx.evaluate_source("typeof a;").unwrap_err();
+        assert_eq!(err.message(), "execution step limit exceeded");
+
+        let mut ctx = VM::new();
+        let err = ctx
+            .evaluate_source("let o = {}; typeof o.missing.deeper;")
+            .unwrap_err();
+        assert!(
+            err.message()
+                .starts_with("Tried to access property of non-object")
+        );
+    }
+
+    #[test]
+    fn test_global_lets() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source("let x = 1;").unwrap();
+        assert!(ctx.get_variable_from_global("x").is_none());
+
+        let mut ctx = VmBuilder::new().with_global_lets(true).build();
+        ctx.evaluate_source("let x = 1;").unwrap();
+        let x = ctx.get_variable_from_global("x").unwrap();
+        assert_eq!(x.try_as_number().unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_native_globals() {
+        fn answer(_vm: &mut VM, _call: CallContext) -> Result<JSValue, EngineError> {
+            Ok(JSValue::Number(42.0))
+        }
+
+        let mut ctx = VmBuilder::new().with_global("answer", answer).build();
+        let result = ctx.evaluate_source("answer() + 1;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 43.0);
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let mut ctx = VmBuilder::new().with_step_limit(50).build();
+        let result = ctx.evaluate_source(
+            r#"
+            let f = function(n) { return f(n + 1); };
+            f(0);
+        "#,
+        );
+        assert_eq!(
+            result.unwrap_err().message(),
+            "execution step limit exceeded"
+        );
+    }
+
+    #[test]
+    fn test_step_limit_resets_per_evaluation() {
+        let mut ctx = VmBuilder::new().with_step_limit(5).build();
+        for _ in 0..10 {
+            let result = ctx.evaluate_source("1 + 2;").unwrap();
+            assert_eq!(result.try_as_number().unwrap(), 3.0);
+        }
+    }
+
+    // Math tests
+    #[test]
+    fn test_math_random_in_range() {
+        let mut ctx = VM::new();
+        for _ in 0..100 {
+            let value = ctx.evaluate_source("Math.random();").unwrap();
+            let value = value.try_as_number().unwrap();
+            assert!((0.0..1.0).contains(&value));
+        }
+    }
+
+    #[test]
+    fn test_math_random_seeded() {
+        let mut first = VmBuilder::new().with_rng_seed(7).build();
+        let mut second = VmBuilder::new().with_rng_seed(7).build();
+
+        for _ in 0..10 {
+            let a = first.evaluate_source("Math.random();").unwrap();
+            let b = second.evaluate_source("Math.random();").unwrap();
+            assert_eq!(a.try_as_number().unwrap(), b.try_as_number().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_call_result_used_before_return() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let one = function() { return 1; };
+                let f = function() {
+                    let a = one();
+                    return a + 1;
+                };
+                f();
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 2.0);
+    }
+
+    #[test]
+    fn test_method_call_binds_this() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let obj = { value: 5, get: function() { return this.value; } };
+                obj.get();
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 5.0);
+    }
+
+    #[test]
+    fn test_array_method_call_binds_this() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let arr = [1, 2];
+                arr.push(3);
+                arr.length;
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 3.0);
+    }
+
+    #[test]
+    fn test_new_with_function_constructor() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let Point = function(x, y) {
+                    this.x = x;
+                    this.y = y;
+                };
+                let p = new Point(1, 2);
+                p.x + p.y;
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 3.0);
+    }
+
+    #[test]
+    fn test_new_inherits_constructor_prototype() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let Counter = function() { this.count = 1; };
+                Counter.prototype = { increment: function() { this.count = this.count + 1; return this.count; } };
+                let counter = new Counter();
+                counter.increment();
+                counter.increment();
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 3.0);
+    }
+
+    #[test]
+    fn test_new_returning_object_replaces_this() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+                let F = function() { this.a = 1; return { a: 2 }; };
+                new F().a;
+            "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 2.0);
+    }
+
+    // Date tests
+    #[test]
+    fn test_date_now_with_fixed_clock() {
+        let mut ctx = VmBuilder::new().with_clock(|| 1_700_000_000_000.0).build();
+        let result = ctx.evaluate_source("Date.now();").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 1_700_000_000_000.0);
+    }
+
+    #[test]
+    fn test_date_defaults_to_clock() {
+        let mut ctx = VmBuilder::new().with_clock(|| 1_700_000_000_000.0).build();
+        let result = ctx.evaluate_source("new Date().getTime();").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 1_700_000_000_000.0);
+
+        let result = ctx.evaluate_source("new Date().getFullYear();").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 2023.0);
+    }
+
+    #[test]
+    fn test_date_to_iso_string() {
+        let mut ctx = VmBuilder::new().with_clock(|| 1_700_000_000_123.0).build();
+        let result = ctx.evaluate_source("new Date().toISOString();").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "2023-11-14T22:13:20.123Z");
+
+        let result = ctx.evaluate_source("new Date(0).toISOString();").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "1970-01-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn test_date_before_epoch() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source("new Date(0 - 86400000).toISOString();")
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "1969-12-31T00:00:00.000Z");
+    }
+
+    // String tests
+    #[test]
+    fn test_string_literal() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("let s = 'hello'; s;").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "hello");
+    }
+
+    #[test]
+    fn test_string_literal_as_element_key() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(r#"let obj = { a: 1 }; obj["a"];"#)
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 1.0);
+    }
+
+    // URI tests
+    #[test]
+    fn test_encode_uri_component() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(r#"encodeURIComponent("a b/c?");"#)
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "a%20b%2Fc%3F");
+
+        let result = ctx
+            .evaluate_source(r#"encodeURIComponent("-_.!~*'()");"#)
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "-_.!~*'()");
+
+        let result = ctx.evaluate_source(r#"encodeURIComponent("é");"#).unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "%C3%A9");
+    }
+
+    #[test]
+    fn test_uri_component_round_trip() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(r#"decodeURIComponent(encodeURIComponent("a b/c?"));"#)
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "a b/c?");
+
+        let result = ctx
+            .evaluate_source(r#"decodeURIComponent("%C3%A9%2f");"#)
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "é/");
+    }
+
+    #[test]
+    fn test_decode_uri_component_malformed() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source(r#"decodeURIComponent("%E0%A4%A");"#);
+        assert_eq!(result.unwrap_err().message(), "URIError: URI malformed");
+
+        let result = ctx.evaluate_source(r#"decodeURIComponent("%FF");"#);
+        assert_eq!(result.unwrap_err().message(), "URIError: URI malformed");
+    }
+
+    #[test]
+    fn test_string_length() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("'hello'.length;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 5.0);
+    }
+
+    #[test]
+    fn test_string_from_char_code() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("String.fromCharCode(65, 66);").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "AB");
+
+        let result = ctx.evaluate_source("String.fromCharCode();").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "");
+
+        // code units wrap modulo 2^16
+        let result = ctx.evaluate_source("String.fromCharCode(65601);").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "A");
+    }
+
+    #[test]
+    fn test_string_char_code_at() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("'AB'.charCodeAt(1);").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 66.0);
+
+        let result = ctx.evaluate_source("'AB'.charCodeAt();").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 65.0);
+
+        let result = ctx.evaluate_source("'AB'.charCodeAt(2);").unwrap();
+        assert!(result.try_as_number().unwrap().is_nan());
+    }
+
+    // Heap tests
+    #[test]
+    fn test_heap_stats_after_free() {
+        let mut ctx = VM::new();
+        let before = ctx.heap_stats();
+        assert_eq!(before.live, before.total);
+        assert_eq!(before.free, 0);
+        assert_eq!(ctx.heap_len(), before.total);
+
+        let first = Object::new().alloc(&mut ctx);
+        let second = Object::new().alloc(&mut ctx);
+
+        let allocated = ctx.heap_stats();
+        assert_eq!(allocated.live, before.live + 2);
+        assert_eq!(allocated.total, before.total + 2);
+
+        ctx.heap_free(first);
+
+        let freed = ctx.heap_stats();
+        assert_eq!(freed.live, allocated.live - 1);
+        assert_eq!(freed.free, 1);
+        assert_eq!(freed.total, allocated.total);
+
+        let reused = Object::new().alloc(&mut ctx);
+        assert_eq!(reused.heap_address, first.heap_address);
+        assert_ne!(reused.heap_address, second.heap_address);
+
+        let after = ctx.heap_stats();
+        assert_eq!(after.live, allocated.live);
+        assert_eq!(after.free, 0);
+        assert_eq!(ctx.heap_len(), allocated.total);
+    }
+
+    #[test]
+    fn test_vec_heap_addresses_on_empty_heap_and_after_frees() {
+        let mut heap = VecHeap::new();
+
+        assert!(heap.get(0).is_none());
+        heap.free(0);
+        assert_eq!(
+            heap.stats(),
+            HeapStats {
+                live: 0,
+                free: 0,
+                total: 0
+            }
+        );
+
+        let first = heap.alloc(Object::new());
+        let last = heap.alloc(Object::new());
+        assert_eq!((first, last), (0, 1));
+
+        // freeing the highest address leaves the other one live and addressable
+        heap.free(last);
+        heap.free(last);
+        assert!(heap.get(first).is_some());
+        assert!(heap.get(last).is_none());
+        assert_eq!(heap.stats().free, 1);
+
+        let reused = heap.alloc(Object::new().with_property("live", JSValue::Boolean(true)));
+        assert_eq!(reused, last);
+        assert!(heap.get(reused).unwrap().get_property("live").is_some());
+    }
+
+    #[test]
+    fn test_dangling_ref_errors_instead_of_panicking() {
+        let mut ctx = VM::new();
+        let obj = ctx.evaluate_source("let obj = { a: 1 }; obj;").unwrap();
+        let obj = obj.try_as_object().unwrap();
+
+        ctx.heap_free(obj);
+
+        let result = ctx.evaluate_source("obj.a;");
+        assert_eq!(
+            result.unwrap_err().message(),
+            format!(
+                "Dangling object reference to heap address {}",
+                obj.heap_address
+            )
+        );
+
+        let result = ctx.evaluate_source("obj.a = 2;");
+        assert!(result.is_err());
+
+        let result = ctx.evaluate_source("obj();");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_run_in_current_scope_persists_bindings() {
+        let mut ctx = VM::new();
+        ctx.run_in_current_scope("let a=1;").unwrap();
+        let result = ctx.run_in_current_scope("a+1;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 2.0);
+
+        ctx.run_in_current_scope("let double = function(n) { return n * 2; };")
+            .unwrap();
+        let result = ctx.run_in_current_scope("double(a + 1);").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 4.0);
+    }
+
+    #[test]
+    fn test_run_in_current_scope_sees_function_locals() {
+        fn peek(vm: &mut VM, _call: CallContext) -> Result<JSValue, EngineError> {
+            vm.run_in_current_scope("local * 10;")
+        }
+
+        let mut ctx = VmBuilder::new().with_global("peek", peek).build();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let f = function() { let local = 4; return peek(); };
+            f();
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 40.0);
+    }
+
+    #[test]
+    fn test_run_in_current_scope_step_limit() {
+        fn nested(vm: &mut VM, _call: CallContext) -> Result<JSValue, EngineError> {
+            vm.run_in_current_scope("1;")
+        }
+
+        let mut ctx = VmBuilder::new()
+            .with_step_limit(5)
+            .with_global("nested", nested)
+            .build();
+        for _ in 0..10 {
+            let result = ctx.run_in_current_scope("1 + 2;").unwrap();
+            assert_eq!(result.try_as_number().unwrap(), 3.0);
+        }
+
+        // a nested run keeps using the budget of the script that called it
+        let err = ctx
+            .run_in_current_scope("nested(); nested(); nested(); nested();")
+            .unwrap_err();
+        assert_eq!(err.message(), "execution step limit exceeded");
+    }
+
+    #[test]
+    fn test_object_plus_number_uses_to_string() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("let o = {}; o + 1;").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "[object Object]1");
+    }
+
+    #[test]
+    fn test_custom_value_of_in_arithmetic() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let money = { valueOf: function() { return 40; } };
+            money + 2;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 42.0);
+
+        let result = ctx.evaluate_source("money * 2 - money / 4;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 70.0);
+
+        let result = ctx.evaluate_source("money > 39;").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_value_of_preferred_over_to_string() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let both = {
+                valueOf: function() { return 1; },
+                toString: function() { return "str"; }
+            };
+            both + 1;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 2.0);
+    }
+
+    #[test]
+    fn test_object_without_primitive_conversion_errors() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source(
+            r#"
+            let bad = { valueOf: function() { return {}; }, toString: function() { return {}; } };
+            bad + 1;
+        "#,
+        );
+        assert_eq!(
+            result.unwrap_err().message(),
+            "TypeError: Cannot convert object to primitive value"
+        );
+    }
+
+    #[test]
+    fn test_string_concatenation_and_comparison() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source(r#""a" + 1 + 2;"#).unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "a12");
+
+        let result = ctx.evaluate_source(r#""abc" < "abd";"#).unwrap();
+        assert!(result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source("2 <= 2;").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source("0 / 0 >= 0;").unwrap();
+        assert!(!result.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_unary_minus_and_plus() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("let x = 5; -x * 2 - -1;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), -9.0);
+
+        let result = ctx.evaluate_source(r#"+"42" + 1;"#).unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 43.0);
+    }
+
+    #[test]
+    fn test_to_number_primitives() {
+        let cases = [
+            (JSValue::Number(1.5), 1.5),
+            (JSValue::Boolean(true), 1.0),
+            (JSValue::Boolean(false), 0.0),
+            (JSValue::string(""), 0.0),
+            (JSValue::string(" \n\t "), 0.0),
+            (JSValue::string(" 42 "), 42.0),
+            (JSValue::string("-1.5e2"), -150.0),
+            (JSValue::string("+.5"), 0.5),
+            (JSValue::string("0x1F"), 31.0),
+            (JSValue::string("0o17"), 15.0),
+            (JSValue::string("0b101"), 5.0),
+            (JSValue::string("-Infinity"), f64::NEG_INFINITY),
+        ];
+
+        for (value, expected) in cases {
+            assert_eq!(value.to_number(), expected, "{value:?}");
+        }
+
+        for nan in [
+            JSValue::Undefined,
+            JSValue::string("abc"),
+            JSValue::string("1px"),
+            JSValue::string("inf"),
+            JSValue::string("NaN"),
+            JSValue::string("-0x10"),
+            JSValue::string("0x"),
+            JSValue::string("1_000"),
+        ] {
+            assert!(nan.to_number().is_nan(), "{nan:?}");
+        }
+    }
+
+    #[test]
+    fn test_to_number_objects() {
+        let mut ctx = VM::new();
+        let cases = [
+            ("+({ valueOf: function () { return 7; } });", 7.0),
+            (r#"+({ toString: function () { return "8"; } });"#, 8.0),
+            (r#"[1, 2, 3].indexOf(3, "1");"#, 2.0),
+            (r#""abc".charCodeAt("1");"#, 98.0),
+        ];
+
+        for (source, expected) in cases {
+            let result = ctx.evaluate_source(source).unwrap();
+            assert_eq!(result.try_as_number().unwrap(), expected, "{source}");
+        }
+
+        let result = ctx.evaluate_source("+({});").unwrap();
+        assert!(result.try_as_number().unwrap().is_nan());
+
+        let result = ctx
+            .evaluate_source(r#"String.fromCharCode("65", true);"#)
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "A\u{1}");
+    }
+
+    #[test]
+    fn test_negative_zero() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("-0;").unwrap();
+        let value = result.try_as_number().unwrap();
+        assert!(value == 0.0 && value.is_sign_negative());
+
+        let result = ctx.evaluate_source("Object.is(-0, 0);").unwrap();
+        assert!(!result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source(r#""" + -0;"#).unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "0");
+    }
+
+    #[test]
+    fn test_negative_infinity() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("1 / -0;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), f64::NEG_INFINITY);
+
+        let result = ctx
+            .evaluate_source("Object.is(-Infinity, 1 / -0);")
+            .unwrap();
+        assert!(result.try_as_boolean().unwrap());
+
+        let result = ctx
+            .evaluate_source(r#""" + -Infinity + " " + NaN;"#)
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "-Infinity NaN");
+    }
+
+    #[test]
+    fn test_object_is_same_value() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("Object.is(NaN, NaN);").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source("Object.is(0, -0);").unwrap();
+        assert!(!result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source("Object.is(1, 1);").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source(r#"Object.is("1", 1);"#).unwrap();
+        assert!(!result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source("let o = {}; Object.is(o, o);").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source("Object.is({}, {});").unwrap();
+        assert!(!result.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_strict_equality() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("NaN === NaN;").unwrap();
+        assert!(!result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source("0 === -0;").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source(r#""1" !== 1;"#).unwrap();
+        assert!(result.try_as_boolean().unwrap());
+
+        let result = ctx.evaluate_source("let a = []; a === a;").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_remainder() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("1 + 7 % 4 * 2;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 7.0);
+
+        let result = ctx.evaluate_source("-7 % 3;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), -1.0);
+
+        let result = ctx.evaluate_source("5.5 % 2;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 1.5);
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let mut ctx = VmBuilder::new().with_strict_undeclared(true).build();
+
+        let err = ctx.evaluate_source("let a = #;").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Lexer);
+        assert!(err.span().is_some());
+
+        let err = ctx.evaluate_source("let = 1;").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Ast);
+
+        let err = ctx.evaluate_source("missing;").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Runtime);
+        assert_eq!(err.message(), "missing is not defined");
+    }
+
+    fn array_numbers(ctx: &VM, value: &JSValue) -> Vec<f64> {
+        ArrayClass::elements(ctx, value.try_as_object().unwrap())
+            .unwrap()
+            .iter()
+            .map(|element| element.try_as_number().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_array_shift() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source("let arr = [1, 2, 3]; arr.shift();")
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 1.0);
+
+        let arr = ctx.evaluate_source("arr;").unwrap();
+        assert_eq!(array_numbers(&ctx, &arr), vec![2.0, 3.0]);
+
+        let result = ctx.evaluate_source("arr[2];").unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+
+        let result = ctx.evaluate_source("[].shift();").unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+    }
+
+    #[test]
+    fn test_array_unshift() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source("let arr = [1, 2]; arr.unshift(0);")
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 3.0);
+
+        let arr = ctx.evaluate_source("arr;").unwrap();
+        assert_eq!(array_numbers(&ctx, &arr), vec![0.0, 1.0, 2.0]);
+
+        let result = ctx.evaluate_source("arr.unshift(-2, -1); arr;").unwrap();
+        assert_eq!(
+            array_numbers(&ctx, &result),
+            vec![-2.0, -1.0, 0.0, 1.0, 2.0]
+        );
+    }
+
+    #[test]
+    fn test_array_flat_map() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source("[1,2].flatMap(function(x){return [x, x*10];});")
+            .unwrap();
+        assert_eq!(array_numbers(&ctx, &result), vec![1.0, 10.0, 2.0, 20.0]);
+
+        // non-array results are pushed as-is, nested arrays are only flattened one level
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let out = [5, 6].flatMap(function(x, i) { if (i) { return [[x]]; }; return x + i; });
+            out[0] + out.length;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 7.0);
+
+        let result = ctx.evaluate_source("out[1][0];").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 6.0);
+    }
+
+    #[test]
+    fn test_logical_operators_return_operands() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(r#"let x = 0 || "fallback"; x;"#)
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "fallback");
+
+        let result = ctx.evaluate_source("x = 1 && 2; x;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 2.0);
+
+        let result = ctx.evaluate_source(r#"x = "" && 2; x;"#).unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "");
+
+        let result = ctx.evaluate_source("x = 0 || 0 / 0 || 3 && 4; x;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 4.0);
+    }
+
+    #[test]
+    fn test_logical_operators_short_circuit() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let calls = 0;
+            let touch = function() { calls = calls + 1; return true; };
+            0 && touch();
+            1 || touch();
+            1 && touch();
+            calls;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_logical_operators_in_conditions() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let check = function(a, b) {
+                if (a && b) { return 1; };
+                if (a || b) { return 2; };
+                return 3;
+            };
+            check("x", [])  * 100 + check(0, "y") * 10 + check("", 0 / 0);
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 123.0);
+    }
+
+    #[test]
+    fn test_optional_call_on_missing_method() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source("let obj = {}; obj.maybeFn?.();")
+            .unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+
+        // arguments are not evaluated when the call short-circuits
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let calls = 0;
+            let touch = function() { calls = calls + 1; };
+            obj.maybeFn?.(touch());
+            calls;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_optional_call_on_present_method() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let obj = { n: 20, add: function(x) { return this.n + x; } };
+            obj.add?.(22);
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 42.0);
+    }
+
+    #[test]
+    fn test_optional_call_on_non_function_errors() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("let obj = { notFn: 1 }; obj.notFn?.();");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_optional_property_access() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source("let holder = {}; holder.missing?.x;")
+            .unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+
+        let result = ctx.evaluate_source("holder.missing?.method();").unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+
+        let result = ctx.evaluate_source("let o = { x: 3 }; o?.x;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 3.0);
+    }
+
+    #[test]
+    fn test_leading_dot_number() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source(".5 + 5.;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 5.5);
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("1 / 0;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), f64::INFINITY);
+
+        let result = ctx.evaluate_source("-1 / 0;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), f64::NEG_INFINITY);
+
+        let result = ctx.evaluate_source("0 / 0;").unwrap();
+        assert!(result.try_as_number().unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_division_coerces_operands() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source(r#""6" / "2";"#).unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 3.0);
+
+        let result = ctx.evaluate_source("true / 0;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), f64::INFINITY);
+
+        let result = ctx.evaluate_source(r#""  " / 5;"#).unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 0.0);
+
+        let result = ctx.evaluate_source(r#""abc" / 1;"#).unwrap();
+        assert!(result.try_as_number().unwrap().is_nan());
+
+        let result = ctx.evaluate_source("let o = {}; o.missing / 1;").unwrap();
+        assert!(result.try_as_number().unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_let_with_multiple_bindings() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source("let a = 1, b = a + 1; a * 10 + b;")
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 12.0);
+
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let f = function() { let x = 2, y = x * x, z = y + x; return z; };
+            f();
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 6.0);
+    }
+
+    #[test]
+    fn test_computed_key_with_function_value() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let k = "greet";
+            let o = { [k]: function(x) { return this.n + x; }, n: 40 };
+            o.greet(2);
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 42.0);
+    }
+
+    #[test]
+    fn test_computed_key_is_stringified_once() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let count = 0;
+            let key = { toString: function() { count = count + 1; return "run"; } };
+            let o = { [key]: function() { return 5; } };
+            o.run() + count * 10;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 15.0);
+    }
+
+    #[test]
+    fn test_shorthand_properties_with_computed_keys() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let a = 1;
+            let f = function() { return 2; };
+            let o = { a, ["g"]: f, f };
+            o.a + o.f() * 10 + o.g() * 100;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 221.0);
+    }
+
+    #[test]
+    fn test_element_assignment_evaluation_order() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let log = [];
+            let target = {};
+            let obj = function() { log.push("obj"); return target; };
+            let key = function() { log.push("key"); return "k"; };
+            let value = function() { log.push("value"); return 1; };
+            obj()[key()] = value();
+            log[0] + "," + log[1] + "," + log[2] + "," + log.length + "," + target.k;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "obj,key,value,3,1");
+    }
+
+    #[test]
+    fn test_property_assignment_evaluation_order() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let step = 0;
+            let seen = {};
+            let target = {};
+            let obj = function() { step = step + 1; seen.obj = step; return target; };
+            let value = function() { step = step + 1; seen.value = step; return 7; };
+            obj().p = value();
+            seen.obj * 100 + seen.value * 10 + target.p;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 127.0);
+    }
+
+    #[test]
+    fn test_assignment_to_non_object_skips_right_hand_side() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source(
+            r#"
+            let called = 0;
+            let missing = {}.nothing;
+            missing.x = (function() { called = 1; return 1; })();
+        "#,
+        );
+        assert!(result.is_err());
+
+        let result = ctx.evaluate_source("called;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_for_loop() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                "let sum = 0; for (let i = 1; i <= 4; i = i + 1) { sum = sum + i; }; sum;",
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 10.0);
+    }
+
+    #[test]
+    fn test_continue_runs_for_update_clause() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let sum = 0;
+            let iterations = 0;
+            for (let i = 0; i < 10; i = i + 1) {
+                iterations = iterations + 1;
+                if (i % 2 === 0) { continue; };
+                sum = sum + i;
+            };
+            sum * 100 + iterations;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 2510.0);
+    }
+
+    #[test]
+    fn test_break_exits_for_loop() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let found = 0;
+            for (let i = 1; ; i = i + 1) {
+                if (i * i > 50) { found = i; break; };
+            };
+            found;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 8.0);
+    }
+
+    #[test]
+    fn test_return_from_inside_for_loop() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let first_over = function(limit) {
+                for (let i = 0; i < 100; i = i + 1) {
+                    if (i > limit) { return i; };
+                };
+                return 0 - 1;
+            };
+            first_over(3) * 10 + first_over(200);
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 39.0);
+    }
+
+    #[test]
+    fn test_array_last_index_of() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source("let arr = [1, 2, 3, 2, 1]; arr.lastIndexOf(2);")
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 3.0);
+
+        let result = ctx.evaluate_source("arr.lastIndexOf(9);").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), -1.0);
+
+        let result = ctx.evaluate_source("arr.lastIndexOf(2, 2);").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 1.0);
+
+        let result = ctx.evaluate_source("arr.lastIndexOf(2, -3);").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 1.0);
+
+        let result = ctx.evaluate_source("arr.lastIndexOf(1, -10);").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), -1.0);
+
+        let result = ctx.evaluate_source(r#"arr.lastIndexOf("2");"#).unwrap();
+        assert_eq!(result.try_as_number().unwrap(), -1.0);
+    }
+
+    #[test]
+    fn test_array_index_of() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source("let arr = [1, 2, 3, 2, 1]; arr.indexOf(2);")
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 1.0);
+
+        let result = ctx.evaluate_source("arr.indexOf(2, 2);").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 3.0);
+
+        let result = ctx.evaluate_source("arr.indexOf(1, -1);").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 4.0);
+
+        let result = ctx.evaluate_source("[NaN].indexOf(NaN);").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), -1.0);
+    }
+
+    #[test]
+    fn test_loose_equality() {
+        let mut ctx = VM::new();
+        let cases = [
+            ("1 == 1", true),
+            (r#""1" == 1"#, true),
+            (r#"1 == "1.0""#, true),
+            (r#""a" == "b""#, false),
+            ("1 != 2", true),
+            (r#""" == 0"#, true),
+            ("undefined == undefined", true),
+            ("undefined == 0", false),
+            (r#"undefined != """#, true),
+            ("NaN == NaN", false),
+            ("1 == (0 < 1)", true),
+            (r#"({ valueOf: function () { return 3; } }) == "3""#, true),
+            ("({}) == ({})", false),
+            (r#"({}) == "[object Object]""#, true),
+        ];
+
+        for (source, expected) in cases {
+            let result = ctx.evaluate_source(source).unwrap();
+            assert_eq!(result.try_as_boolean().unwrap(), expected, "{source}");
+        }
+
+        let result = ctx
+            .evaluate_source("let o = {}; let p = o; o == p;")
+            .unwrap();
+        assert!(result.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_if_with_comparison_condition() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let label = "small";
+            let n = 7;
+            if (n > 5 && n != 6) {
+                label = "big";
+            };
+            if (n == "7") {
+                label = label + "!";
+            } else {
+                label = "unreachable";
+            };
+            label;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "big!");
+    }
+
+    #[test]
+    fn test_switch_matches_case_and_breaks() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let describe = function (n) {
+                let out = "";
+                switch (n) {
+                    case 1:
+                        out = "one";
+                        break;
+                    case 2:
+                        out = "two";
+                        break;
+                };
+                return out;
+            };
+            describe(1) + describe(2) + describe(3);
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "onetwo");
+    }
+
+    #[test]
+    fn test_switch_default_in_the_middle() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source(
+            r#"
+            let pick = function (n) {
+                let out = "";
+                switch (n) {
+                    case 1:
+                        out = out + "a";
+                    default:
+                        out = out + "d";
+                    case 2:
+                        out = out + "b";
+                        break;
+                    case 3:
+                        out = out + "c";
+                };
+                return out;
+            };
+        "#,
+        )
+        .unwrap();
+
+        // a later case is still matched although default comes first
+        let result = ctx.evaluate_source("pick(3);").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "c");
+
+        // falls through default into the next case
+        let result = ctx.evaluate_source("pick(1);").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "adb");
+
+        // no match starts at default and falls out of it
+        let result = ctx.evaluate_source("pick(9);").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "db");
+
+        let result = ctx.evaluate_source("pick(2);").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "b");
+    }
+
+    #[test]
+    fn test_switch_uses_strict_equality() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let hit = "none";
+            switch ("1") {
+                case 1:
+                    hit = "number";
+                    break;
+                case "1":
+                    hit = "string";
+            };
+            hit;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "string");
+    }
+
+    #[test]
+    fn test_switch_inside_loop() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let total = 0;
+            for (let i = 0; i < 5; i = i + 1) {
+                switch (i) {
+                    case 1:
+                        continue;
+                    case 3:
+                        break;
+                    default:
+                        total = total + i;
+                };
+                total = total + 100;
+            };
+            total;
+        "#,
+            )
+            .unwrap();
+        // 0, 2, 4 add themselves and 100; 3 breaks the switch only and adds 100
+        assert_eq!(result.try_as_number().unwrap(), 406.0);
+    }
+
+    #[test]
+    fn test_array_at() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("[10, 20, 30].at(-1);").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 30.0);
+
+        let result = ctx.evaluate_source("[10, 20, 30].at(5);").unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+
+        let result = ctx.evaluate_source("[10, 20, 30].at(-4);").unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+
+        let result = ctx.evaluate_source("[10, 20, 30].at();").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 10.0);
+
+        let result = ctx.evaluate_source(r#"[10, 20, 30].at("1.7");"#).unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 20.0);
+    }
+
+    #[test]
+    fn test_string_at() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source(r#""hello".at(-1);"#).unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "o");
+
+        let result = ctx.evaluate_source(r#""hello".at(1);"#).unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "e");
+
+        let result = ctx.evaluate_source(r#""hello".at(5);"#).unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+
+        let result = ctx.evaluate_source(r#""héllo".at(-4);"#).unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "é");
+    }
+
+    #[test]
+    fn test_string_code_point_at() {
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source(r#""A".codePointAt(0);"#).unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 65.0);
+
+        let result = ctx.evaluate_source(r#""a😀".codePointAt(1);"#).unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 0x1F600 as f64);
+
+        let result = ctx.evaluate_source(r#""A".codePointAt(1);"#).unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+
+        let result = ctx.evaluate_source(r#""A".codePointAt(-1);"#).unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+    }
+
+    #[test]
+    fn test_array_keys_iterator() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source(r#"let it = ["a", "b"].keys();"#)
+            .unwrap();
+
+        for expected in [0.0, 1.0] {
+            let result = ctx
+                .evaluate_source("let step = it.next(); step.value;")
+                .unwrap();
+            assert_eq!(result.try_as_number().unwrap(), expected);
+            let done = ctx.evaluate_source("step.done;").unwrap();
+            assert!(!done.try_as_boolean().unwrap());
+        }
+
+        let done = ctx.evaluate_source("it.next().done;").unwrap();
+        assert!(done.try_as_boolean().unwrap());
+        let value = ctx.evaluate_source("it.next().value;").unwrap();
+        assert!(matches!(value, JSValue::Undefined));
+    }
+
+    #[test]
+    fn test_array_values_iterator() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source("let arr = [10, 20]; let it = arr.values();")
+            .unwrap();
+
+        let result = ctx.evaluate_source("it.next().value;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 10.0);
+
+        // elements pushed before exhaustion are still visited
+        ctx.evaluate_source("arr.push(30);").unwrap();
+        let result = ctx
+            .evaluate_source("it.next().value + it.next().value;")
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 50.0);
+
+        let done = ctx.evaluate_source("it.next().done;").unwrap();
+        assert!(done.try_as_boolean().unwrap());
+
+        // once done, the iterator stays done
+        ctx.evaluate_source("arr.push(40);").unwrap();
+        let done = ctx.evaluate_source("it.next().done;").unwrap();
+        assert!(done.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_array_entries_iterator() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source(r#"let it = ["x", "y"].entries();"#)
+            .unwrap();
+
+        let entry = ctx
+            .evaluate_source("let step = it.next(); step.value;")
+            .unwrap();
+        let entry = ArrayClass::elements(&ctx, entry.try_as_object().unwrap()).unwrap();
+        assert_eq!(entry[0].try_as_number().unwrap(), 0.0);
+        assert_eq!(entry[1].try_as_string().unwrap(), "x");
+
+        let result = ctx.evaluate_source("it.next().value[1];").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "y");
+
+        let done = ctx.evaluate_source("it.next().done;").unwrap();
+        assert!(done.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_array_iterator_next_incompatible_receiver() {
+        let mut ctx = VM::new();
+        let error = ctx
+            .evaluate_source("let next = [].values().next; let o = { next }; o.next();")
+            .unwrap_err();
+        assert_eq!(
+            error.message(),
+            "TypeError: next method called on incompatible receiver"
+        );
+    }
+
+    #[test]
+    fn test_custom_heap_counts_allocations() {
+        use crate::heap::{Heap, HeapStats, VecHeap};
+        use std::{cell::Cell, rc::Rc};
+
+        #[derive(Default)]
+        struct CountingHeap {
+            inner: VecHeap,
+            allocs: Rc<Cell<usize>>,
+            frees: Rc<Cell<usize>>,
+        }
+
+        impl Heap for CountingHeap {
+            fn alloc(&mut self, object: Object) -> usize {
+                self.allocs.set(self.allocs.get() + 1);
+                self.inner.alloc(object)
+            }
+
+            fn get(&self, address: usize) -> Option<&Object> {
+                self.inner.get(address)
+            }
+
+            fn get_mut(&mut self, address: usize) -> Option<&mut Object> {
+                self.inner.get_mut(address)
+            }
+
+            fn free(&mut self, address: usize) {
+                self.frees.set(self.frees.get() + 1);
+                self.inner.free(address)
+            }
+
+            fn stats(&self) -> HeapStats {
+                self.inner.stats()
+            }
+        }
+
+        let heap = CountingHeap::default();
+        let allocs = heap.allocs.clone();
+        let frees = heap.frees.clone();
+
+        let mut ctx = VmBuilder::new().with_heap(heap).build();
+        let after_init = allocs.get();
+        assert_eq!(after_init, ctx.heap_stats().live);
+
+        let result = ctx
+            .evaluate_source("let a = {}; let b = [1, 2]; a;")
+            .unwrap();
+        assert_eq!(allocs.get(), after_init + 2);
+
+        ctx.heap_free(result.try_as_object().unwrap());
+        assert_eq!(frees.get(), 1);
+        assert_eq!(ctx.heap_stats().free, 1);
+    }
+
+    #[test]
+    fn test_global_this_self_reference() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source("globalThis.globalThis.globalThis;")
+            .unwrap();
+        assert_eq!(result.try_as_object(), Some(ctx.global_this));
+
+        let result = ctx.evaluate_source("this === globalThis;").unwrap();
+        assert!(result.try_as_boolean().unwrap());
+
+        let result = ctx
+            .evaluate_source("globalThis.Object === Object;")
+            .unwrap();
+        assert!(result.try_as_boolean().unwrap());
+    }
+
+    #[test]
+    fn test_bare_return_short_circuits() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let reached = 0;
+            let f = function(skip) {
+                if (skip) { return; };
+                reached = reached + 1;
+                return 5;
+            };
+            let first = f(1);
+            f(0) + reached * 10;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 15.0);
+
+        let result = ctx.evaluate_source("first;").unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+    }
+
+    #[test]
+    fn test_define_property_descriptors() {
+        let mut ctx = VM::new();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let o = { a: 1 };
+            Object.defineProperty(o, "hidden", { value: 2 });
+            Object.keys(o).length * 10 + o.hidden;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 12.0);
+
+        let result = ctx
+            .evaluate_source(
+                r#"
+            o.hidden = 5;
+            o.hidden;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 2.0);
 
-                    if self.exit_current_call {
-                        return Ok(value);
-                    }
-                }
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let stored = 0;
+            Object.defineProperty(o, "double", {
+                get: function() { return this.a * 2; },
+                set: function(v) { stored = v; },
+                enumerable: true
+            });
+            o.double = 7;
+            o.double * 100 + stored * 10 + Object.keys(o).length;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 272.0);
 
-                Ok(JSValue::Undefined)
-            }
-            Statement::If(_if_statement) => {
-                unimplemented!()
-            }
-        }
+        let err = ctx
+            .evaluate_source(r#"Object.defineProperty(o, "x", { value: 1, get: function() {} });"#)
+            .unwrap_err();
+        assert!(err.message().starts_with("TypeError"));
     }
 
-    pub fn evaluate_source(&mut self, source: &str) -> Result<JSValue, EngineError> {
-        let ast = ASTParser::parse_from_source(source)?;
+    #[test]
+    fn test_array_to_string() {
+        let mut ctx = VM::new();
 
-        ast.iter()
-            .map(|statement| self.execute_statement(statement))
-            .last()
-            .unwrap_or(Ok(JSValue::Undefined))
-    }
-}
+        let result = ctx.evaluate_source(r#""" + [1, 2, 3];"#).unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "1,2,3");
 
-#[cfg(test)]
-mod tests {
-    use crate::vm::VM;
+        let result = ctx.evaluate_source("String([]);").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "");
 
-    #[test]
-    fn test_evaluate_numeric_literal() {
-        let mut ctx = VM::new();
-        let result = ctx.evaluate_source("42;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 42.0);
+        let result = ctx
+            .evaluate_source(r#"[1, [2, 3], undefined, "a"].join(" - ");"#)
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "1 - 2,3 -  - a");
     }
 
     #[test]
-    fn test_evaluate_addition() {
-        let mut ctx = VM::new();
-        let result = ctx.evaluate_source("5 + 3;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 8.0);
-    }
+    fn test_cast_to_string_cycles_and_throwing_to_string() {
+        let mut ctx = VmBuilder::new().with_recursion_limit(100).build();
 
-    #[test]
-    fn test_evaluate_subtraction() {
-        let mut ctx = VM::new();
-        let result = ctx.evaluate_source("10 - 4;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 6.0);
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let a = [1];
+            a.push(a);
+            "" + a;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "1,");
+
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let o = { name: "o" };
+            o.self = o;
+            o.toString = function() { return "<" + this.self + ">"; };
+            String(o);
+        "#,
+            )
+            .unwrap_err();
+        assert_eq!(result.message(), "Maximum call stack size exceeded");
+
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let bad = { toString: function() { return missing.value; } };
+            [bad].join();
+        "#,
+            )
+            .unwrap_err();
+        assert_eq!(result.kind(), ErrorKind::Runtime);
+        assert!(ctx.stringifying.is_empty());
+
+        let result = ctx.evaluate_source(r#"String({ toString: 5 });"#).unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "[object Object]");
     }
 
     #[test]
-    fn test_evaluate_multiplication() {
+    fn test_typeof_evaluates_operand_once() {
         let mut ctx = VM::new();
-        let result = ctx.evaluate_source("6 * 7;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 42.0);
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let reads = 0;
+            let obj = {};
+            Object.defineProperty(obj, "getter", {
+                get: function() { reads = reads + 1; return "value"; }
+            });
+            typeof obj.getter;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "string");
+
+        let result = ctx.evaluate_source("reads;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 1.0);
+
+        let result = ctx
+            .evaluate_source(
+                r#"
+            [typeof 1, typeof "s", typeof true, typeof obj, typeof String,
+             typeof obj.maybe, typeof notDeclared].join(" ");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(
+            result.try_as_string().unwrap(),
+            "number string boolean object function undefined undefined"
+        );
     }
 
     #[test]
-    fn test_evaluate_division() {
+    fn test_for_loop_closures_capture_their_iteration() {
         let mut ctx = VM::new();
-        let result = ctx.evaluate_source("20 / 4;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 5.0);
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let fns = [];
+            for (let i = 0; i < 3; i = i + 1) {
+                fns.push(function() { return i; });
+            };
+            fns[0]() * 100 + fns[1]() * 10 + fns[2]();
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 12.0);
+
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let makeCounter = function() {
+                let count = 0;
+                return function() {
+                    count = count + 1;
+                    return count;
+                };
+            };
+            let counter = makeCounter();
+            counter();
+            counter();
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 2.0);
     }
 
     #[test]
-    fn test_evaluate_complex_expression() {
+    fn test_array_callbacks_receive_the_array() {
         let mut ctx = VM::new();
-        let result = ctx.evaluate_source("2 + 3 * 4;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 14.0); // 2 + (3 * 4) = 14
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let arr = [10, 20, 30];
+            arr.map(function(x, i, array) { return x + i + array.length; }).join(",");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "13,24,35");
+
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let seen = 0;
+            arr.forEach(function(x, i, array) { seen = seen + array[i]; });
+            [
+                seen,
+                arr.filter(function(x, i, array) { return array[i] > 10; }).length,
+                arr.find(function(x) { return x > 15; }),
+                arr.some(function(x, i, array) { return array === arr; }),
+                arr.every(function(x) { return x > 10; })
+            ].join(",");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "60,2,20,true,false");
     }
 
     #[test]
-    fn test_evaluate_parenthesized_expression() {
+    fn test_new_target() {
         let mut ctx = VM::new();
-        let result = ctx.evaluate_source("(5 + 3) * 2;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 16.0); // (5 + 3) * 2 = 16
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let kind = "";
+            let F = function() {
+                kind = typeof new.target;
+                let arrow = () => { return new.target; };
+                this.same = arrow() === F;
+            };
+            let made = new F();
+            let constructed = kind;
+            F();
+            [constructed, kind, made.same].join(",");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "function,undefined,true");
     }
 
     #[test]
-    fn test_evaluate_let_statement() {
+    fn test_conditional_expression() {
         let mut ctx = VM::new();
-        let result = ctx.evaluate_source("let x = 42; x;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 42.0);
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let a = 0;
+            let c = 1;
+            let x = a ? "yes" : "no";
+            a ? 5 : c = 7;
+            [x, c, 1 > 2 ? "first" : 2 > 1 ? "second" : "third"].join(",");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "no,7,second");
     }
 
     #[test]
-    fn test_evaluate_let_with_expression() {
+    fn test_array_nan_search() {
         let mut ctx = VM::new();
-        let result = ctx.evaluate_source("let y = 10 + 5; y;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 15.0);
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let arr = [1, NaN, 0];
+            [arr.includes(NaN), arr.indexOf(NaN), arr.lastIndexOf(NaN), arr.includes(-0),
+             arr.includes(NaN, 2), arr.includes(1, -3), arr.includes("1")].join(",");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(
+            result.try_as_string().unwrap(),
+            "true,-1,-1,true,false,true,false"
+        );
     }
 
     #[test]
-    fn test_evaluate_variable_in_expression() {
-        let mut ctx = VM::new();
-        let result = ctx.evaluate_source("let x = 10; x + 5;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 15.0);
+    fn test_console_log_writes_to_output_sink() {
+        let captured = Rc::new(RefCell::new(vec![]));
+        let sink = captured.clone();
+
+        let mut ctx = VmBuilder::new()
+            .with_output(move |line| sink.borrow_mut().push(line.to_string()))
+            .build();
+
+        ctx.evaluate_source(r#"console.log("hi"); console.log("a", 1, [2, 3]);"#)
+            .unwrap();
+
+        assert_eq!(*captured.borrow(), vec!["hi", "a 1 2,3"]);
     }
 
     #[test]
-    fn test_evaluate_multiple_variables() {
-        let mut ctx = VM::new();
-        let result = ctx.evaluate_source("let a = 5; let b = 3; a * b;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 15.0);
+    fn test_errors_skip_backtrace_when_disabled() {
+        let mut ctx = VmBuilder::new().with_backtraces(false).build();
+
+        let error = ctx.evaluate_source("let f = 1; f();").unwrap_err();
+        assert_eq!(
+            error.backtrace().status(),
+            std::backtrace::BacktraceStatus::Disabled
+        );
+
+        // the setting ends with the call, other errors on the thread are unaffected
+        let _enabled = crate::error::capture_backtraces(true);
+        let error = EngineError::js("outside the VM");
+        assert_eq!(
+            error.backtrace().status(),
+            std::backtrace::BacktraceStatus::Captured
+        );
     }
 
     #[test]
-    fn test_evaluate_chained_operations() {
+    fn test_return_inside_try_finally() {
         let mut ctx = VM::new();
-        let result = ctx.evaluate_source("1 + 2 + 3;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 6.0);
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let log = [];
+            let f = function() {
+                try {
+                    log.push("try");
+                    return "from try";
+                } finally {
+                    log.push("finally");
+                };
+                log.push("after");
+            };
+            [f(), log.join(" ")].join(" / ");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "from try / try finally");
+
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let g = function() {
+                try {
+                    return 1;
+                } finally {
+                    return 2;
+                };
+            };
+            g();
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 2.0);
     }
 
     #[test]
-    fn test_evaluate_variable_reassignment() {
+    fn test_try_catch_runtime_error() {
         let mut ctx = VM::new();
-        let result = ctx.evaluate_source("let x = 10; let x = 20; x;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 20.0);
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let caught = "";
+            let cleaned = 0;
+            for (let i = 0; i < 3; i = i + 1) {
+                try {
+                    if (i === 1) { break; };
+                    let x = 1;
+                    x();
+                } catch (e) {
+                    caught = e;
+                } finally {
+                    cleaned = cleaned + 1;
+                };
+            };
+            cleaned;
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 2.0);
+
+        let caught = ctx.evaluate_source("caught;").unwrap();
+        assert!(
+            caught
+                .try_as_string()
+                .unwrap()
+                .starts_with("Tried to call non-function")
+        );
     }
 
     #[test]
-    fn test_evaluate_complex_with_variables() {
+    fn test_try_blocks_are_scoped() {
         let mut ctx = VM::new();
         let result = ctx
-            .evaluate_source("let a = 2; let b = 3; let c = 4; a + b * c;")
+            .evaluate_source(
+                r#"
+            let seen = [];
+            try { let q = 1; seen.push(q); } catch (e) {};
+            try { let q = 2; q(); } catch (e) { let r = 3; seen.push(r); } finally { let s = 4; seen.push(s); };
+            [seen.join(","), typeof q, typeof r, typeof s].join("|");
+        "#,
+            )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 14.0); // 2 + (3 * 4) = 14
+        assert_eq!(
+            result.try_as_string().unwrap(),
+            "1,3,4|undefined|undefined|undefined"
+        );
     }
 
-    // Function tests
     #[test]
-    fn test_function_definition() {
+    fn test_array_length_assignment() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let f = function() { return 42; };
-                f();
-            "#,
+            let arr = [1, 2, 3];
+            arr.length = 1;
+            [arr.length, arr[1] === undefined, arr.join("-")].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 42.0);
+        assert_eq!(result.try_as_string().unwrap(), "1,true,1");
+
+        let result = ctx
+            .evaluate_source(
+                r#"
+            arr.length = 3;
+            arr[4] = "e";
+            [arr.length, arr.join("-")].join(",");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "5,1----e");
+
+        let err = ctx.evaluate_source("arr.length = -1;").unwrap_err();
+        assert_eq!(err.message(), "RangeError: Invalid array length");
     }
 
     #[test]
-    fn test_function_with_parameters() {
+    fn test_read_only_globals() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let add = function(a, b) { return a + b; };
-                add(5, 3);
-            "#,
+            undefined = 1;
+            NaN = 2;
+            Infinity = 3;
+            globalThis.undefined = 4;
+            [typeof undefined, NaN === NaN, Infinity > 1000].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 8.0);
+        assert_eq!(result.try_as_string().unwrap(), "undefined,false,true");
+
+        let result = ctx
+            .evaluate_source("Object.keys(globalThis).includes(\"undefined\");")
+            .unwrap();
+        assert!(!result.try_as_boolean().unwrap());
     }
 
     #[test]
-    fn test_function_with_multiple_parameters() {
+    fn test_array_reduce_right() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let calc = function(a, b, c) { return a + b * c; };
-                calc(2, 3, 4);
-            "#,
+            let letters = ["a", "b", "c"];
+            let indices = [];
+            let right = letters.reduceRight(function(acc, x, i) {
+                indices.push(i);
+                return acc + x;
+            });
+            [right, indices.join(""), letters.reduce(function(acc, x) { return acc + x; }, ">")].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 14.0);
+        assert_eq!(result.try_as_string().unwrap(), "cba,10,>abc");
+
+        let result = ctx
+            .evaluate_source("[].reduceRight(function(acc, x) { return acc; }, 7);")
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 7.0);
+
+        let err = ctx
+            .evaluate_source("[].reduceRight(function(acc, x) { return acc; });")
+            .unwrap_err();
+        assert_eq!(
+            err.message(),
+            "TypeError: Reduce of empty array with no initial value"
+        );
     }
 
     #[test]
-    fn test_function_closure() {
+    fn test_exponent_operator() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let x = 10;
-                let f = function(y) { return x + y; };
-                f(5);
-            "#,
+            [2 ** -2, 9 ** 0.5, NaN ** 0, 2 ** 3 ** 2, 0.5 ** 2, 1 ** NaN, "3" ** 2].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 15.0);
+        assert_eq!(result.try_as_string().unwrap(), "0.25,3,1,512,0.25,NaN,9");
     }
 
     #[test]
-    fn test_function_no_parameters() {
+    fn test_array_find_last() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let getVal = function() { return 100; };
-                getVal();
-            "#,
+            let arr = [1, 2, 3, 2, 1];
+            let visited = [];
+            let last = arr.findLast(function(x, i) { visited.push(i); return x > 1; });
+            [
+                last,
+                arr.findLastIndex(function(x) { return x > 1; }),
+                visited.join(""),
+                arr.findLast(function(x) { return x > 5; }),
+                arr.findLastIndex(function(x) { return x > 5; })
+            ].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 100.0);
+        assert_eq!(result.try_as_string().unwrap(), "2,3,43,,-1");
+    }
+
+    #[test]
+    fn test_execute_block_statement_from_helper() {
+        let mut ctx = VM::new();
+        ctx.evaluate_source("let total = 0;").unwrap();
+
+        let body = ASTParser::parse_from_source("let x = 1; total = x + 41;").unwrap();
+        let block = Statement::block(body);
+
+        assert!(block.try_as_block().is_some());
+        assert!(matches!(
+            ctx.execute_statement(&block).unwrap(),
+            JSValue::Undefined
+        ));
+
+        let result = ctx.evaluate_source("total;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 42.0);
+
+        // the block's own `let` stays inside it
+        let result = ctx.evaluate_source("typeof x;").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "undefined");
     }
 
     #[test]
-    fn test_function_nested_calls() {
+    fn test_array_to_primitive() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let double = function(x) { return x * 2; };
-                let quad = function(x) { return double(double(x)); };
-                quad(5);
-            "#,
+            let arr = [1, 2];
+            [+[], +[5], arr.valueOf() === arr, [2] * [3], +["7"]].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 20.0);
+        assert_eq!(result.try_as_string().unwrap(), "0,5,true,6,7");
+
+        let result = ctx.evaluate_source("+[1, 2];").unwrap();
+        assert!(result.try_as_number().unwrap().is_nan());
     }
 
-    // Object tests
     #[test]
-    fn test_object_literal_empty() {
+    fn test_embedder_calls_script_function() {
         let mut ctx = VM::new();
-        let result = ctx.evaluate_source("let obj = {}; obj;").unwrap();
-        assert!(result.try_as_object().is_some());
+        let callback = ctx
+            .evaluate_source(
+                r#"
+            let calls = 0;
+            let callback = function(a, b) {
+                calls = calls + 1;
+                return this.prefix + (a + b);
+            };
+            callback;
+        "#,
+            )
+            .unwrap();
+
+        let this = ctx
+            .evaluate_source(r#"({ prefix: "sum=" });"#)
+            .unwrap()
+            .try_as_object();
+
+        let result = ctx
+            .call(
+                &callback,
+                this,
+                vec![JSValue::Number(2.0), JSValue::Number(3.0)],
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "sum=5");
+
+        let calls = ctx.evaluate_source("calls;").unwrap();
+        assert_eq!(calls.try_as_number().unwrap(), 1.0);
+
+        let err = ctx.call(&JSValue::Number(1.0), None, vec![]).unwrap_err();
+        assert_eq!(err.message(), "TypeError: 1 is not a function");
     }
 
     #[test]
-    fn test_object_literal_with_properties() {
-        let mut ctx = VM::new();
-        ctx.evaluate_source("let obj = { x: 10, y: 20 };").unwrap();
-        let result = ctx.evaluate_source("obj.x;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 10.0);
+    fn test_vm_call_resets_step_limit() {
+        let mut ctx = VmBuilder::new().with_step_limit(20).build();
+        let callback = ctx
+            .evaluate_source("let add = function(a, b) { return a + b; }; add;")
+            .unwrap();
+
+        // every host call gets its own budget, so the total can go past the limit
+        for i in 0..50 {
+            let result = ctx
+                .call(
+                    &callback,
+                    None,
+                    vec![JSValue::Number(i as f64), JSValue::Number(1.0)],
+                )
+                .unwrap();
+            assert_eq!(result.try_as_number().unwrap(), i as f64 + 1.0);
+        }
     }
 
     #[test]
-    fn test_object_property_access() {
+    fn test_object_literal_numeric_and_string_keys() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let person = { age: 25 };
-                person.age;
-            "#,
+            let obj = { 0: "a", 1: "b", "two words": "c" };
+            [obj[0], obj["1"], obj["two words"], ({ 0: "x" })[0]].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 25.0);
+        assert_eq!(result.try_as_string().unwrap(), "a,b,c,x");
     }
 
     #[test]
-    fn test_object_property_assignment() {
+    fn test_spread_and_for_of_visit_the_same_values() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let obj = { val: 10 };
-                obj.val = 20;
-                obj.val;
-            "#,
+            let arr = [1, 2, 3];
+            let copy = [...arr];
+            let visited = [];
+            for (let x of arr) {
+                visited.push(x);
+            };
+            [copy.join(","), visited.join(","), copy === arr].join("|");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 20.0);
+        assert_eq!(result.try_as_string().unwrap(), "1,2,3|1,2,3|false");
     }
 
     #[test]
-    fn test_object_nested_properties() {
+    fn test_spread_and_for_of_treat_holes_as_undefined() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let obj = { a: 1, b: 2, c: 3 };
-                obj.a + obj.b + obj.c;
-            "#,
+            let arr = [1];
+            arr.length = 3;
+            let seen = [];
+            for (let x of arr) {
+                seen.push(typeof x);
+            };
+            [[0, ...arr, 4].length, seen.join(",")].join("|");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 6.0);
+        assert_eq!(
+            result.try_as_string().unwrap(),
+            "5|number,undefined,undefined"
+        );
     }
 
     #[test]
-    fn test_object_dynamic_property_assignment() {
+    fn test_for_of_break_continue_and_closures() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let obj = {};
-                obj.newProp = 42;
-                obj.newProp;
-            "#,
+            let fns = [];
+            for (let x of [1, 2, 3, 4]) {
+                if (x === 2) continue;
+                if (x === 4) break;
+                fns.push(function () {
+                    return x;
+                });
+            };
+            fns.map(function (f) {
+                return f();
+            }).join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 42.0);
-    }
+        assert_eq!(result.try_as_string().unwrap(), "1,3");
 
-    // Array tests
-    #[test]
-    fn test_array_literal_empty() {
-        let mut ctx = VM::new();
-        let result = ctx.evaluate_source("let arr = []; arr;").unwrap();
-        assert!(result.try_as_object().is_some());
+        let err = ctx.evaluate_source("for (let x of 1) {};").unwrap_err();
+        assert_eq!(err.message(), "TypeError: 1 is not iterable");
     }
 
     #[test]
-    fn test_array_literal_with_elements() {
+    fn test_string_search_substring() {
         let mut ctx = VM::new();
-        ctx.evaluate_source("let arr = [1, 2, 3];").unwrap();
-        let result = ctx.evaluate_source("arr[0];").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 1.0);
+        let result = ctx
+            .evaluate_source(
+                r#"
+            ["hello".search("ll"), "hello".search("z"), "hello".search(""), "ab".search()].join(",");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "2,-1,0,0");
     }
 
     #[test]
-    fn test_array_element_access() {
+    fn test_number_constants() {
         let mut ctx = VM::new();
-        ctx.evaluate_source("let arr = [10, 20, 30];").unwrap();
-        let result = ctx.evaluate_source("arr[1];").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 20.0);
-    }
 
-    #[test]
-    fn test_array_element_assignment() {
-        let mut ctx = VM::new();
+        let epsilon = ctx.evaluate_source("Number.EPSILON;").unwrap();
+        assert_eq!(epsilon.try_as_number().unwrap(), f64::EPSILON);
+
         let result = ctx
             .evaluate_source(
                 r#"
-                let arr = [1, 2, 3];
-                arr[1] = 99;
-                arr[1];
-            "#,
+            Number.EPSILON = 1;
+            [
+                Number.POSITIVE_INFINITY === Infinity,
+                Number.NEGATIVE_INFINITY === -Infinity,
+                Number.NaN !== Number.NaN,
+                Number.MAX_SAFE_INTEGER + 1 === Number.MAX_SAFE_INTEGER + 2,
+                Number.MAX_SAFE_INTEGER - 1 !== Number.MAX_SAFE_INTEGER,
+                Number.MIN_SAFE_INTEGER === -Number.MAX_SAFE_INTEGER,
+                Number.EPSILON < 1,
+            ].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 99.0);
+        assert_eq!(
+            result.try_as_string().unwrap(),
+            "true,true,true,true,true,true,true"
+        );
     }
 
     #[test]
-    fn test_array_with_expressions() {
+    fn test_empty_and_whitespace_only_sources() {
         let mut ctx = VM::new();
-        ctx.evaluate_source("let arr = [1 + 1, 2 * 2, 3 + 3];")
-            .unwrap();
-        let result = ctx.evaluate_source("arr[2];").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 6.0);
+
+        for source in ["", "   \n ", "\t// nothing here\n/* or here */"] {
+            let result = ctx.evaluate_source(source).unwrap();
+            assert!(matches!(result, JSValue::Undefined));
+
+            let result = ctx.run_in_current_scope(source).unwrap();
+            assert!(matches!(result, JSValue::Undefined));
+        }
     }
 
     #[test]
-    fn test_array_index_with_variable() {
+    fn test_join_stringifies_nested_arrays() {
         let mut ctx = VM::new();
-        ctx.evaluate_source("let arr = [10, 20, 30];").unwrap();
-        ctx.evaluate_source("let i = 2;").unwrap();
-        let result = ctx.evaluate_source("arr[i];").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 30.0);
+
+        let result = ctx.evaluate_source(r#"[1, [2, 3], 4].join(",");"#).unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "1,2,3,4");
+
+        // nested arrays go through their own `toString`, which always uses ","
+        let result = ctx
+            .evaluate_source(r#"[1, [2, [3, [4]]], []].join("|");"#)
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "1|2,3,4|");
     }
 
-    // Return statement tests
     #[test]
-    fn test_return_simple() {
+    fn test_assignment_walks_scopes_then_global_this() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let f = function() { return 5; };
-                f();
-            "#,
+            let outer = 1;
+            globalThis.fromHost = 1;
+            let f = function () {
+                outer = 2;
+                fromHost = 3;
+            };
+            f();
+            [outer, globalThis.fromHost].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 5.0);
+        assert_eq!(result.try_as_string().unwrap(), "2,3");
+
+        // a failed assignment must not leave a global behind
+        ctx.evaluate_source("undeclared = 1;").unwrap_err();
+        assert!(ctx.get_variable_from_global("undeclared").is_none());
     }
 
     #[test]
-    fn test_return_expression() {
+    fn test_parse_int() {
         let mut ctx = VM::new();
+
+        let result = ctx.evaluate_source(r#"parseInt("xyz");"#).unwrap();
+        assert!(result.try_as_number().unwrap().is_nan());
+
         let result = ctx
             .evaluate_source(
                 r#"
-                let f = function(x) { return x * 2; };
-                f(7);
-            "#,
+            [
+                parseInt("10", 2),
+                parseInt("0x1F"),
+                parseInt("  -42px"),
+                parseInt("12", 0),
+                parseInt("0x1F", 16),
+                parseInt("0x1F", 10),
+                parseInt("z", 36),
+                parseInt("7", 1),
+                parseInt(""),
+                Number.parseInt === parseInt,
+            ].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 14.0);
+        assert_eq!(
+            result.try_as_string().unwrap(),
+            "2,31,-42,12,31,0,35,NaN,NaN,true"
+        );
     }
 
     #[test]
-    fn test_return_early() {
+    fn test_arrow_function_bodies() {
         let mut ctx = VM::new();
+
+        let result = ctx.evaluate_source("(() => ({ x: 1 }))().x;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 1.0);
+
+        let result = ctx.evaluate_source("(() => { return 2; })();").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 2.0);
+
         let result = ctx
-            .evaluate_source(
-                r#"
-                let f = function() {
-                    return 10;
-                    return 20;
-                };
-                f();
-            "#,
-            )
+            .evaluate_source("[1, 2, 3].map((x) => x * 2).join(\",\");")
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 10.0);
+        assert_eq!(result.try_as_string().unwrap(), "2,4,6");
+
+        // a block body without `return` still yields `undefined`
+        let result = ctx.evaluate_source("(() => { 1; })();").unwrap();
+        assert!(matches!(result, JSValue::Undefined));
     }
 
     #[test]
-    fn test_return_from_nested_block() {
+    fn test_array_search_from_index() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let f = function() { { return 42; } };
-                f();
-            "#,
+            let arr = [1, 2, 1];
+            [
+                arr.indexOf(1, 1),
+                arr.indexOf(1, -1),
+                arr.indexOf(2, -100),
+                arr.indexOf(1, 100),
+                arr.lastIndexOf(1, 1),
+                arr.lastIndexOf(1, -2),
+                arr.lastIndexOf(1, -100),
+                arr.lastIndexOf(1, Infinity),
+                arr.includes(2, -1),
+                arr.includes(2, -2),
+                arr.includes(1, Infinity),
+            ].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 42.0);
+        assert_eq!(
+            result.try_as_string().unwrap(),
+            "2,2,1,-1,0,0,-1,2,false,true,false"
+        );
     }
 
     #[test]
-    fn test_return_with_computation() {
+    fn test_primitive_to_string_dispatches_through_prototypes() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let f = function(a, b) { return a * b + 10; };
-                f(3, 4);
-            "#,
+            Number.prototype.toString = function () {
+                return "num:" + this.valueOf();
+            };
+            Boolean.prototype.toString = function () {
+                return "bool";
+            };
+            String.prototype.toString = function () {
+                return "str";
+            };
+            [(5).toString(), true.toString(), "s".toString(), "" + 5, String(true)].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 22.0);
+        // implicit conversions stay on the built-in ToString, as in JS
+        assert_eq!(result.try_as_string().unwrap(), "num:5,bool,str,5,true");
     }
 
-    // Block statement tests
     #[test]
-    fn test_block_simple() {
+    fn test_array_of_and_array_constructor() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let f = function() { return 42; };
-                f();
-            "#,
+            [
+                Array.of(7).length,
+                Array(7).length,
+                new Array(3).length,
+                Array.of(1, 2, 3).join("-"),
+                Array(1, 2).join("-"),
+                Array.of().length,
+                Array("7").length,
+                Array.of(7).constructor === Array,
+            ].join(",");
+        "#,
             )
             .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "1,7,3,1-2-3,1-2,0,1,true");
+
+        let err = ctx.evaluate_source("Array(-1);").unwrap_err();
+        assert_eq!(err.message(), "RangeError: Invalid array length");
+    }
+
+    #[test]
+    fn test_evaluate_source_completion_value() {
+        let mut ctx = VM::new();
+
+        let result = ctx.evaluate_source("let x = 2; x * 21;").unwrap();
         assert_eq!(result.try_as_number().unwrap(), 42.0);
+
+        let result = ctx.evaluate_source("x + 1; let y = 3;").unwrap();
+        assert!(matches!(result, JSValue::Undefined));
     }
 
     #[test]
-    fn test_block_with_variable() {
+    fn test_object_spread() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let f = function() {
-                    let x = 10;
-                    return x;
-                };
-                f();
-            "#,
+            let both = { ...{ a: 1 }, b: 2 };
+            let hidden = {};
+            Object.defineProperty(hidden, "secret", { value: 1 });
+            let merged = { ...hidden, ...undefined, ...5, ..."hi", c: 3 };
+            [
+                Object.keys(both).join(""),
+                both.a + both.b,
+                ({ ...{ a: 1 }, a: 2 }).a,
+                ({ a: 2, ...{ a: 1 } }).a,
+                Object.keys(merged).join(""),
+            ].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 10.0);
+        assert_eq!(result.try_as_string().unwrap(), "ab,3,2,1,01c");
     }
 
     #[test]
-    fn test_block_multiple_statements() {
+    fn test_math_max_min() {
         let mut ctx = VM::new();
+
+        let result = ctx.evaluate_source("Math.max();").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), f64::NEG_INFINITY);
+
+        let result = ctx.evaluate_source("Math.min();").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), f64::INFINITY);
+
         let result = ctx
             .evaluate_source(
                 r#"
-                let f = function() {
-                    let a = 5;
-                    let b = 3;
-                    return a + b;
-                };
-                f();
-            "#,
+            [
+                Math.max(1, 5, 3),
+                Math.min(4, -2, 8, 0),
+                Math.max("7", [9]),
+                Math.max(1, NaN, 3),
+                Math.min(undefined, 1),
+                1 / Math.max(-0, 0),
+                1 / Math.min(0, -0),
+            ].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 8.0);
+        assert_eq!(
+            result.try_as_string().unwrap(),
+            "5,-2,9,NaN,NaN,Infinity,-Infinity"
+        );
     }
 
     #[test]
-    fn test_block_nested() {
+    fn test_logical_assignment() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let f = function() {
-                    let x = 1;
-                    let y = 2;
-                    return x + y;
-                };
-                f();
-            "#,
+            let calls = 0;
+            let rhs = function () {
+                calls = calls + 1;
+                return 5;
+            };
+            let a = 0;
+            let b = 1;
+            let c = 0;
+            let d = undefined;
+            let e = 1;
+            let f = 0;
+            a ||= rhs();
+            b ||= rhs();
+            c ??= rhs();
+            d ??= rhs();
+            e &&= rhs();
+            f &&= rhs();
+            [a, b, c, d, e, f, calls].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 3.0);
-    }
+        assert_eq!(result.try_as_string().unwrap(), "5,1,0,5,5,0,3");
 
-    #[test]
-    fn test_block_in_function() {
-        let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let f = function() {
-                    let x = 10;
-                    let y = 20;
-                    return 30;
-                };
-                f();
-            "#,
+            let obj = { count: 0 };
+            let key = "name";
+            obj.count ||= 2;
+            obj[key] ??= "x";
+            obj[key] ??= "y";
+            [obj.count, obj.name, (obj.missing ||= 7)].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 30.0);
+        assert_eq!(result.try_as_string().unwrap(), "2,x,7");
     }
 
-    // Combined tests
     #[test]
-    fn test_function_returning_object() {
+    fn test_to_display_string() {
         let mut ctx = VM::new();
-        ctx.evaluate_source(
-            r#"
-            let f = function() { return { val: 42 }; };
-        "#,
-        )
-        .unwrap();
-        let result = ctx.evaluate_source("f().val;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 42.0);
+
+        let cases = [
+            ("1.5;", "1.5"),
+            ("-0;", "0"),
+            (r#""say \"hi\"\n";"#, r#""say \"hi\"\n""#),
+            ("true;", "true"),
+            ("undefined;", "undefined"),
+            ("[];", "[]"),
+            ("({});", "{}"),
+            (r#"[1, "a", [true]];"#, r#"[1, "a", [true]]"#),
+            (
+                r#"({ b: 1, a: { c: "x" }, 0: 2, "two words": 3 });"#,
+                r#"{ 0: 2, a: { c: "x" }, b: 1, "two words": 3 }"#,
+            ),
+            ("(function () {});", "[Function]"),
+            ("({ f: () => 1 });", "{ f: [Function] }"),
+            (
+                "let o = {}; Object.defineProperty(o, \"g\", { get: function () { return 1; }, enumerable: true }); o;",
+                "{ g: [Getter] }",
+            ),
+            (
+                "let self = { n: 1 }; self.self = self; self;",
+                "{ n: 1, self: [Circular] }",
+            ),
+            ("let arr = [1]; arr.push(arr); arr;", "[1, [Circular]]"),
+            (
+                "let big = []; big.length = 16777217; [big];",
+                "[[Array(16777217)]]",
+            ),
+        ];
+
+        for (source, expected) in cases {
+            let value = ctx.evaluate_source(source).unwrap();
+            assert_eq!(value.to_display_string(&mut ctx), expected, "{}", source);
+        }
     }
 
     #[test]
-    fn test_function_returning_array() {
+    fn test_for_each_side_effects_and_errors() {
         let mut ctx = VM::new();
         ctx.evaluate_source(
             r#"
-            let f = function() { return [1, 2, 3]; };
+            let count = 0;
+            let arr = [1, 2, 3, 4];
+            let bump = function (x) {
+                count = count + x;
+            };
         "#,
         )
         .unwrap();
-        ctx.evaluate_source("let result = f();").unwrap();
-        let result = ctx.evaluate_source("result[1];").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 2.0);
-    }
 
-    #[test]
-    fn test_array_of_functions() {
-        let mut ctx = VM::new();
-        ctx.evaluate_source(
-            r#"
-            let f1 = function() { return 10; };
-            let f2 = function() { return 20; };
-            let arr = [f1, f2];
+        // nothing is allocated on the heap for the results of the callbacks
+        let before = ctx.heap_stats().total;
+        let result = ctx.evaluate_source("arr.forEach(bump);").unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+        assert_eq!(ctx.heap_stats().total, before);
+
+        let count = ctx.evaluate_source("count;").unwrap();
+        assert_eq!(count.try_as_number().unwrap(), 10.0);
+
+        // an error in the callback stops the iteration and reaches the caller
+        let err = ctx
+            .evaluate_source(
+                r#"
+            count = 0;
+            arr.forEach(function (x) {
+                if (x === 3) {
+                    undefined();
+                };
+                count = count + 1;
+            });
         "#,
-        )
-        .unwrap();
-        ctx.evaluate_source("let fn = arr[0];").unwrap();
-        let result = ctx.evaluate_source("fn();").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 10.0);
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Runtime);
+
+        let count = ctx.evaluate_source("count;").unwrap();
+        assert_eq!(count.try_as_number().unwrap(), 2.0);
     }
 
     #[test]
-    fn test_object_with_function_property() {
+    fn test_let_without_initializer() {
         let mut ctx = VM::new();
+
+        let result = ctx.evaluate_source("let x; x;").unwrap();
+        assert!(matches!(result, JSValue::Undefined));
+
+        let result = ctx.evaluate_source("let y; y = 5; y;").unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 5.0);
+
+        // the binding is local even when an outer one exists
         let result = ctx
             .evaluate_source(
                 r#"
-                let obj = { method: function(x) { return x * 2; } };
-                obj.method(5);
-            "#,
+            let z = 1;
+            let f = function () {
+                let z;
+                return typeof z;
+            };
+            [f(), z].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 10.0);
+        assert_eq!(result.try_as_string().unwrap(), "undefined,1");
     }
 
     #[test]
-    fn test_complex_nested_structure() {
+    fn test_block_let_shadows_parameter() {
         let mut ctx = VM::new();
-        ctx.evaluate_source("let obj = { arr: [1, 2, { inner: 42 }] };")
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let f = function (x) {
+                let seen = [];
+                if (true) {
+                    let x = "inner";
+                    seen.push(x);
+                };
+                seen.push(x);
+                x = x + 1;
+                seen.push(x);
+                return seen.join(",");
+            };
+            f(1);
+        "#,
+            )
             .unwrap();
-        ctx.evaluate_source("let arrVal = obj.arr;").unwrap();
-        ctx.evaluate_source("let innerObj = arrVal[2];").unwrap();
-        let result = ctx.evaluate_source("innerObj.inner;").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 42.0);
-    }
+        assert_eq!(result.try_as_string().unwrap(), "inner,1,2");
 
-    #[test]
-    fn test_function_with_block_and_return() {
-        let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let f = function(x) {
-                    {
-                        let y = x * 2;
-                        return y + 5;
-                    }
-                };
-                f(10);
-            "#,
+            let y = 1;
+            {
+                let y = 2;
+            };
+            y;
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 25.0);
-    }
+        assert_eq!(result.try_as_number().unwrap(), 1.0);
 
-    // Nested function tests with returns
-    #[test]
-    fn test_nested_function_simple_return() {
-        let mut ctx = VM::new();
+        // try/catch/finally blocks shadow like block statements do
         let result = ctx
             .evaluate_source(
                 r#"
-                let outer = function() {
-                    let inner = function() { return 42; };
-                    return inner();
+            let h = function (x) {
+                let seen = [];
+                try {
+                    let x = "try";
+                    seen.push(x);
+                    x();
+                } catch (e) {
+                    let x = "catch";
+                    seen.push(x);
+                } finally {
+                    let x = "finally";
+                    seen.push(x);
                 };
-                outer();
-            "#,
+                seen.push(x);
+                return seen.join(",");
+            };
+            h(1);
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 42.0);
+        assert_eq!(result.try_as_string().unwrap(), "try,catch,finally,1");
     }
 
     #[test]
-    fn test_nested_function_return_with_parameter() {
+    fn test_string_replace_all() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let outer = function(x) {
-                    let inner = function(y) { return x + y; };
-                    return inner(10);
-                };
-                outer(5);
-            "#,
+            [
+                "aaa".replaceAll("a", "b"),
+                "a.b.c".replaceAll(".", ""),
+                "abc".replaceAll("", "-"),
+                "aaaa".replaceAll("aa", "a"),
+                "1 + 1".replaceAll(1, 2),
+                "none".replaceAll("x", "y"),
+            ].join("|");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 15.0);
+        assert_eq!(
+            result.try_as_string().unwrap(),
+            "bbb|abc|-a-b-c-|aa|2 + 2|none"
+        );
     }
 
     #[test]
-    fn test_nested_function_return_function() {
-        let mut ctx = VM::new();
-        ctx.evaluate_source(
-            r#"
-            let makeAdder = function(x) {
-                let inner = function(y) {
-                    let sum = 5 + 3;
-                    return sum;
-                };
-                return inner;
-            };
+    fn test_range_test_helper() {
+        let mut ctx = VmBuilder::new().with_test_helpers().build();
+        let result = ctx
+            .evaluate_source(
+                r#"
+            [
+                __range(4).map((x) => x * 2).join(","),
+                __range(0).length,
+                __range(-3).length,
+                __range("2.7").join(","),
+            ].join("|");
         "#,
-        )
-        .unwrap();
-        ctx.evaluate_source("let add5 = makeAdder(5);").unwrap();
-        let result = ctx.evaluate_source("add5(3);").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 8.0);
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "0,2,4,6|0|0|0,1");
+
+        let err = ctx.evaluate_source("__range(4294967295);").unwrap_err();
+        assert_eq!(err.message(), "RangeError: Array is too large");
+
+        // not part of the default globals
+        let mut ctx = VM::new();
+        let result = ctx.evaluate_source("typeof __range;").unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "undefined");
     }
 
     #[test]
-    fn test_nested_function_multiple_levels() {
+    fn test_every_and_some_stop_early() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let level1 = function(a) {
-                    let level2 = function(b) {
-                        let level3 = function(c) {
-                            return a + b + c;
-                        };
-                        return level3(3);
-                    };
-                    return level2(2);
-                };
-                level1(1);
-            "#,
+            let calls = 0;
+            let identity = function (x) {
+                calls = calls + 1;
+                return x;
+            };
+            let every = [1, 0, 1].every(identity);
+            let everyCalls = calls;
+            calls = 0;
+            let some = [0, 1, 0].some(identity);
+            [every, everyCalls, some, calls].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 6.0);
+        assert_eq!(result.try_as_string().unwrap(), "false,2,true,2");
     }
 
     #[test]
-    fn test_nested_function_early_return() {
+    fn test_member_access_on_parenthesized_expression() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let outer = function(x) {
-                    let inner = function() { return x * 2; };
-                    return inner();
-                    return 999;
-                };
-                outer(7);
-            "#,
+            let a = { prop: "a", items: [10, 20] };
+            let b = { prop: "b" };
+            let pick = function () {
+                return a;
+            };
+            let cond = false;
+            [
+                (cond ? a : b).prop,
+                (a || b).prop,
+                (pick()).items[1],
+                (pick)().prop,
+                (a.items)[0],
+            ].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 14.0);
+        assert_eq!(result.try_as_string().unwrap(), "b,a,20,a,10");
     }
 
     #[test]
-    fn test_nested_function_with_computation() {
+    fn test_array_methods_on_non_array_this() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let outer = function(x) {
-                    let inner = function(y) { return y * 2; };
-                    return inner(x) + 10;
-                };
-                outer(5);
-            "#,
+            let o = { x: [].shift };
+            let shifted = o.x();
+            let like = { length: 2, 0: "a", 1: "b", join: [].join, push: [].push };
+            like.push("c");
+            let odd = { length: "many", map: [].map };
+            [typeof shifted, o.length, like.join("-"), odd.map(function (x) { return x; }).length].join(",");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 20.0);
+        assert_eq!(result.try_as_string().unwrap(), "undefined,0,a-b-c,0");
     }
 
     #[test]
-    fn test_nested_function_return_nested_call() {
+    fn test_huge_array_length_fails_cleanly() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let double = function(x) { return x * 2; };
-                let quadruple = function(x) {
-                    return double(double(x));
-                };
-                quadruple(3);
-            "#,
+            let arr = [1, 2];
+            arr.length = 4294967295;
+            arr[0] + arr.length;
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 12.0);
+        assert_eq!(result.try_as_number().unwrap(), 4294967296.0);
+
+        let err = ctx
+            .evaluate_source("arr.map(function (x) { return x; });")
+            .unwrap_err();
+        assert_eq!(err.message(), "RangeError: Array is too large");
+
+        let result = ctx
+            .evaluate_source("arr.length = 2; arr.join(\",\");")
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "1,2");
     }
 
     #[test]
-    fn test_nested_function_closure_with_return() {
+    fn test_array_constructor_with_huge_length() {
         let mut ctx = VM::new();
-        ctx.evaluate_source(
-            r#"
-            let outer = function(x) {
-                let inner = function() { return 50; };
-                return inner;
-            };
-        "#,
-        )
-        .unwrap();
-        ctx.evaluate_source("let fn = outer(5);").unwrap();
-        let result = ctx.evaluate_source("fn();").unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 50.0);
+        let result = ctx
+            .evaluate_source("let arr = Array(4294967295); arr.length;")
+            .unwrap();
+        assert_eq!(result.try_as_number().unwrap(), 4294967295.0);
+
+        let err = ctx.evaluate_source("arr.join(\",\");").unwrap_err();
+        assert_eq!(err.message(), "RangeError: Array is too large");
+
+        let err = ctx.evaluate_source("for (let x of arr) {};").unwrap_err();
+        assert_eq!(err.message(), "RangeError: Array is too large");
     }
 
     #[test]
-    fn test_nested_function_multiple_returns() {
+    fn test_to_primitive_reenters_same_object() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let outer = function(x) {
-                    let inner1 = function() { return x + 1; };
-                    let inner2 = function() { return x + 2; };
-                    return inner1() + inner2();
-                };
-                outer(10);
-            "#,
+            let m = {
+                valueOf: function () { return 42; },
+                toString: function () { return "m=" + (+this); },
+            };
+            let d = {
+                valueOf: function () { return String(this) + "!"; },
+                toString: function () { return "d"; },
+            };
+            let nested = [1];
+            for (let i = 0; i < 34; i = i + 1) {
+                nested = [nested];
+            };
+            [String(m), d + "", String(nested)].join("|");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 23.0);
+        assert_eq!(result.try_as_string().unwrap(), "m=42|d!|1");
     }
 
     #[test]
-    fn test_nested_function_return_with_block() {
+    fn test_builtin_properties_are_not_enumerable() {
         let mut ctx = VM::new();
         let result = ctx
             .evaluate_source(
                 r#"
-                let outer = function(x) {
-                    let inner = function(y) {
-                        let z = y + 5;
-                        return z * 2;
-                    };
-                    return inner(x);
-                };
-                outer(3);
-            "#,
+            let arr = [1, 2];
+            arr.length = 3;
+            Array.prototype.toString = Array.prototype.join;
+            [
+                Object.keys(arr).join(","),
+                Object.keys(Object.prototype).length,
+                Object.keys(Array.prototype).length,
+                Object.keys(Object).length,
+            ].join("|");
+        "#,
             )
             .unwrap();
-        assert_eq!(result.try_as_number().unwrap(), 16.0);
+        assert_eq!(result.try_as_string().unwrap(), "0,1|0|0|0");
     }
 
-    // Boolean tests
     #[test]
-    fn test_boolean_literal_true() {
+    fn test_object_spread_of_array() {
         let mut ctx = VM::new();
-        let result = ctx.evaluate_source("true;").unwrap();
-        assert_eq!(result.try_as_boolean().unwrap(), true);
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let copy = { ...[1, 2] };
+            [Object.keys(copy).join(","), copy[0], copy[1], typeof copy.length].join("|");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "0,1|1|2|undefined");
     }
 
     #[test]
-    fn test_boolean_literal_false() {
+    fn test_computed_keys_prefer_to_string() {
         let mut ctx = VM::new();
-        let result = ctx.evaluate_source("false;").unwrap();
-        assert_eq!(result.try_as_boolean().unwrap(), false);
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let key = {
+                valueOf: function () { return "fromValueOf"; },
+                toString: function () { return "fromToString"; },
+            };
+            let obj = { [key]: 1 };
+            [Object.keys(obj).join(","), obj[key], obj.fromToString].join("|");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "fromToString|1|1");
     }
 
     #[test]
-    fn test_boolean_constructor_with_truthy_values() {
+    fn test_assignment_and_define_property_keys_prefer_to_string() {
         let mut ctx = VM::new();
-        let result = ctx.evaluate_source("Boolean(1);").unwrap();
-        assert_eq!(result.try_as_boolean().unwrap(), true);
-
-        let result = ctx.evaluate_source("Boolean('hello');").unwrap();
-        assert_eq!(result.try_as_boolean().unwrap(), true);
-
-        let result = ctx.evaluate_source("Boolean({});").unwrap();
-        assert_eq!(result.try_as_boolean().unwrap(), true);
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let key = {
+                valueOf: function () { return "fromValueOf"; },
+                toString: function () { return "fromToString"; },
+            };
+            let obj = {};
+            obj[key] = 1;
+            obj[key] = obj[key] + 1;
+            obj[key] ??= 10;
+            Object.defineProperty(obj, key, { value: obj[key] + 1, enumerable: true });
+            [Object.keys(obj).join(","), obj.fromToString].join("|");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "fromToString|3");
     }
 
     #[test]
-    fn test_boolean_constructor_with_falsy_values() {
+    fn test_spread_and_for_of_over_iterators() {
         let mut ctx = VM::new();
-        let result = ctx.evaluate_source("Boolean(0);").unwrap();
-        assert_eq!(result.try_as_boolean().unwrap(), false);
-
-        let result = ctx.evaluate_source("Boolean('');").unwrap();
-        assert_eq!(result.try_as_boolean().unwrap(), false);
-    }
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let pairs = [...["a", "b"].entries()].map((entry) => entry.join(":"));
+            let keys = [];
+            for (let key of [5, 6, 7].keys()) {
+                keys.push(key);
+            };
+            let count = 0;
+            let countdown = {
+                next: function () {
+                    count = count + 1;
+                    return { value: count, done: count > 3 };
+                },
+            };
+            [pairs.join(","), keys.join(","), [...countdown].join(",")].join("|");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "0:a,1:b|0,1,2|1,2,3");
 
-    #[test]
-    fn test_boolean_constructor_with_undefined() {
-        let mut ctx = VM::new();
-        let result = ctx.evaluate_source("Boolean();").unwrap();
-        // Boolean() without arguments should return false, matching JavaScript behavior
-        assert_eq!(result.try_as_boolean().unwrap(), false);
+        let err = ctx
+            .evaluate_source("[...{ next: function () { return 1; } }];")
+            .unwrap_err();
+        assert_eq!(err.message(), "TypeError: Iterator result is not an object");
     }
 
     #[test]
-    fn test_boolean_in_variable() {
+    fn test_iterator_result_properties_are_enumerable() {
         let mut ctx = VM::new();
-        let result = ctx.evaluate_source("let x = true; x;").unwrap();
-        assert_eq!(result.try_as_boolean().unwrap(), true);
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let step = [1].values().next();
+            let copy = { ...step };
+            [Object.keys(step).join(","), copy.value, copy.done].join("|");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(result.try_as_string().unwrap(), "done,value|1|false");
     }
 
     #[test]
-    fn test_boolean_constructor_with_number() {
+    fn test_optional_chain_short_circuits_rest_of_chain() {
         let mut ctx = VM::new();
-        let result = ctx.evaluate_source("Boolean(42);").unwrap();
-        assert_eq!(result.try_as_boolean().unwrap(), true);
+        let result = ctx
+            .evaluate_source(
+                r#"
+            let a;
+            let o = { inner: { list: [7] } };
+            [
+                typeof a?.b.c,
+                typeof o.x?.y.z,
+                typeof a?.b(),
+                typeof o.x?.["y"].z,
+                o?.["inner"].list[0],
+                typeof a?.[missing()],
+            ].join(",");
+        "#,
+            )
+            .unwrap();
+        assert_eq!(
+            result.try_as_string().unwrap(),
+            "undefined,undefined,undefined,undefined,7,undefined"
+        );
 
-        let result = ctx.evaluate_source("Boolean(-1);").unwrap();
-        assert_eq!(result.try_as_boolean().unwrap(), true);
+        let err = ctx.evaluate_source("o.x.y?.z;").unwrap_err();
+        assert!(
+            err.message()
+                .starts_with("Tried to access property of non-object")
+        );
     }
 }